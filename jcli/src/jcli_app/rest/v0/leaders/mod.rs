@@ -26,6 +26,10 @@ pub enum Leaders {
         /// If not provided, YAML will be read from stdin.
         #[structopt(short, long)]
         file: Option<PathBuf>,
+        /// admin token of the node, the `admin_token` of its REST
+        /// configuration
+        #[structopt(long)]
+        admin_token: Option<String>,
     },
     /// Delete leader
     Delete {
@@ -35,6 +39,10 @@ pub enum Leaders {
         debug: DebugFlag,
         /// ID of deleted leader
         id: u32,
+        /// admin token of the node, the `admin_token` of its REST
+        /// configuration
+        #[structopt(long)]
+        admin_token: Option<String>,
     },
 
     /// Leadership log operations
@@ -63,8 +71,18 @@ impl Leaders {
                 debug,
                 output_format,
             } => get(addr, debug, output_format),
-            Leaders::Post { addr, debug, file } => post(addr, debug, file),
-            Leaders::Delete { id, addr, debug } => delete(addr, debug, id),
+            Leaders::Post {
+                addr,
+                debug,
+                file,
+                admin_token,
+            } => post(addr, debug, file, admin_token),
+            Leaders::Delete {
+                id,
+                addr,
+                debug,
+                admin_token,
+            } => delete(addr, debug, id, admin_token),
             Leaders::Logs(GetLogs::Get {
                 addr,
                 debug,
@@ -85,9 +103,14 @@ fn get(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<
     Ok(())
 }
 
-fn post(addr: HostAddr, debug: DebugFlag, file: Option<PathBuf>) -> Result<(), Error> {
+fn post(
+    addr: HostAddr,
+    debug: DebugFlag,
+    file: Option<PathBuf>,
+    admin_token: Option<String>,
+) -> Result<(), Error> {
     let url = addr.with_segments(&["v0", "leaders"])?.into_url();
-    let builder = reqwest::Client::new().post(url);
+    let builder = with_admin_token(reqwest::Client::new().post(url), admin_token);
     let input: serde_json::Value = io::read_yaml(&file)?;
     let response = RestApiSender::new(builder, &debug)
         .with_json_body(&input)?
@@ -97,17 +120,35 @@ fn post(addr: HostAddr, debug: DebugFlag, file: Option<PathBuf>) -> Result<(), E
     Ok(())
 }
 
-fn delete(addr: HostAddr, debug: DebugFlag, id: u32) -> Result<(), Error> {
+fn delete(
+    addr: HostAddr,
+    debug: DebugFlag,
+    id: u32,
+    admin_token: Option<String>,
+) -> Result<(), Error> {
     let url = addr
         .with_segments(&["v0", "leaders", &id.to_string()])?
         .into_url();
-    let builder = reqwest::Client::new().delete(url);
+    let builder = with_admin_token(reqwest::Client::new().delete(url), admin_token);
     let response = RestApiSender::new(builder, &debug).send()?;
     response.ok_response()?;
     println!("Success");
     Ok(())
 }
 
+fn with_admin_token(
+    builder: reqwest::RequestBuilder,
+    admin_token: Option<String>,
+) -> reqwest::RequestBuilder {
+    match admin_token {
+        None => builder,
+        Some(admin_token) => builder.header(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {}", admin_token),
+        ),
+    }
+}
+
 fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<(), Error> {
     let url = addr.with_segments(&["v0", "leaders", "logs"])?.into_url();
     let builder = reqwest::Client::new().get(url);
@@ -443,7 +443,7 @@ pub fn assert_transaction_log_shows_rejected(
                 jormungandr.logger.get_log_content()
             );
             match x.status() {
-                FragmentStatus::Rejected { reason } => assert!(reason.contains(&expected_msg)),
+                FragmentStatus::Rejected { reason, .. } => assert!(reason.contains(&expected_msg)),
                 _ => panic!("Non expected state for for rejected log"),
             }
         }
@@ -27,6 +27,10 @@ pub fn test_log_ttl() {
             log_max_entries: 100_000usize.into(),
             log_ttl: Duration::from_secs(log_ttl_timeout).into(),
             garbage_collection_interval: Duration::from_secs(garbage_collection_interval).into(),
+            selection_strategy: Default::default(),
+            persistent: false,
+            replace_by_fee: false,
+            min_fee_bump: Default::default(),
         })
         .build();
 
@@ -64,6 +68,10 @@ pub fn garbage_collection_interval() {
             log_max_entries: 100_000usize.into(),
             log_ttl: Duration::from_secs(log_ttl_timeout).into(),
             garbage_collection_interval: Duration::from_secs(garbage_collection_interval).into(),
+            selection_strategy: Default::default(),
+            persistent: false,
+            replace_by_fee: false,
+            min_fee_bump: Default::default(),
         })
         .build();
 
@@ -28,6 +28,10 @@ pub fn test_blocks_are_being_created_for_48_hours() {
                 log_max_entries: 1_000_000usize.into(),
                 log_ttl: duration_48_hours.clone().into(),
                 garbage_collection_interval: duration_48_hours.clone().into(),
+                selection_strategy: Default::default(),
+                persistent: false,
+                replace_by_fee: false,
+                min_fee_bump: Default::default(),
             }),
     )
     .unwrap();
@@ -7,6 +7,19 @@ pub struct PoolMaxEntries(usize);
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct LogMaxEntries(usize);
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct MinFeeBump(u64);
+
+/// order in which the fragments of the mempool are selected for a block
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentSelectionStrategy {
+    /// the fragments received first are selected first
+    OldestFirst,
+    /// the fragments paying the highest fee per byte are selected first
+    HighestFeeRate,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Mempool {
@@ -24,6 +37,20 @@ pub struct Mempool {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check of the mempool and the log cache.
     pub garbage_collection_interval: Duration,
+    /// order in which the fragments are selected for a block
+    #[serde(default)]
+    pub selection_strategy: FragmentSelectionStrategy,
+    /// keep a journal of the pending fragments in the storage directory,
+    /// so they are restored on restart
+    #[serde(default)]
+    pub persistent: bool,
+    /// allow a pending fragment to be replaced by a fragment spending the
+    /// same inputs with a higher fee
+    #[serde(default)]
+    pub replace_by_fee: bool,
+    /// minimum increase of the fee for a fragment to replace pending ones
+    #[serde(default)]
+    pub min_fee_bump: MinFeeBump,
 }
 
 impl Default for PoolMaxEntries {
@@ -38,6 +65,18 @@ impl Default for LogMaxEntries {
     }
 }
 
+impl Default for MinFeeBump {
+    fn default() -> Self {
+        MinFeeBump(1)
+    }
+}
+
+impl Default for FragmentSelectionStrategy {
+    fn default() -> Self {
+        FragmentSelectionStrategy::OldestFirst
+    }
+}
+
 impl Default for Mempool {
     fn default() -> Self {
         Mempool {
@@ -46,6 +85,10 @@ impl Default for Mempool {
             log_max_entries: LogMaxEntries::default(),
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            selection_strategy: FragmentSelectionStrategy::default(),
+            persistent: false,
+            replace_by_fee: false,
+            min_fee_bump: MinFeeBump::default(),
         }
     }
 }
@@ -73,3 +116,15 @@ impl From<LogMaxEntries> for usize {
         s.0
     }
 }
+
+impl From<u64> for MinFeeBump {
+    fn from(s: u64) -> Self {
+        MinFeeBump(s)
+    }
+}
+
+impl From<MinFeeBump> for u64 {
+    fn from(s: MinFeeBump) -> Self {
+        s.0
+    }
+}
@@ -18,15 +18,41 @@ pub enum FragmentOrigin {
     Rest,
 }
 
+/// why a fragment has been rejected
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FragmentRejectionCause {
+    /// a witness of the fragment does not match its input
+    InvalidSignature,
+    /// the inputs of the fragment do not balance its outputs and fees
+    NotBalanced,
+    /// the fragment stayed in the pool longer than its time to live
+    Expired,
+    /// the pool had no room left for the fragment
+    PoolFull,
+    /// the fragment spends the inputs of pending fragments without paying
+    /// enough more than them to replace them
+    InsufficientFeeBump,
+    /// the fragment cannot be applied to the ledger, the reason of the
+    /// rejection is the error of the ledger
+    Ledger,
+}
+
 /// status of the fragment within the blockchain or the pool
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum FragmentStatus {
     /// the fragment is yet to be processed
     Pending,
     /// the fragment has been rejected and won't be added in a block
-    Rejected { reason: String },
+    Rejected {
+        reason: String,
+        #[serde(default)]
+        cause: FragmentRejectionCause,
+    },
     /// The fragment has been added in a block
     InABlock { date: BlockDate, block: Hash },
+    /// the fragment has been replaced in the pool by a fragment spending
+    /// the same inputs with a higher fee
+    Replaced { by: Hash },
 }
 
 /// the log associated to a given fragment
@@ -39,6 +65,12 @@ pub struct FragmentLog {
     status: FragmentStatus,
 }
 
+impl Default for FragmentRejectionCause {
+    fn default() -> Self {
+        FragmentRejectionCause::Ledger
+    }
+}
+
 impl FragmentStatus {
     #[inline]
     pub fn is_pending(&self) -> bool {
@@ -62,6 +94,15 @@ impl FragmentStatus {
             false
         }
     }
+
+    #[inline]
+    pub fn is_replaced(&self) -> bool {
+        if let FragmentStatus::Replaced { .. } = &self {
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl FragmentLog {
@@ -92,6 +133,11 @@ impl FragmentLog {
         self.status().is_in_a_block()
     }
 
+    #[inline]
+    pub fn is_replaced(&self) -> bool {
+        self.status().is_replaced()
+    }
+
     /// set the new status
     #[inline]
     pub fn modify(&mut self, new_status: FragmentStatus) {
@@ -11,12 +11,15 @@ pub struct EnclaveLeaderId(u32);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LeadershipLogId(EnclaveLeaderId, BlockDate);
 
-/// the status of a leadership log
+/// the status of a leadership log. `DryRun` is the status of the events
+/// whose block was signed but not submitted, the node running in dry run.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum LeadershipLogStatus {
     Pending,
     Rejected { reason: String },
     Block { block: Hash, chain_length: u32 },
+    Missed,
+    DryRun { block: Hash, chain_length: u32 },
 }
 
 /// provides information regarding events in the leadership schedule
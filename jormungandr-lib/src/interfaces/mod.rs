@@ -31,7 +31,7 @@ pub use self::certificate::{
     SignedCertificate, CERTIFICATE_HRP, SIGNED_CERTIFICATE_HRP,
 };
 pub use self::config::*;
-pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentStatus};
+pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentRejectionCause, FragmentStatus};
 pub use self::leadership_log::{
     EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus,
 };
@@ -41,7 +41,7 @@ pub use self::ratio::{ParseRatioError, Ratio};
 pub use self::reward_parameters::RewardParams;
 pub use self::settings::{ParametersDef, RatioDef, SettingsDto, TaxTypeDef, TaxTypeSerde};
 pub use self::stake_pool_stats::{Rewards, StakePoolStats};
-pub use self::stats::{NodeState, Stats};
+pub use self::stats::{LatencyHistogram, NodeState, Stats};
 pub use self::tax_type::TaxType;
 pub use self::transaction_input::{TransactionInput, TransactionInputType};
 pub use self::transaction_output::TransactionOutput;
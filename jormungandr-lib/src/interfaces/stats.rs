@@ -12,6 +12,7 @@ pub struct Stats {
     pub peer_unreachable_cnt: Option<u32>,
     pub tx_recv_cnt: Option<u32>,
     pub block_recv_cnt: Option<u32>,
+    pub slots_missed_cnt: Option<u64>,
     pub uptime: Option<u32>,
     pub state: NodeState,
     pub last_block_hash: Option<String>,
@@ -23,6 +24,26 @@ pub struct Stats {
     pub last_block_sum: Option<u32>,
     pub last_block_fees: Option<u32>,
     pub last_block_content_size: Option<u32>,
+    pub storage_cache_hits: Option<u64>,
+    pub storage_cache_misses: Option<u64>,
+    pub storage_block_cnt: Option<u64>,
+    pub storage_bytes_on_disk: Option<u64>,
+    pub storage_read_queue_depth: Option<u64>,
+    pub storage_get_latency: Option<LatencyHistogram>,
+    pub storage_put_latency: Option<LatencyHistogram>,
+    pub storage_stream_block_latency: Option<LatencyHistogram>,
+}
+
+/// number of operations per latency bucket
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct LatencyHistogram {
+    /// upper bounds of the buckets in microseconds, the last bucket has no
+    /// upper bound
+    pub bounds_us: Vec<u64>,
+    pub counts: Vec<u64>,
+    /// total duration of the operations in microseconds
+    pub sum_us: u64,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
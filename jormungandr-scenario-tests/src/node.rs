@@ -305,7 +305,7 @@ impl NodeController {
                         self.progress_bar
                             .log_info(format!("Fragment '{}' is still pending", check.fragment_id));
                     }
-                    Rejected { reason } => {
+                    Rejected { reason, .. } => {
                         self.progress_bar.log_info(format!(
                             "Fragment '{}' rejected: {}",
                             check.fragment_id, reason
@@ -319,6 +319,13 @@ impl NodeController {
                         ));
                         return Ok(status);
                     }
+                    Replaced { by } => {
+                        self.progress_bar.log_info(format!(
+                            "Fragment '{}' replaced by: {}",
+                            check.fragment_id, by
+                        ));
+                        return Ok(status);
+                    }
                 }
             } else {
                 bail!(ErrorKind::FragmentNoInMemPoolLogs(
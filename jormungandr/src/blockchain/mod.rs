@@ -34,6 +34,10 @@ pub use self::{
     multiverse::Multiverse,
     process::{process_new_ref, Process},
     reference::Ref,
-    storage::Storage,
+    storage::{
+        is_fragment_index_disabled, BlockCursor, BlockStream, BloomConfig, CacheConfig,
+        CancellationToken, ClosestAncestor, OrphanConfig, PutOutcome, Recovery, RetryPolicy,
+        SendConfig, Storage, StorageUsage, VerifyReport, STORAGE_VERSION,
+    },
     tip::Tip,
 };
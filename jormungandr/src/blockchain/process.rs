@@ -687,7 +687,7 @@ async fn process_network_block(
     blockchain: &Blockchain,
     block: Block,
     tx_msg_box: &mut MessageBox<TransactionMsg>,
-    explorer_msg_box: Option<&mut MessageBox<ExplorerMsg>>,
+    mut explorer_msg_box: Option<&mut MessageBox<ExplorerMsg>>,
     get_next_block_scheduler: &mut GetNextBlockScheduler,
     logger: &Logger,
 ) -> Result<Option<Arc<Ref>>, chain::Error> {
@@ -718,6 +718,9 @@ async fn process_network_block(
                 "parent" => %parent_hash,
                 "date" => %header.block_date(),
             );
+            // applied without being downloaded again once the parent is
+            // stored, if the storage keeps the orphan blocks
+            blockchain.storage().add_orphan(block);
             Err(ErrorKind::MissingParentBlock(parent_hash).into())
         }
         PreCheckedHeader::HeaderWithCache { parent_ref, .. } => {
@@ -726,13 +729,83 @@ async fn process_network_block(
                 parent_ref,
                 block,
                 tx_msg_box,
-                explorer_msg_box,
+                explorer_msg_box.as_deref_mut(),
                 logger,
             )
-            .await;
-            r
+            .await?;
+            match r {
+                Some(block_ref) => {
+                    let descendant = apply_orphans(
+                        blockchain,
+                        Arc::clone(&block_ref),
+                        tx_msg_box,
+                        explorer_msg_box,
+                        logger,
+                    )
+                    .await;
+                    Ok(Some(descendant.unwrap_or(block_ref)))
+                }
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// apply the descendants of `parent_ref` received before it and kept in
+/// the orphan pool of the storage. Returns the longest chain applied, if
+/// any. An orphan that cannot be applied is dropped with its descendants.
+async fn apply_orphans(
+    blockchain: &Blockchain,
+    parent_ref: Arc<Ref>,
+    tx_msg_box: &mut MessageBox<TransactionMsg>,
+    mut explorer_msg_box: Option<&mut MessageBox<ExplorerMsg>>,
+    logger: &Logger,
+) -> Option<Arc<Ref>> {
+    let mut longest: Option<Arc<Ref>> = None;
+    // every orphan comes after its parent
+    for block in blockchain.storage().resolve_orphans(parent_ref.hash()) {
+        let block_hash = block.id();
+        let parent_ref = match blockchain.get_ref(block.parent_id()).compat().await {
+            Ok(Some(parent_ref)) => parent_ref,
+            // the parent is an orphan that could not be applied
+            Ok(None) => continue,
+            Err(e) => {
+                info!(
+                    logger,
+                    "cannot get the parent of an orphan block";
+                    "hash" => %block_hash,
+                    "reason" => ?e,
+                );
+                continue;
+            }
+        };
+        let res = check_and_apply_block(
+            blockchain,
+            parent_ref,
+            block,
+            tx_msg_box,
+            explorer_msg_box.as_deref_mut(),
+            logger,
+        )
+        .await;
+        match res {
+            Ok(Some(block_ref)) => {
+                if longest.as_ref().map_or(true, |longest| {
+                    longest.chain_length() < block_ref.chain_length()
+                }) {
+                    longest = Some(block_ref);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => info!(
+                logger,
+                "validation of an orphan block failed";
+                "hash" => %block_hash,
+                "reason" => ?e,
+            ),
         }
     }
+    longest
 }
 
 async fn check_and_apply_block(
@@ -0,0 +1,220 @@
+//! flat file format used to export a branch of the blockchain
+//!
+//! The file starts with the `MAGIC` bytes followed by the format `VERSION`
+//! (big endian `u32`). Then every block of the branch is serialized, prefixed
+//! by its length (big endian `u32`), from the ancestor to the descendant.
+//!
+//! An archive is a backup in which the blocks are followed by an empty
+//! block (a length of 0) and the `Checksum` of the blocks.
+
+use crate::blockcfg::Block;
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_crypto::Blake2b256;
+use futures03::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::io;
+use thiserror::Error;
+
+use super::StorageError;
+
+pub const MAGIC: &[u8; 8] = b"JORMBRCH";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("I/O error while reading or writing the backup")]
+    Io(#[from] io::Error),
+    #[error("the block is too large to be written in the backup")]
+    BlockTooLarge,
+    #[error("not a backup file, the magic bytes do not match")]
+    InvalidMagic,
+    #[error("unsupported backup format version {0}, expected {}", VERSION)]
+    UnsupportedVersion(u32),
+    #[error("the backup is truncated")]
+    Truncated,
+    #[error("cannot decode a block from the backup")]
+    Decode(#[source] io::Error),
+    #[error("the archive does not start with the expected block0")]
+    UnexpectedBlock0,
+    #[error("the archive is empty")]
+    Empty,
+    #[error("the checksum of the archive does not match its blocks")]
+    ChecksumMismatch,
+}
+
+/// checksum of the blocks of an archive: the hash of the list of the
+/// hashes of the serialized blocks
+#[derive(Default)]
+pub struct Checksum {
+    hashes: Vec<u8>,
+}
+
+impl Checksum {
+    fn update(&mut self, bytes: &[u8]) {
+        self.hashes
+            .extend_from_slice(Blake2b256::new(bytes).as_ref());
+    }
+
+    pub fn finalize(&self) -> Blake2b256 {
+        Blake2b256::new(&self.hashes)
+    }
+}
+
+fn truncated(error: io::Error) -> BackupError {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => BackupError::Truncated,
+        _ => BackupError::Io(error),
+    }
+}
+
+impl From<BackupError> for StorageError {
+    fn from(error: BackupError) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+pub async fn write_header<W>(writer: &mut W) -> Result<(), BackupError>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(MAGIC).await?;
+    writer.write_all(&VERSION.to_be_bytes()).await?;
+    Ok(())
+}
+
+pub async fn write_block<W>(writer: &mut W, block: &Block) -> Result<(), BackupError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let bytes = block.serialize_as_vec()?;
+    write_bytes(writer, &bytes).await
+}
+
+pub async fn write_archive_block<W>(
+    writer: &mut W,
+    block: &Block,
+    checksum: &mut Checksum,
+) -> Result<(), BackupError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let bytes = block.serialize_as_vec()?;
+    checksum.update(&bytes);
+    write_bytes(writer, &bytes).await
+}
+
+/// end the blocks of the archive with its checksum
+pub async fn write_checksum<W>(writer: &mut W, checksum: &Checksum) -> Result<(), BackupError>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(&0u32.to_be_bytes()).await?;
+    writer.write_all(checksum.finalize().as_ref()).await?;
+    Ok(())
+}
+
+async fn write_bytes<W>(writer: &mut W, bytes: &[u8]) -> Result<(), BackupError>
+where
+    W: AsyncWrite + Unpin,
+{
+    if bytes.len() > std::u32::MAX as usize {
+        return Err(BackupError::BlockTooLarge);
+    }
+    writer
+        .write_all(&(bytes.len() as u32).to_be_bytes())
+        .await?;
+    writer.write_all(bytes).await?;
+    Ok(())
+}
+
+pub async fn read_header<R>(reader: &mut R) -> Result<(), BackupError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).await.map_err(truncated)?;
+    if &magic != MAGIC {
+        return Err(BackupError::InvalidMagic);
+    }
+
+    let mut version = [0; 4];
+    reader.read_exact(&mut version).await.map_err(truncated)?;
+    let version = u32::from_be_bytes(version);
+    if version != VERSION {
+        return Err(BackupError::UnsupportedVersion(version));
+    }
+
+    Ok(())
+}
+
+/// read the next block of the backup, returns `None` if the end of
+/// the backup has been reached.
+pub async fn read_block<R>(reader: &mut R) -> Result<Option<Block>, BackupError>
+where
+    R: AsyncRead + Unpin,
+{
+    match read_bytes(reader).await? {
+        Some(bytes) => decode(&bytes).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// read the next block of the archive, returns `None` once the blocks
+/// are over, the checksum of the archive follows
+pub async fn read_archive_block<R>(
+    reader: &mut R,
+    checksum: &mut Checksum,
+) -> Result<Option<Block>, BackupError>
+where
+    R: AsyncRead + Unpin,
+{
+    match read_bytes(reader).await? {
+        Some(bytes) => {
+            checksum.update(&bytes);
+            decode(&bytes).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// read the checksum ending the archive and compare it to the `checksum`
+/// of the blocks read
+pub async fn check_checksum<R>(reader: &mut R, checksum: &Checksum) -> Result<(), BackupError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut expected = [0; 32];
+    reader.read_exact(&mut expected).await.map_err(truncated)?;
+    if checksum.finalize().as_ref() != &expected[..] {
+        return Err(BackupError::ChecksumMismatch);
+    }
+    Ok(())
+}
+
+/// the bytes of the next block, `None` at the end of the backup or at
+/// the empty block ending the blocks of an archive
+async fn read_bytes<R>(reader: &mut R) -> Result<Option<Vec<u8>>, BackupError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len = [0; 4];
+    let read = reader.read(&mut len).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    reader
+        .read_exact(&mut len[read..])
+        .await
+        .map_err(truncated)?;
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).await.map_err(truncated)?;
+    Ok(Some(bytes))
+}
+
+fn decode(bytes: &[u8]) -> Result<Block, BackupError> {
+    Block::deserialize(bytes).map_err(BackupError::Decode)
+}
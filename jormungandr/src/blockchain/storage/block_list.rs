@@ -0,0 +1,207 @@
+//! list of the stored blocks
+//!
+//! The backend cannot list the stored blocks, so the hash of every block
+//! is appended to a file next to the storage file before the block is
+//! written. The record is removed again if the write fails, and a record
+//! left by a crash during the write is removed when the list is opened:
+//! the list never misses a stored block. A storage without file (in
+//! memory) keeps its list in memory.
+//!
+//! A storage holding blocks but no list file was created before its blocks
+//! were listed, its list is incomplete and is not kept.
+
+use super::{NodeStorageConnection, StorageError};
+use crate::{blockcfg::HeaderHash, blockchain::MAIN_BRANCH_TAG};
+use std::{
+    fs,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const RECORD_SIZE: u64 = 32;
+
+#[derive(Debug, Error)]
+#[error("the storage was created before its blocks were listed")]
+pub struct BlockListIncomplete;
+
+impl From<BlockListIncomplete> for StorageError {
+    fn from(error: BlockListIncomplete) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+pub struct BlockList {
+    path: Option<PathBuf>,
+    read_only: bool,
+    // `None` until the list has been opened
+    state: Option<State>,
+}
+
+enum State {
+    Incomplete,
+    Memory(Vec<HeaderHash>),
+    File(fs::File),
+}
+
+impl BlockList {
+    /// the list of the blocks of the storage persisted in `storage_path`,
+    /// or of a storage in memory if `None`
+    pub fn new(storage_path: Option<&Path>, read_only: bool) -> Self {
+        let path = storage_path.map(|storage_path| {
+            let mut path = storage_path.as_os_str().to_owned();
+            path.push(".blocks");
+            path.into()
+        });
+        BlockList {
+            path,
+            read_only,
+            state: None,
+        }
+    }
+
+    fn open(&mut self, connection: &mut NodeStorageConnection) -> Result<&mut State, StorageError> {
+        if self.state.is_none() {
+            let state = match self.path.as_ref() {
+                None => State::Memory(Vec::new()),
+                Some(path) => open_file(connection, path, self.read_only)?,
+            };
+            self.state = Some(state);
+        }
+        Ok(self.state.as_mut().unwrap())
+    }
+
+    /// list the block about to be written, returns whether it was listed:
+    /// an incomplete list is not kept
+    pub fn push(
+        &mut self,
+        connection: &mut NodeStorageConnection,
+        header_hash: &HeaderHash,
+    ) -> Result<bool, StorageError> {
+        match self.open(connection)? {
+            State::Incomplete => Ok(false),
+            State::Memory(hashes) => {
+                hashes.push(*header_hash);
+                Ok(true)
+            }
+            State::File(file) => {
+                file.write_all(header_hash.as_ref())
+                    .map_err(backend_error)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// remove the block listed last, to call when its write failed
+    pub fn pop(&mut self) -> Result<(), StorageError> {
+        match self.state.as_mut() {
+            None | Some(State::Incomplete) => Ok(()),
+            Some(State::Memory(hashes)) => {
+                hashes.pop();
+                Ok(())
+            }
+            Some(State::File(file)) => truncate_last(file).map_err(backend_error),
+        }
+    }
+
+    /// number of stored blocks, `None` if the list is incomplete
+    pub fn len(
+        &mut self,
+        connection: &mut NodeStorageConnection,
+    ) -> Result<Option<u64>, StorageError> {
+        match self.open(connection)? {
+            State::Incomplete => Ok(None),
+            State::Memory(hashes) => Ok(Some(hashes.len() as u64)),
+            State::File(file) => {
+                let len = file.metadata().map_err(backend_error)?.len();
+                Ok(Some(len / RECORD_SIZE))
+            }
+        }
+    }
+
+    /// call `f` with every stored block, fails with `BlockListIncomplete`
+    /// if the list is incomplete
+    pub fn for_each<F>(
+        &mut self,
+        connection: &mut NodeStorageConnection,
+        mut f: F,
+    ) -> Result<(), StorageError>
+    where
+        F: FnMut(&HeaderHash),
+    {
+        let path = self.path.clone();
+        match self.open(connection)? {
+            State::Incomplete => Err(BlockListIncomplete.into()),
+            State::Memory(hashes) => {
+                hashes.iter().for_each(f);
+                Ok(())
+            }
+            State::File(file) => {
+                let count = file.metadata().map_err(backend_error)?.len() / RECORD_SIZE;
+                let file = fs::File::open(path.unwrap()).map_err(backend_error)?;
+                let mut reader = BufReader::new(file);
+                for _ in 0..count {
+                    let mut header_hash = [0; RECORD_SIZE as usize];
+                    reader.read_exact(&mut header_hash).map_err(backend_error)?;
+                    f(&HeaderHash::from_bytes(header_hash));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// open the list file, without the record truncated by a crash if any and
+/// without the record of a block whose write did not complete
+fn open_file(
+    connection: &mut NodeStorageConnection,
+    path: &Path,
+    read_only: bool,
+) -> Result<State, StorageError> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true).append(!read_only);
+    let mut file = match options.open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            // a storage without main branch holds no block yet
+            if read_only || connection.get_tag(MAIN_BRANCH_TAG)?.is_some() {
+                return Ok(State::Incomplete);
+            }
+            let file = options.create(true).open(path).map_err(backend_error)?;
+            return Ok(State::File(file));
+        }
+        Err(e) => return Err(backend_error(e)),
+    };
+    if read_only {
+        return Ok(State::File(file));
+    }
+
+    let len = file.metadata().map_err(backend_error)?.len();
+    file.set_len(len - len % RECORD_SIZE)
+        .map_err(backend_error)?;
+    if len >= RECORD_SIZE {
+        // the blocks are written one at a time, only the last one may have
+        // been interrupted
+        let mut header_hash = [0; RECORD_SIZE as usize];
+        file.seek(SeekFrom::End(-(RECORD_SIZE as i64)))
+            .and_then(|_| file.read_exact(&mut header_hash))
+            .map_err(backend_error)?;
+        let written = match connection.block_exists(&HeaderHash::from_bytes(header_hash)) {
+            Err(StorageError::BlockNotFound) => false,
+            res => res?,
+        };
+        if !written {
+            truncate_last(&mut file).map_err(backend_error)?;
+        }
+    }
+    Ok(State::File(file))
+}
+
+fn truncate_last(file: &mut fs::File) -> io::Result<()> {
+    let len = file.metadata()?.len();
+    file.set_len(len.saturating_sub(RECORD_SIZE))
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
@@ -0,0 +1,73 @@
+//! bloom filter of the block hashes present in the storage
+//!
+//! The filter never answers "no" for a block that was inserted, it may
+//! answer "yes" for a block that was not.
+
+use crate::blockcfg::HeaderHash;
+use std::convert::TryInto;
+
+/// sizing of the bloom filter, see `Storage::with_bloom_filter`
+#[derive(Debug, Clone, Copy)]
+pub struct BloomConfig {
+    /// number of blocks the filter is sized for, the rate of false
+    /// positives grows once more blocks are stored
+    pub capacity: usize,
+    /// rate of the blocks not stored the filter cannot rule out, in
+    /// `]0, 1[`
+    pub false_positive_rate: f64,
+}
+
+impl BloomConfig {
+    pub fn is_valid(&self) -> bool {
+        self.capacity > 0 && self.false_positive_rate > 0.0 && self.false_positive_rate < 1.0
+    }
+}
+
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    pub fn new(config: BloomConfig) -> Self {
+        assert!(config.is_valid());
+        let BloomConfig {
+            capacity,
+            false_positive_rate,
+        } = config;
+
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+        let num_bits = (num_bits as u64).max(64);
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round();
+        let num_hashes = (num_hashes as u32).max(1);
+
+        BloomFilter {
+            bits: vec![0; ((num_bits + 63) / 64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    pub fn insert(&mut self, header_hash: &HeaderHash) {
+        for index in self.indexes(header_hash) {
+            self.bits[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    pub fn contains(&self, header_hash: &HeaderHash) -> bool {
+        self.indexes(header_hash)
+            .all(|index| self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+
+    // the header hash is already uniformly distributed, the indexes are
+    // derived from it with double hashing instead of rehashing it
+    fn indexes(&self, header_hash: &HeaderHash) -> impl Iterator<Item = u64> {
+        let bytes = header_hash.as_ref();
+        let h1 = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        let num_bits = self.num_bits;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
@@ -0,0 +1,185 @@
+//! read-through cache of the blocks of the storage
+//!
+//! The blocks are immutable, a cached block never becomes invalid. The
+//! cache only needs to be purged of the blocks removed from the storage.
+
+use crate::blockcfg::HeaderHash;
+use linked_hash_map::LinkedHashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// maximum number of blocks in the cache, the least recently used
+    /// blocks are evicted first
+    pub capacity: usize,
+    /// maximum total size of the blocks in the cache, in bytes
+    pub max_bytes: Option<usize>,
+    /// evict the blocks inserted longer ago than `ttl`, this is only a
+    /// mean to reduce the memory used by the cache
+    pub ttl: Option<Duration>,
+}
+
+/// counters of the cache since the start of the node
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CacheMetrics {
+    pub insertions: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// total size of the blocks currently in the cache, in bytes
+    pub bytes: usize,
+}
+
+impl CacheConfig {
+    /// a cache only bounded by the total size of its blocks
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
+        CacheConfig {
+            capacity: std::usize::MAX,
+            max_bytes: Some(max_bytes),
+            ttl: None,
+        }
+    }
+}
+
+pub struct Cache<V> {
+    config: CacheConfig,
+    entries: LinkedHashMap<HeaderHash, (V, Instant, usize)>,
+    metrics: CacheMetrics,
+}
+
+impl<V: Clone> Cache<V> {
+    pub fn new(config: CacheConfig) -> Self {
+        assert!(config.capacity > 0);
+        Cache {
+            config,
+            entries: LinkedHashMap::new(),
+            metrics: CacheMetrics::default(),
+        }
+    }
+
+    pub fn get(&mut self, header_hash: &HeaderHash) -> Option<V> {
+        let expired = match (self.entries.get_refresh(header_hash), self.config.ttl) {
+            (None, _) => {
+                self.metrics.misses += 1;
+                return None;
+            }
+            (Some((_, inserted_at, _)), Some(ttl)) => inserted_at.elapsed() > ttl,
+            (Some(_), None) => false,
+        };
+
+        if expired {
+            self.remove(header_hash);
+            self.metrics.evictions += 1;
+            self.metrics.misses += 1;
+            None
+        } else {
+            self.metrics.hits += 1;
+            self.entries
+                .get(header_hash)
+                .map(|(value, _, _)| value.clone())
+        }
+    }
+
+    /// insert the value, `size` being its size in bytes
+    pub fn insert(&mut self, header_hash: HeaderHash, value: V, size: usize) {
+        match self
+            .entries
+            .insert(header_hash, (value, Instant::now(), size))
+        {
+            None => self.metrics.insertions += 1,
+            Some((_, _, replaced_size)) => self.metrics.bytes -= replaced_size,
+        }
+        self.metrics.bytes += size;
+
+        let max_bytes = self.config.max_bytes.unwrap_or(std::usize::MAX);
+        while self.entries.len() > self.config.capacity || self.metrics.bytes > max_bytes {
+            if let Some((_, (_, _, size))) = self.entries.pop_front() {
+                self.metrics.bytes -= size;
+                self.metrics.evictions += 1;
+            }
+        }
+    }
+
+    /// remove the block from the cache, to call when the block is removed
+    /// from the storage
+    pub fn remove(&mut self, header_hash: &HeaderHash) {
+        if let Some((_, _, size)) = self.entries.remove(header_hash) {
+            self.metrics.bytes -= size;
+        }
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        self.metrics.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u8) -> HeaderHash {
+        HeaderHash::hash_bytes(&[n])
+    }
+
+    fn cache(capacity: usize, ttl: Option<Duration>) -> Cache<u8> {
+        Cache::new(CacheConfig {
+            capacity,
+            max_bytes: None,
+            ttl,
+        })
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = cache(2, None);
+        cache.insert(hash(0), 0, 1);
+        cache.insert(hash(1), 1, 1);
+        assert_eq!(cache.get(&hash(0)), Some(0));
+        cache.insert(hash(2), 2, 1);
+
+        assert_eq!(cache.get(&hash(1)), None);
+        assert_eq!(cache.get(&hash(0)), Some(0));
+        assert_eq!(cache.get(&hash(2)), Some(2));
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.insertions, 3);
+        assert_eq!(metrics.hits, 3);
+        assert_eq!(metrics.misses, 1);
+        assert_eq!(metrics.evictions, 1);
+    }
+
+    #[test]
+    fn evicts_over_max_bytes() {
+        let mut cache = Cache::new(CacheConfig::with_max_bytes(10));
+        cache.insert(hash(0), 0, 4);
+        cache.insert(hash(1), 1, 4);
+        cache.insert(hash(2), 2, 4);
+
+        assert_eq!(cache.get(&hash(0)), None);
+        assert_eq!(cache.get(&hash(1)), Some(1));
+        assert_eq!(cache.metrics().bytes, 8);
+
+        cache.insert(hash(3), 3, 20);
+        assert_eq!(cache.get(&hash(3)), None);
+        assert_eq!(cache.metrics().bytes, 0);
+    }
+
+    #[test]
+    fn expired_entries_are_misses() {
+        let mut cache = cache(2, Some(Duration::from_secs(0)));
+        cache.insert(hash(0), 0, 1);
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(cache.get(&hash(0)), None);
+        assert_eq!(cache.metrics().evictions, 1);
+    }
+
+    #[test]
+    fn removed_entries_are_purged() {
+        let mut cache = cache(2, None);
+        cache.insert(hash(0), 0, 1);
+        cache.remove(&hash(0));
+
+        assert_eq!(cache.get(&hash(0)), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! checksum of the stored blocks
+//!
+//! The hash of a block only covers its header. To detect the corruption of
+//! the content of a block, the Blake2b hash of the whole serialized block
+//! is recorded when the block is stored and checked when the block is read.
+//!
+//! The checksums are kept out of the storage, in a file next to the storage
+//! file where every record is the hash of a block followed by its checksum.
+//! The file is loaded in memory the first time a checksum is needed. A
+//! storage without file (in memory) keeps its checksums in memory only.
+
+use super::StorageError;
+use crate::blockcfg::{Block, HeaderHash};
+use chain_core::property::Serialize as _;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const RECORD_SIZE: usize = 64;
+
+#[derive(Debug, Error)]
+#[error("block {0} does not match its checksum")]
+pub struct ChecksumMismatch(pub HeaderHash);
+
+impl From<ChecksumMismatch> for StorageError {
+    fn from(error: ChecksumMismatch) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+pub struct Checksums {
+    path: Option<PathBuf>,
+    // `None` until the file has been loaded
+    checksums: Option<HashMap<HeaderHash, HeaderHash>>,
+    // opened on the first write so a read-only storage never creates it
+    file: Option<fs::File>,
+}
+
+impl Checksums {
+    /// the checksums of the storage persisted in `storage_path`, or of a
+    /// storage in memory if `None`
+    pub fn new(storage_path: Option<&Path>) -> Self {
+        let path = storage_path.map(|storage_path| {
+            let mut path = storage_path.as_os_str().to_owned();
+            path.push(".checksums");
+            path.into()
+        });
+        Checksums {
+            path,
+            checksums: None,
+            file: None,
+        }
+    }
+
+    fn load(&mut self) -> io::Result<&mut HashMap<HeaderHash, HeaderHash>> {
+        if self.checksums.is_none() {
+            let mut checksums = HashMap::new();
+            let bytes = match self.path.as_ref().map(fs::read) {
+                None => Vec::new(),
+                Some(Ok(bytes)) => bytes,
+                Some(Err(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Some(Err(e)) => return Err(e),
+            };
+            // a record truncated by a crash while it was appended is ignored
+            for record in bytes.chunks_exact(RECORD_SIZE) {
+                let mut header_hash = [0; 32];
+                let mut checksum = [0; 32];
+                header_hash.copy_from_slice(&record[..32]);
+                checksum.copy_from_slice(&record[32..]);
+                checksums.insert(
+                    HeaderHash::from_bytes(header_hash),
+                    HeaderHash::from_bytes(checksum),
+                );
+            }
+            self.checksums = Some(checksums);
+        }
+        Ok(self.checksums.as_mut().unwrap())
+    }
+
+    /// the file the records are appended to, without the record truncated
+    /// by a crash if any
+    fn file(&mut self) -> io::Result<Option<&mut fs::File>> {
+        if self.file.is_none() {
+            if let Some(path) = self.path.as_ref() {
+                let file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                let len = file.metadata()?.len();
+                file.set_len(len - len % RECORD_SIZE as u64)?;
+                self.file = Some(file);
+            }
+        }
+        Ok(self.file.as_mut())
+    }
+
+    pub fn put(&mut self, block: &Block) -> Result<(), StorageError> {
+        let header_hash = block.header.hash();
+        let checksum = checksum(block)?;
+        self.load().map_err(backend_error)?;
+        if let Some(file) = self.file().map_err(backend_error)? {
+            let mut record = [0; RECORD_SIZE];
+            record[..32].copy_from_slice(header_hash.as_ref());
+            record[32..].copy_from_slice(checksum.as_ref());
+            file.write_all(&record).map_err(backend_error)?;
+        }
+        self.load()
+            .map_err(backend_error)?
+            .insert(header_hash, checksum);
+        Ok(())
+    }
+
+    /// check the block read under `header_hash` matches its checksum. The
+    /// blocks stored without checksum are not checked.
+    pub fn verify(&mut self, header_hash: &HeaderHash, block: &Block) -> Result<(), StorageError> {
+        match self.load().map_err(backend_error)?.get(header_hash) {
+            Some(expected) if *expected != checksum(block)? => {
+                Err(ChecksumMismatch(*header_hash).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
+
+fn checksum(block: &Block) -> Result<HeaderHash, StorageError> {
+    let bytes = block
+        .serialize_as_vec()
+        .map_err(|e| StorageError::BackendError(Box::new(e)))?;
+    Ok(HeaderHash::hash_bytes(&bytes))
+}
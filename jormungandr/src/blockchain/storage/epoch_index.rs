@@ -0,0 +1,169 @@
+//! index of the first block of every epoch
+//!
+//! Kept out of the storage like the fragment index, in a file next to the
+//! storage file where every record is an epoch followed by the hash of a
+//! block. A block is recorded when it is stored if it is the first block
+//! of its epoch on its branch: blocks are stored after their parent, so no
+//! block of the epoch recorded before is its ancestor. If the chain forks
+//! across an epoch boundary an epoch has a block recorded on every branch,
+//! the one on the main branch is picked when the index is read.
+
+use super::{NodeStorageConnection, StorageError};
+use crate::{
+    blockcfg::{Block, Epoch, HeaderHash},
+    blockchain::MAIN_BRANCH_TAG,
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+const RECORD_SIZE: usize = 36;
+
+pub struct EpochIndex {
+    path: Option<PathBuf>,
+    // `None` until the file has been loaded
+    starts: Option<HashMap<Epoch, Vec<HeaderHash>>>,
+    // opened on the first write so a read-only storage never creates it
+    file: Option<fs::File>,
+    // the block indexed last and its epoch, the parent of the next block
+    // on a single branch
+    last: Option<(HeaderHash, Epoch)>,
+}
+
+impl EpochIndex {
+    /// the index of the storage persisted in `storage_path`, or of a
+    /// storage in memory if `None`
+    pub fn new(storage_path: Option<&Path>) -> Self {
+        let path = storage_path.map(|storage_path| {
+            let mut path = storage_path.as_os_str().to_owned();
+            path.push(".epochs");
+            path.into()
+        });
+        EpochIndex {
+            path,
+            starts: None,
+            file: None,
+            last: None,
+        }
+    }
+
+    fn load(&mut self) -> io::Result<&mut HashMap<Epoch, Vec<HeaderHash>>> {
+        if self.starts.is_none() {
+            let mut starts: HashMap<Epoch, Vec<HeaderHash>> = HashMap::new();
+            let bytes = match self.path.as_ref().map(fs::read) {
+                None => Vec::new(),
+                Some(Ok(bytes)) => bytes,
+                Some(Err(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Some(Err(e)) => return Err(e),
+            };
+            // a record truncated by a crash while it was appended is ignored
+            for record in bytes.chunks_exact(RECORD_SIZE) {
+                let epoch = Epoch::from_be_bytes(record[..4].try_into().unwrap());
+                let mut header_hash = [0; 32];
+                header_hash.copy_from_slice(&record[4..]);
+                starts
+                    .entry(epoch)
+                    .or_default()
+                    .push(HeaderHash::from_bytes(header_hash));
+            }
+            self.starts = Some(starts);
+        }
+        Ok(self.starts.as_mut().unwrap())
+    }
+
+    /// the file the records are appended to, without the record truncated
+    /// by a crash if any
+    fn file(&mut self) -> io::Result<Option<&mut fs::File>> {
+        if self.file.is_none() {
+            if let Some(path) = self.path.as_ref() {
+                let file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                let len = file.metadata()?.len();
+                file.set_len(len - len % RECORD_SIZE as u64)?;
+                self.file = Some(file);
+            }
+        }
+        Ok(self.file.as_mut())
+    }
+
+    /// record the block if it is the first block of its epoch on its
+    /// branch. The block following the one indexed last is checked without
+    /// querying the storage.
+    pub fn index_block(
+        &mut self,
+        connection: &mut NodeStorageConnection,
+        block: &Block,
+    ) -> Result<(), StorageError> {
+        let header_hash = block.header.hash();
+        let epoch = block.header.block_date().epoch;
+        let first = match self.last {
+            Some((parent, parent_epoch)) if parent == block.header.block_parent_hash() => {
+                parent_epoch != epoch
+            }
+            _ => {
+                let starts = self.load().map_err(backend_error)?;
+                let mut first = true;
+                for start in starts.get(&epoch).into_iter().flatten() {
+                    if connection.is_ancestor(start, &header_hash)?.is_some() {
+                        first = false;
+                        break;
+                    }
+                }
+                first
+            }
+        };
+        if first {
+            self.record(epoch, header_hash)?;
+        }
+        self.last = Some((header_hash, epoch));
+        Ok(())
+    }
+
+    fn record(&mut self, epoch: Epoch, header_hash: HeaderHash) -> Result<(), StorageError> {
+        self.load().map_err(backend_error)?;
+        if let Some(file) = self.file().map_err(backend_error)? {
+            let mut record = [0; RECORD_SIZE];
+            record[..4].copy_from_slice(&epoch.to_be_bytes());
+            record[4..].copy_from_slice(header_hash.as_ref());
+            file.write_all(&record).map_err(backend_error)?;
+        }
+        self.load()
+            .map_err(backend_error)?
+            .entry(epoch)
+            .or_default()
+            .push(header_hash);
+        Ok(())
+    }
+
+    /// the first block of the epoch on the main branch
+    pub fn get(
+        &mut self,
+        connection: &mut NodeStorageConnection,
+        epoch: Epoch,
+    ) -> Result<Option<HeaderHash>, StorageError> {
+        let starts = match self.load().map_err(backend_error)?.get(&epoch) {
+            None => return Ok(None),
+            Some(starts) => starts.clone(),
+        };
+        let tip = match connection.get_tag(MAIN_BRANCH_TAG)? {
+            None => return Ok(None),
+            Some(tip) => tip,
+        };
+        for start in starts {
+            if start == tip || connection.is_ancestor(&start, &tip)?.is_some() {
+                return Ok(Some(start));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
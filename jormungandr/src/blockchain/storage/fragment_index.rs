@@ -0,0 +1,155 @@
+//! index of the fragments of the stored blocks
+//!
+//! The index is kept out of the storage, in a file next to the storage file
+//! where every record is the id of a fragment followed by the hash of the
+//! block containing it. The file is loaded in memory the first time the
+//! index is used. A storage without file (in memory) keeps its index in
+//! memory only. The position of the fragment in the block is found again
+//! by reading the block.
+
+use super::{NodeStorageConnection, StorageError};
+use crate::blockcfg::{Block, FragmentId, HeaderHash};
+use chain_core::property::Fragment as _;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const RECORD_SIZE: usize = 64;
+
+/// the fragments are looked up in a storage that does not index them, see
+/// `Storage::with_fragment_index`
+#[derive(Debug, Error)]
+#[error("the fragments of the stored blocks are not indexed")]
+pub struct FragmentIndexDisabled;
+
+impl From<FragmentIndexDisabled> for StorageError {
+    fn from(error: FragmentIndexDisabled) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+pub fn is_fragment_index_disabled(error: &StorageError) -> bool {
+    match error {
+        StorageError::BackendError(e) => e.downcast_ref::<FragmentIndexDisabled>().is_some(),
+        _ => false,
+    }
+}
+
+pub struct FragmentIndex {
+    path: Option<PathBuf>,
+    // `None` until the file has been loaded
+    blocks: Option<HashMap<FragmentId, HeaderHash>>,
+    // opened on the first write so a read-only storage never creates it
+    file: Option<fs::File>,
+}
+
+impl FragmentIndex {
+    /// the index of the storage persisted in `storage_path`, or of a
+    /// storage in memory if `None`
+    pub fn new(storage_path: Option<&Path>) -> Self {
+        let path = storage_path.map(|storage_path| {
+            let mut path = storage_path.as_os_str().to_owned();
+            path.push(".fragments");
+            path.into()
+        });
+        FragmentIndex {
+            path,
+            blocks: None,
+            file: None,
+        }
+    }
+
+    fn load(&mut self) -> io::Result<&mut HashMap<FragmentId, HeaderHash>> {
+        if self.blocks.is_none() {
+            let mut blocks = HashMap::new();
+            let bytes = match self.path.as_ref().map(fs::read) {
+                None => Vec::new(),
+                Some(Ok(bytes)) => bytes,
+                Some(Err(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Some(Err(e)) => return Err(e),
+            };
+            // a record truncated by a crash while it was appended is ignored,
+            // a fragment recorded several times is in the block recorded last
+            for record in bytes.chunks_exact(RECORD_SIZE) {
+                let mut fragment_id = [0; 32];
+                let mut header_hash = [0; 32];
+                fragment_id.copy_from_slice(&record[..32]);
+                header_hash.copy_from_slice(&record[32..]);
+                blocks.insert(
+                    FragmentId::from_bytes(fragment_id),
+                    HeaderHash::from_bytes(header_hash),
+                );
+            }
+            self.blocks = Some(blocks);
+        }
+        Ok(self.blocks.as_mut().unwrap())
+    }
+
+    /// the file the records are appended to, without the record truncated
+    /// by a crash if any
+    fn file(&mut self) -> io::Result<Option<&mut fs::File>> {
+        if self.file.is_none() {
+            if let Some(path) = self.path.as_ref() {
+                let file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                let len = file.metadata()?.len();
+                file.set_len(len - len % RECORD_SIZE as u64)?;
+                self.file = Some(file);
+            }
+        }
+        Ok(self.file.as_mut())
+    }
+
+    /// point every fragment of the block to the block. If the fragment is
+    /// in several blocks (on different branches) the block indexed last
+    /// wins.
+    pub fn index_block(&mut self, block: &Block) -> Result<(), StorageError> {
+        let header_hash = block.header.hash();
+        let fragment_ids: Vec<FragmentId> = block.fragments().map(|f| f.id()).collect();
+        if fragment_ids.is_empty() {
+            return Ok(());
+        }
+        self.load().map_err(backend_error)?;
+        if let Some(file) = self.file().map_err(backend_error)? {
+            let mut records = Vec::with_capacity(fragment_ids.len() * RECORD_SIZE);
+            for fragment_id in fragment_ids.iter() {
+                records.extend_from_slice(fragment_id.as_ref());
+                records.extend_from_slice(header_hash.as_ref());
+            }
+            file.write_all(&records).map_err(backend_error)?;
+        }
+        let blocks = self.load().map_err(backend_error)?;
+        for fragment_id in fragment_ids {
+            blocks.insert(fragment_id, header_hash);
+        }
+        Ok(())
+    }
+
+    /// the block containing the fragment and the index of the fragment in
+    /// the block
+    pub fn locate(
+        &mut self,
+        connection: &mut NodeStorageConnection,
+        fragment_id: &FragmentId,
+    ) -> Result<Option<(HeaderHash, usize)>, StorageError> {
+        let header_hash = match self.load().map_err(backend_error)?.get(fragment_id) {
+            None => return Ok(None),
+            Some(header_hash) => *header_hash,
+        };
+        let (block, _block_info) = connection.get_block(&header_hash)?;
+        Ok(block
+            .fragments()
+            .position(|fragment| fragment.id() == *fragment_id)
+            .map(|index| (header_hash, index)))
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
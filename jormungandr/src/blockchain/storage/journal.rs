@@ -0,0 +1,134 @@
+//! journal of the block being written
+//!
+//! Before a block is written its hash is recorded in a file next to the
+//! storage file, the file is removed once the write is over. A journal
+//! found on start up means the node stopped while writing the block.
+//!
+//! The journal is kept out of the storage so recording a write does not
+//! cost any write to the storage. It is only kept if enabled with
+//! `Storage::with_journal`, a storage without file (in memory) has no
+//! journal.
+
+use super::{NodeStorageConnection, StorageError};
+use crate::blockcfg::{Block, HeaderHash};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// outcome of the recovery of the journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    /// no write was interrupted
+    Clean,
+    /// the write of the block was interrupted but the block was fully
+    /// written
+    Completed(HeaderHash),
+    /// the write of the block was interrupted before anything was written
+    NotWritten(HeaderHash),
+    /// the block was partially written. The backend cannot remove a block
+    /// so the write is not rolled back, a `verify` of the storage tells
+    /// whether it is usable.
+    Partial(HeaderHash),
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// the journal of the storage persisted in `storage_path`
+    pub fn new(storage_path: &Path) -> Self {
+        let mut path = storage_path.as_os_str().to_owned();
+        path.push(".journal");
+        Journal { path: path.into() }
+    }
+
+    fn begin(&self, header_hash: &HeaderHash) -> io::Result<()> {
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(header_hash.as_ref())?;
+        file.sync_all()
+    }
+
+    fn end(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// the block of the write that did not complete, if any. A journal
+    /// truncated by a crash while it was recorded is ignored, the write of
+    /// the block had not started.
+    fn pending(&self) -> io::Result<Option<HeaderHash>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if bytes.len() != 32 {
+            return Ok(None);
+        }
+        let mut header_hash = [0; 32];
+        header_hash.copy_from_slice(&bytes);
+        Ok(Some(HeaderHash::from_bytes(header_hash)))
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
+
+/// write the block, recording it in the `journal` for the duration of the
+/// write. The result is the one of writing the block: a journal that
+/// cannot be removed once the block is written is checked on start up.
+pub fn put_block(
+    connection: &mut NodeStorageConnection,
+    journal: Option<&Journal>,
+    block: &Block,
+) -> Result<(), StorageError> {
+    if let Some(journal) = journal {
+        journal.begin(&block.header.hash()).map_err(backend_error)?;
+    }
+    let res = connection.put_block(block);
+    if let Some(journal) = journal {
+        let _ = journal.end();
+    }
+    res
+}
+
+/// check the block of a write that did not complete, if any, and clear
+/// the journal
+pub fn recover(
+    connection: &mut NodeStorageConnection,
+    journal: &Journal,
+) -> Result<Recovery, StorageError> {
+    let header_hash = match journal.pending().map_err(backend_error)? {
+        Some(header_hash) => header_hash,
+        None => {
+            journal.end().map_err(backend_error)?;
+            return Ok(Recovery::Clean);
+        }
+    };
+
+    let has_block = match connection.get_block(&header_hash) {
+        Ok(_) => true,
+        Err(StorageError::BlockNotFound) => false,
+        Err(e) => return Err(e),
+    };
+    let has_info = match connection.get_block_info(&header_hash) {
+        Ok(_) => true,
+        Err(StorageError::BlockNotFound) => false,
+        Err(e) => return Err(e),
+    };
+    let recovery = match (has_block, has_info) {
+        (true, true) => Recovery::Completed(header_hash),
+        (false, false) => Recovery::NotWritten(header_hash),
+        _ => Recovery::Partial(header_hash),
+    };
+
+    journal.end().map_err(backend_error)?;
+    Ok(recovery)
+}
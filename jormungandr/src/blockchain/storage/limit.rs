@@ -0,0 +1,56 @@
+use futures03::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio02::sync::Semaphore;
+
+/// bound on the number of read operations running concurrently on the
+/// backend, the other read operations are queued. The write operations
+/// are not bounded.
+pub(super) struct ReadLimit {
+    semaphore: Semaphore,
+    queued: AtomicUsize,
+}
+
+/// decrement the queue depth when the read operation leaves the queue,
+/// including when it is cancelled
+struct Queued<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for Queued<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ReadLimit {
+    pub fn new(max_concurrent_reads: usize) -> Self {
+        assert!(max_concurrent_reads > 0);
+        ReadLimit {
+            semaphore: Semaphore::new(max_concurrent_reads),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// number of read operations waiting for a permit
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+}
+
+/// run the read operation once a permit of the `limit` is available
+pub(super) async fn with_read_limit<F, R>(limit: Option<&ReadLimit>, f: F) -> R
+where
+    F: Future<Output = R>,
+{
+    match limit {
+        None => f.await,
+        Some(limit) => {
+            let permit = {
+                limit.queued.fetch_add(1, Ordering::Relaxed);
+                let _queued = Queued(&limit.queued);
+                limit.semaphore.acquire().await
+            };
+            let res = f.await;
+            drop(permit);
+            res
+        }
+    }
+}
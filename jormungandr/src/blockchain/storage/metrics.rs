@@ -0,0 +1,115 @@
+//! latency metrics of the storage operations
+//!
+//! The metrics are always collected, they only cost a few atomic
+//! increments per operation.
+
+use super::observer::StorageObserver;
+use crate::blockcfg::HeaderHash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// upper bounds of the buckets of the latency histograms, in microseconds.
+/// The last bucket of a histogram counts the operations slower than the
+/// last bound.
+const BUCKET_BOUNDS_US: [u64; 6] = [100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+
+/// histogram of the duration of an operation since the start of the node
+#[derive(Default)]
+pub struct Histogram {
+    counts: [AtomicU64; BUCKET_BOUNDS_US.len() + 1],
+    sum_us: AtomicU64,
+}
+
+/// state of a `Histogram` at a given time
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramSnapshot {
+    /// upper bounds of the buckets in microseconds, there is one more
+    /// bucket than bounds
+    pub bounds_us: Vec<u64>,
+    pub counts: Vec<u64>,
+    /// total duration of the operations in microseconds
+    pub sum_us: u64,
+}
+
+/// latency histograms of the storage operations
+#[derive(Default)]
+pub struct StorageMetrics {
+    get: Histogram,
+    put: Histogram,
+    stream_block: Histogram,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageMetricsSnapshot {
+    pub get: HistogramSnapshot,
+    pub put: HistogramSnapshot,
+    pub stream_block: HistogramSnapshot,
+}
+
+impl Histogram {
+    pub fn record(&self, duration: Duration) {
+        let us = duration.as_micros() as u64;
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|bound| us <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            bounds_us: BUCKET_BOUNDS_US.to_vec(),
+            counts: self
+                .counts
+                .iter()
+                .map(|count| count.load(Ordering::Relaxed))
+                .collect(),
+            sum_us: self.sum_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl StorageMetrics {
+    pub fn snapshot(&self) -> StorageMetricsSnapshot {
+        StorageMetricsSnapshot {
+            get: self.get.snapshot(),
+            put: self.put.snapshot(),
+            stream_block: self.stream_block.snapshot(),
+        }
+    }
+}
+
+impl StorageObserver for StorageMetrics {
+    fn on_get(&self, _header_hash: &HeaderHash, _found: bool, duration: Duration) {
+        self.get.record(duration)
+    }
+
+    fn on_put(&self, _header_hash: &HeaderHash, duration: Duration) {
+        self.put.record(duration)
+    }
+
+    fn on_stream_block(&self, _header_hash: &HeaderHash, duration: Duration) {
+        self.stream_block.record(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_in_buckets() {
+        let histogram = Histogram::default();
+        histogram.record(Duration::from_micros(50));
+        histogram.record(Duration::from_micros(100));
+        histogram.record(Duration::from_millis(5));
+        histogram.record(Duration::from_secs(60));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.counts, vec![2, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(snapshot.sum_us, 50 + 100 + 5_000 + 60_000_000);
+    }
+}
@@ -0,0 +1,3617 @@
+mod backup;
+mod block_list;
+mod bloom;
+mod cache;
+mod checksum;
+mod epoch_index;
+mod fragment_index;
+mod journal;
+mod limit;
+mod metrics;
+mod observer;
+mod orphan;
+mod retry;
+mod timeout;
+mod version;
+
+use crate::{
+    blockcfg::{Block, Epoch, FragmentId, Header, HeaderHash},
+    blockchain::MAIN_BRANCH_TAG,
+    start_up::{NodeStorage, NodeStorageConnection},
+};
+use async_trait::async_trait;
+use bb8::{ManageConnection, Pool, RunError};
+use chain_core::property::Serialize as _;
+use chain_storage_sqlite_old::{for_path_to_nth_ancestor, BlockInfo, BlockStore};
+use futures::{Async, Future as Future01, Poll as Poll01, Sink as Sink01, Stream as Stream01};
+use futures03::{
+    compat::*,
+    executor::block_on,
+    future,
+    io::{AllowStdIo, AsyncRead, AsyncWrite, AsyncWriteExt},
+    prelude::*,
+    sink::{Sink, SinkExt},
+    stream::{self, Stream},
+    task::{Context, Poll},
+};
+use slog::Logger;
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::identity,
+    io::{BufReader, BufWriter},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio02::{
+    sync::{Mutex, MutexGuard},
+    task::spawn_blocking,
+};
+use tokio_compat::runtime;
+
+use self::{
+    block_list::BlockList, bloom::BloomFilter, cache::Cache, checksum::Checksums,
+    epoch_index::EpochIndex, fragment_index::FragmentIndex, journal::Journal, limit::ReadLimit,
+    metrics::StorageMetrics, observer::StorageObserver, orphan::OrphanPool, version::VersionFile,
+};
+
+pub use self::backup::BackupError;
+pub use self::block_list::BlockListIncomplete;
+pub use self::bloom::BloomConfig;
+pub use self::cache::{CacheConfig, CacheMetrics};
+pub use self::checksum::ChecksumMismatch;
+pub use self::fragment_index::{is_fragment_index_disabled, FragmentIndexDisabled};
+pub use self::journal::Recovery;
+pub use self::metrics::{HistogramSnapshot, StorageMetricsSnapshot};
+pub use self::orphan::OrphanConfig;
+pub use self::retry::{is_backend_error, RetryPolicy};
+pub use self::timeout::{is_timeout, StorageTimeout};
+pub use self::version::{VersionError, STORAGE_VERSION};
+pub use chain_storage_sqlite_old::Error as StorageError;
+
+/// Default number of blocks `send_branch` reads from the storage before
+/// handing them to the sink and yielding back to the executor.
+pub const DEFAULT_SEND_BATCH_SIZE: usize = 32;
+/// number of blocks read ahead by the reversed block streams
+pub const DEFAULT_REVERSED_PREFETCH: usize = 32;
+
+/// tuning of the sending of a branch to a sink, see
+/// `Storage::send_branch_with_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendConfig {
+    /// number of blocks read from the storage and sent to the sink
+    /// before yielding back to the executor.
+    pub batch_size: NonZeroUsize,
+    /// maximum number of blocks to send, the branch is cut short once
+    /// this many blocks have been sent.
+    pub max_blocks: Option<u64>,
+}
+
+/// outcome of `Storage::put_block_checked`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutOutcome {
+    /// the block was not in the storage and has been stored
+    Inserted,
+    /// the block was already in the storage, nothing was written
+    AlreadyPresent,
+}
+
+/// token to stop a `send_branch_cancellable` from another task. All the
+/// clones of a token share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        SendConfig {
+            batch_size: NonZeroUsize::new(DEFAULT_SEND_BATCH_SIZE).unwrap(),
+            max_blocks: None,
+        }
+    }
+}
+
+async fn run_blocking_storage<F, R>(f: F) -> Result<R, StorageError>
+where
+    F: FnOnce() -> Result<R, StorageError> + Send + 'static,
+    R: Send + 'static,
+{
+    spawn_blocking(f)
+        .await
+        .map_err(|e| StorageError::BackendError(Box::new(e)))
+        .and_then(identity)
+}
+
+/// read the block, `None` if it is not in the storage
+fn read_block(
+    connection: &mut NodeStorageConnection,
+    header_hash: &HeaderHash,
+    checksums: Option<&std::sync::Mutex<Checksums>>,
+) -> Result<Option<Block>, StorageError> {
+    match connection.get_block(header_hash) {
+        Err(StorageError::BlockNotFound) => Ok(None),
+        Ok((block, _block_info)) => {
+            if let Some(checksums) = checksums {
+                checksums.lock().unwrap().verify(header_hash, &block)?;
+            }
+            Ok(Some(block))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// the SQLite URI of the file at `path` opened read-only, the backend
+/// opens its file with the URI filenames of SQLite enabled
+fn read_only_uri(path: &Path) -> PathBuf {
+    let path = path
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    PathBuf::from(format!("file:{}?mode=ro", path))
+}
+
+/// run `f` on an archive file and its checksum in the blocking thread
+/// pool, they are given back with the result
+async fn blocking_archive_io<T, F, R>(
+    archive: (T, backup::Checksum),
+    f: F,
+) -> Result<((T, backup::Checksum), R), StorageError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut (T, backup::Checksum)) -> Result<R, BackupError> + Send + 'static,
+    R: Send + 'static,
+{
+    let mut archive = archive;
+    run_blocking_storage(move || {
+        let res = f(&mut archive)?;
+        Ok((archive, res))
+    })
+    .await
+}
+
+async fn run_blocking_with_connection<F, R>(
+    pool: &Pool<ConnectionManager>,
+    f: F,
+) -> Result<R, StorageError>
+where
+    F: FnOnce(&mut NodeStorageConnection) -> Result<R, StorageError> + Send + 'static,
+    R: Send + 'static,
+{
+    pool.run(|mut connection| async move {
+        spawn_blocking(move || match f(&mut connection) {
+            Ok(r) => Ok((r, connection)),
+            Err(r) => Err((r, connection)),
+        })
+        .await
+        .unwrap()
+    })
+    .await
+    .map_err(|e| match e {
+        RunError::User(e) => e,
+        e => StorageError::BackendError(Box::new(e)),
+    })
+}
+
+#[derive(Clone)]
+struct ConnectionManager {
+    inner: Arc<NodeStorage>,
+}
+
+impl ConnectionManager {
+    pub fn new(storage: NodeStorage) -> Self {
+        Self {
+            inner: Arc::new(storage),
+        }
+    }
+}
+
+#[async_trait]
+impl ManageConnection for ConnectionManager {
+    type Connection = NodeStorageConnection;
+    type Error = StorageError;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let inner = self.inner.clone();
+        run_blocking_storage(move || inner.connect()).await
+    }
+
+    async fn is_valid(&self, conn: Self::Connection) -> Result<Self::Connection, Self::Error> {
+        run_blocking_storage(move || conn.ping().and(Ok(conn))).await
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.ping().is_ok()
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage03 {
+    pool: Pool<ConnectionManager>,
+
+    // All write operations must be performed only via this lock. The lock helps
+    // us to ensure that all of the write operations are performed in the right
+    // sequence. Otherwise they can be performed out of the expected order (for
+    // example, by different tokio executors) which eventually leads to a panic
+    // because the block data would be inconsistent at the time of a write.
+    write_lock: Arc<Mutex<()>>,
+
+    // Number of blocks read from the storage with a single connection
+    // while sending a branch. The connection is released between batches
+    // so other queries can be served in the meantime.
+    send_batch_size: NonZeroUsize,
+    // Number of blocks read ahead by the reversed block streams.
+    reversed_prefetch: usize,
+
+    // Path of the database file, if the storage is not in memory. Only used
+    // to report the disk usage.
+    file_path: Option<PathBuf>,
+    // Journal of the block writes, if enabled.
+    journal: Option<Journal>,
+
+    // Hashes of all the stored blocks.
+    block_list: Arc<std::sync::Mutex<BlockList>>,
+    // Sizing and filter of the hashes of the stored blocks, used by
+    // `block_exists` to answer "no" without querying the storage. The
+    // filter is only set once it has been populated, see
+    // `enable_bloom_filter`.
+    bloom_config: Option<BloomConfig>,
+    bloom_filter: Arc<RwLock<Option<BloomFilter>>>,
+
+    // How the read operations are retried on transient errors.
+    retry_policy: RetryPolicy,
+
+    // Reports the storage operations to `metrics`.
+    observer: Arc<dyn StorageObserver>,
+    metrics: Arc<StorageMetrics>,
+
+    // Blocks recently read with `get`, if enabled.
+    cache: Option<Arc<std::sync::Mutex<Cache<Block>>>>,
+    // Whether the write operations fail with `StorageReadOnly`.
+    read_only: bool,
+    // Index of the fragments of the stored blocks, if enabled.
+    fragment_index: Option<Arc<std::sync::Mutex<FragmentIndex>>>,
+    // Index of the first block of every epoch, if enabled.
+    epoch_index: Option<Arc<std::sync::Mutex<EpochIndex>>>,
+    // Checksums of the blocks, checked when the blocks are read, if enabled.
+    checksums: Option<Arc<std::sync::Mutex<Checksums>>>,
+    // Blocks whose parent is not in the storage yet, if enabled.
+    orphans: Option<Arc<std::sync::Mutex<OrphanPool<Block>>>>,
+
+    // Maximum duration of a read operation, or of the reading of a block
+    // of a stream.
+    timeout: Option<Duration>,
+    // Logger of the block streaming operations, if enabled.
+    logger: Option<Logger>,
+    // Bound on the number of concurrent read operations, if enabled.
+    read_limit: Option<Arc<ReadLimit>>,
+    // Latest usage computed and when, see `usage`.
+    usage: Arc<std::sync::Mutex<Option<(Instant, StorageUsage)>>>,
+}
+
+// Compatibility layer for using new storage with old futures API.
+#[derive(Clone)]
+pub struct Storage {
+    inner: Storage03,
+}
+
+/// report of the resources used by the storage
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    /// approximate size of the storage on disk, `None` if the storage
+    /// is in memory. The size of the files is reported, this includes
+    /// the space the backend has not reclaimed yet.
+    pub bytes_on_disk: Option<u64>,
+    /// number of stored blocks, side branches included. `None` if the
+    /// storage was created before its blocks were listed, see
+    /// `BlockListIncomplete`.
+    pub block_count: Option<u64>,
+    /// chain length of the tip of the main branch, the number of blocks
+    /// of the main branch
+    pub tip_depth: u64,
+}
+
+/// a write operation was attempted on a storage opened read-only
+#[derive(Debug, Error)]
+#[error("the storage is read-only")]
+pub struct StorageReadOnly;
+
+impl From<StorageReadOnly> for StorageError {
+    fn from(error: StorageReadOnly) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+/// a `put_blocks` batch failed, the first `stored` blocks of the batch are
+/// in the storage
+#[derive(Debug, Error)]
+#[error("{source} (after {stored} blocks of the batch were stored)")]
+pub struct PutBlocksError {
+    pub stored: usize,
+    #[source]
+    pub source: StorageError,
+}
+
+/// problems found by `Storage::verify`
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// number of blocks checked
+    pub blocks_checked: u64,
+    /// tags pointing to a block that is not in the storage
+    pub dangling_tags: Vec<(String, HeaderHash)>,
+    /// blocks whose parent is not in the storage: `(block, parent)`
+    pub dangling_parents: Vec<(HeaderHash, HeaderHash)>,
+    /// blocks whose stored parent does not match the parent in the
+    /// block's header: `(block, stored parent, header's parent)`
+    pub parent_mismatches: Vec<(HeaderHash, HeaderHash, HeaderHash)>,
+    /// blocks whose chain length is not the one of their parent plus one:
+    /// `(block, block's chain length, parent's chain length)`
+    pub depth_discontinuities: Vec<(HeaderHash, u64, u64)>,
+    /// blocks stored under a hash that is not the hash of their header:
+    /// `(stored hash, header's hash)`
+    pub hash_mismatches: Vec<(HeaderHash, HeaderHash)>,
+}
+
+impl VerifyReport {
+    /// no problem was found
+    pub fn is_consistent(&self) -> bool {
+        self.dangling_tags.is_empty()
+            && self.dangling_parents.is_empty()
+            && self.parent_mismatches.is_empty()
+            && self.depth_discontinuities.is_empty()
+            && self.hash_mismatches.is_empty()
+    }
+}
+
+/// debug log of a block streaming operation, emitted once the operation
+/// is over (when dropped), whether the stream completed or was dropped
+/// early.
+///
+/// The node logs through slog only, there is no `tracing` subscriber to
+/// report spans to. The log is a structured record so its fields stay
+/// queryable with the JSON and GELF outputs, and it is not built at all
+/// without `Storage::with_logger`.
+struct OperationLog {
+    logger: Logger,
+    operation: &'static str,
+    to: HeaderHash,
+    from_depth: u64,
+    blocks: u64,
+    started: Instant,
+}
+
+impl Drop for OperationLog {
+    fn drop(&mut self) {
+        debug!(self.logger, "storage operation completed";
+            "operation" => self.operation,
+            "to" => %self.to,
+            "from_depth" => self.from_depth,
+            "blocks" => self.blocks,
+            "duration_ms" => self.started.elapsed().as_millis() as u64,
+        );
+    }
+}
+
+/// maximum number of checkpoints `find_closest_ancestor` looks up
+/// concurrently
+const FIND_ANCESTOR_CONCURRENCY: usize = 4;
+
+/// how long the usage of the storage is cached
+const USAGE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// stream of blocks read from the storage.
+///
+/// It implements both the `futures` 0.3 and 0.1 `Stream` traits so it
+/// can be consumed from the code that has already been migrated to the
+/// new futures and from the code that has not.
+pub struct BlockStream {
+    inner: Compat<Pin<Box<dyn Stream<Item = Result<Block, StorageError>> + Send>>>,
+    cursor: Option<BlockCursor>,
+}
+
+/// position of a `BlockStream`, allows to resume the stream later with
+/// `resume_stream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCursor {
+    /// the last block yielded by the stream, or the `from` block of the
+    /// stream if no block has been yielded yet
+    pub last: HeaderHash,
+    /// the depth (chain length in the storage) of `last`
+    pub depth: u64,
+}
+
+pub struct Ancestor {
+    pub header_hash: HeaderHash,
+    pub distance: u64,
+}
+
+/// result of `find_closest_ancestor`
+pub struct ClosestAncestor {
+    pub ancestor: Option<Ancestor>,
+    /// the checkpoints that are not in the storage, a peer sending many
+    /// of them may be misbehaving
+    pub unknown_checkpoints: Vec<HeaderHash>,
+}
+
+struct BlockIterState {
+    to_length: u64,
+    cur_length: u64,
+    remaining: Option<u64>,
+    pending_infos: Vec<BlockInfo<HeaderHash>>,
+}
+
+struct BlockIterReversedState {
+    next: Option<HeaderHash>,
+    to: Option<HeaderHash>,
+    remaining: Option<u64>,
+    // blocks read ahead and not yielded yet, `next` is the parent of the
+    // last one
+    buffer: VecDeque<Block>,
+    prefetch: usize,
+}
+
+impl Storage03 {
+    pub fn new(storage: NodeStorage) -> Self {
+        let mut rt = runtime::Builder::new()
+            .name_prefix("new-storage-worker-")
+            .core_threads(1)
+            .build()
+            .unwrap();
+
+        rt.block_on_std(async move {
+            let manager = ConnectionManager::new(storage);
+            let pool = Pool::builder().build(manager).await.unwrap();
+            let write_lock = Arc::new(Mutex::new(()));
+            let metrics = Arc::new(StorageMetrics::default());
+
+            Storage03 {
+                pool,
+                write_lock,
+                send_batch_size: NonZeroUsize::new(DEFAULT_SEND_BATCH_SIZE).unwrap(),
+                reversed_prefetch: DEFAULT_REVERSED_PREFETCH,
+                file_path: None,
+                journal: None,
+                block_list: Arc::new(std::sync::Mutex::new(BlockList::new(None, false))),
+                bloom_config: None,
+                bloom_filter: Arc::new(RwLock::new(None)),
+                retry_policy: RetryPolicy::none(),
+                observer: metrics.clone(),
+                metrics,
+                cache: None,
+                read_only: false,
+                fragment_index: None,
+                epoch_index: None,
+                checksums: None,
+                orphans: None,
+                timeout: None,
+                logger: None,
+                read_limit: None,
+                usage: Arc::new(std::sync::Mutex::new(None)),
+            }
+        })
+    }
+
+    async fn run<F, R>(&self, f: F) -> Result<R, StorageError>
+    where
+        F: FnOnce(&mut NodeStorageConnection) -> Result<R, StorageError> + Send + 'static,
+        R: Send + 'static,
+    {
+        run_blocking_with_connection(&self.pool, f).await
+    }
+
+    /// same as `run` but every attempt waits for a permit of the
+    /// `read_limit`, is bounded by the `timeout` and the operation is
+    /// retried according to the `retry_policy`, only use for read
+    /// operations.
+    async fn run_read<F, R>(&self, f: F) -> Result<R, StorageError>
+    where
+        F: Fn(&mut NodeStorageConnection) -> Result<R, StorageError> + Clone + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run_read_with_timeout(self.timeout, f).await
+    }
+
+    /// same as `run_read` with the given `timeout` instead of the one of
+    /// the storage
+    async fn run_read_with_timeout<F, R>(
+        &self,
+        timeout: Option<Duration>,
+        f: F,
+    ) -> Result<R, StorageError>
+    where
+        F: Fn(&mut NodeStorageConnection) -> Result<R, StorageError> + Clone + Send + 'static,
+        R: Send + 'static,
+    {
+        self.retry_policy
+            .run(|| {
+                limit::with_read_limit(
+                    self.read_limit.as_deref(),
+                    timeout::with_timeout(timeout, self.run(f.clone())),
+                )
+            })
+            .await
+    }
+
+    pub async fn get_tag(&self, tag: String) -> Result<Option<HeaderHash>, StorageError> {
+        self.run_read(move |connection| connection.get_tag(&tag))
+            .await
+    }
+
+    /// check whether the tag exists. The sqlite backend does not have a
+    /// query for the existence of a tag so the tag is still read.
+    pub async fn contains_tag(&self, tag: String) -> Result<bool, StorageError> {
+        self.run_read(move |connection| connection.get_tag(&tag).map(|tag| tag.is_some()))
+            .await
+    }
+
+    pub async fn put_tag(&self, tag: String, header_hash: HeaderHash) -> Result<(), StorageError> {
+        let _write_lock = self.lock_for_write().await?;
+        self.run(move |connection| connection.put_tag(&tag, &header_hash))
+            .await
+    }
+
+    /// Store the `blocks` in order, with a single connection while holding
+    /// the write lock. The blocks already present are skipped. Returns the
+    /// number of blocks of the batch in the storage, the blocks already
+    /// present included: all of them on success.
+    ///
+    /// The storage backend does not expose transactions: the batch is not
+    /// atomic. On error the blocks before the failing one remain stored,
+    /// their number is given by `PutBlocksError::stored` so the batch can
+    /// be resumed from the failing block.
+    pub async fn put_blocks(&self, blocks: Vec<Block>) -> Result<usize, PutBlocksError> {
+        let not_stored = |source| PutBlocksError { stored: 0, source };
+        let _write_lock = self.lock_for_write().await.map_err(not_stored)?;
+        let started = Instant::now();
+        let options = self.write_options();
+        let journal = self.journal();
+        let (stored, res) = self
+            .run(move |connection| {
+                let mut stored = Vec::with_capacity(blocks.len());
+                for block in blocks.iter() {
+                    match write_block(connection, journal.as_ref(), block, &options) {
+                        Ok(()) => {
+                            stored.push(block.header.hash());
+                            if let Err(e) = record_block(connection, block, &options) {
+                                return Ok((stored, Err(e)));
+                            }
+                        }
+                        Err(StorageError::BlockAlreadyPresent) => stored.push(block.header.hash()),
+                        Err(StorageError::BlockNotFound) => {
+                            return Ok((stored, Err(StorageError::MissingParent)))
+                        }
+                        Err(e) => return Ok((stored, Err(e))),
+                    }
+                }
+                Ok((stored, Ok(())))
+            })
+            .await
+            .map_err(not_stored)?;
+        for header_hash in stored.iter() {
+            self.bloom_insert(header_hash);
+            self.observer.on_put(header_hash, started.elapsed());
+        }
+        let stored = stored.len();
+        res.map(|()| stored)
+            .map_err(|source| PutBlocksError { stored, source })
+    }
+
+    /// Point every tag to its block, with a single connection while holding
+    /// the write lock.
+    ///
+    /// All the blocks are checked to be present before any tag is written,
+    /// if one of them is missing the call fails with `BlockNotFound` and no
+    /// tag is changed. The storage backend does not expose transactions:
+    /// the readers may see some of the tags updated before the others. If
+    /// writing a tag fails, the tags already written are pointed back to
+    /// their previous block, a tag that did not exist before cannot be
+    /// removed and is left updated.
+    pub async fn put_tags(&self, tags: Vec<(String, HeaderHash)>) -> Result<(), StorageError> {
+        let _write_lock = self.lock_for_write().await?;
+        self.run(move |connection| {
+            let mut previous = Vec::with_capacity(tags.len());
+            for (tag, header_hash) in tags.iter() {
+                if !connection.block_exists(header_hash)? {
+                    return Err(StorageError::BlockNotFound);
+                }
+                previous.push(connection.get_tag(tag)?);
+            }
+            for (written, (tag, header_hash)) in tags.iter().enumerate() {
+                if let Err(e) = connection.put_tag(tag, header_hash) {
+                    for ((tag, _), previous) in tags.iter().zip(previous).take(written) {
+                        if let Some(previous) = previous {
+                            // best effort, the original error is reported
+                            let _ = connection.put_tag(tag, &previous);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    fn write_options(&self) -> WriteOptions {
+        WriteOptions {
+            fragment_index: self.fragment_index.clone(),
+            epoch_index: self.epoch_index.clone(),
+            checksums: self.checksums.clone(),
+            block_list: self.block_list.clone(),
+        }
+    }
+
+    /// the journal of the writes, if enabled
+    fn journal(&self) -> Option<Journal> {
+        self.journal.clone()
+    }
+
+    /// take the write lock, fails if the storage is read-only
+    async fn lock_for_write(&self) -> Result<MutexGuard<'_, ()>, StorageError> {
+        if self.read_only {
+            return Err(StorageReadOnly.into());
+        }
+        Ok(self.write_lock.lock().await)
+    }
+
+    /// Check whether the node stopped while writing a block, see the
+    /// `journal` module. To call on start up, before writing to the storage.
+    /// A journal left next to the storage file is checked even if the
+    /// journal is not enabled anymore.
+    pub async fn recover(&self) -> Result<Recovery, StorageError> {
+        let _write_lock = self.lock_for_write().await?;
+        let journal = match self.file_path.as_deref().map(Journal::new) {
+            Some(journal) => journal,
+            None => return Ok(Recovery::Clean),
+        };
+        self.run(move |connection| journal::recover(connection, &journal))
+            .await
+    }
+
+    /// Check the storage has the `expected` format version, kept in a file
+    /// next to the storage file. A storage without version (new or created
+    /// before the version was recorded) is stamped with the `expected`
+    /// version, unless it is read-only. A storage in memory is not checked.
+    pub async fn check_version(&self, expected: u32) -> Result<(), StorageError> {
+        let _write_lock = self.write_lock.lock().await;
+        let version_file = match self.file_path.as_deref() {
+            Some(path) => VersionFile::new(path),
+            None => return Ok(()),
+        };
+        let read_only = self.read_only;
+        run_blocking_storage(move || version_file.check(expected, read_only)).await
+    }
+
+    pub async fn get(&self, header_hash: HeaderHash) -> Result<Option<Block>, StorageError> {
+        let started = Instant::now();
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.lock().unwrap().get(&header_hash));
+        if let Some(block) = cached {
+            self.observer.on_get(&header_hash, true, started.elapsed());
+            return Ok(Some(block));
+        }
+
+        let checksums = self.checksums.clone();
+        let res = self
+            .run_read(move |connection| read_block(connection, &header_hash, checksums.as_deref()))
+            .await;
+        if let Ok(block) = &res {
+            self.observer
+                .on_get(&header_hash, block.is_some(), started.elapsed());
+            if let Some(block) = block {
+                self.cache_block(header_hash, block);
+            }
+        }
+        res
+    }
+
+    fn cache_block(&self, header_hash: HeaderHash, block: &Block) {
+        if let Some(cache) = self.cache.as_ref() {
+            let size = block.serialize_as_vec().map_or(0, |bytes| bytes.len());
+            cache
+                .lock()
+                .unwrap()
+                .insert(header_hash, block.clone(), size);
+        }
+    }
+
+    /// the block containing the fragment and the index of the fragment in
+    /// the block, `None` if the fragment is not in an indexed block. Fails
+    /// with `FragmentIndexDisabled` if the fragments are not indexed, see
+    /// `Storage::with_fragment_index`.
+    pub async fn get_fragment_location(
+        &self,
+        fragment_id: FragmentId,
+    ) -> Result<Option<(HeaderHash, usize)>, StorageError> {
+        let fragment_index = self.fragment_index.clone().ok_or(FragmentIndexDisabled)?;
+        self.run_read(move |connection| {
+            fragment_index
+                .lock()
+                .unwrap()
+                .locate(connection, &fragment_id)
+        })
+        .await
+    }
+
+    /// the first block of the epoch on the main branch, `None` if no block
+    /// of the epoch has been stored since the epochs are indexed or if they
+    /// are not indexed. See `Storage::with_epoch_index`.
+    pub async fn get_epoch_start(&self, epoch: Epoch) -> Result<Option<HeaderHash>, StorageError> {
+        let epoch_index = match self.epoch_index.clone() {
+            Some(epoch_index) => epoch_index,
+            None => return Ok(None),
+        };
+        self.run_read(move |connection| epoch_index.lock().unwrap().get(connection, epoch))
+            .await
+    }
+
+    /// keep the block in the orphan pool until its parent is stored, to
+    /// call when storing the block failed with `MissingParent`. Does
+    /// nothing if the orphan pool is not enabled.
+    pub fn add_orphan(&self, block: Block) {
+        if let Some(orphans) = self.orphans.as_ref() {
+            orphans.lock().unwrap().insert(
+                block.header.hash(),
+                block.header.block_parent_hash(),
+                block,
+            );
+        }
+    }
+
+    /// remove from the orphan pool the descendants of `parent` and return
+    /// them, each block coming after its parent, so they can be stored
+    /// once `parent` has been stored
+    pub fn resolve_orphans(&self, parent: HeaderHash) -> Vec<Block> {
+        self.orphans
+            .as_ref()
+            .map_or_else(Vec::new, |orphans| orphans.lock().unwrap().resolve(&parent))
+    }
+
+    /// latency histograms of the storage operations since the start of
+    /// the node
+    pub fn metrics(&self) -> StorageMetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// counters of the block cache, `None` if the cache is not enabled
+    pub fn cache_metrics(&self) -> Option<CacheMetrics> {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().metrics())
+    }
+
+    /// get the blocks of the given hashes using a single storage connection.
+    /// The result is positionally aligned with `header_hashes`, with `None`
+    /// for the blocks that are not in the storage. Same as `get` the cache
+    /// is used and the checksums are verified.
+    pub async fn get_blocks(
+        &self,
+        header_hashes: Vec<HeaderHash>,
+    ) -> Result<Vec<Option<Block>>, StorageError> {
+        let mut blocks: Vec<Option<Block>> = match self.cache.as_ref() {
+            None => vec![None; header_hashes.len()],
+            Some(cache) => {
+                let mut cache = cache.lock().unwrap();
+                header_hashes
+                    .iter()
+                    .map(|header_hash| cache.get(header_hash))
+                    .collect()
+            }
+        };
+        let missing: Vec<HeaderHash> = header_hashes
+            .iter()
+            .zip(blocks.iter())
+            .filter(|(_, block)| block.is_none())
+            .map(|(header_hash, _)| *header_hash)
+            .collect();
+        if missing.is_empty() {
+            return Ok(blocks);
+        }
+
+        let checksums = self.checksums.clone();
+        let read = self
+            .run_read(move |connection| {
+                missing
+                    .iter()
+                    .map(|header_hash| {
+                        read_block(connection, header_hash, checksums.as_deref())
+                            .map(|block| (*header_hash, block))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .await?;
+
+        let mut read = read.into_iter();
+        for block in blocks.iter_mut().filter(|block| block.is_none()) {
+            let (header_hash, block_read) = read.next().expect("one read per missing block");
+            if let Some(block_read) = block_read.as_ref() {
+                self.cache_block(header_hash, block_read);
+            }
+            *block = block_read;
+        }
+        Ok(blocks)
+    }
+
+    pub async fn get_with_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> Result<Option<(Block, BlockInfo<HeaderHash>)>, StorageError> {
+        let started = Instant::now();
+        let res = self
+            .run_read(move |connection| match connection.get_block(&header_hash) {
+                Err(StorageError::BlockNotFound) => Ok(None),
+                Ok(v) => Ok(Some(v)),
+                Err(e) => Err(e),
+            })
+            .await;
+        if let Ok(block) = &res {
+            self.observer
+                .on_get(&header_hash, block.is_some(), started.elapsed());
+        }
+        res
+    }
+
+    /// get the metadata of the block without decoding the block itself
+    pub async fn get_block_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> Result<Option<BlockInfo<HeaderHash>>, StorageError> {
+        self.run_read(
+            move |connection| match connection.get_block_info(&header_hash) {
+                Err(StorageError::BlockNotFound) => Ok(None),
+                Ok(v) => Ok(Some(v)),
+                Err(e) => Err(e),
+            },
+        )
+        .await
+    }
+
+    /// get the depth (the `chain_length` of the storage) of the block,
+    /// `None` if the block is not in the storage. The block itself is not
+    /// read.
+    pub async fn depth_of(&self, header_hash: HeaderHash) -> Result<Option<u64>, StorageError> {
+        self.get_block_info(header_hash)
+            .await
+            .map(|info| info.map(|info| info.chain_length))
+    }
+
+    /// get the ancestor `back` blocks before `from`, `None` if `from` has
+    /// less than `back` ancestors.
+    pub async fn nth_ancestor(
+        &self,
+        from: HeaderHash,
+        back: u64,
+    ) -> Result<Option<(Block, BlockInfo<HeaderHash>)>, StorageError> {
+        self.run_read(move |connection| {
+            let from_info = connection.get_block_info(&from)?;
+            // the block0 is stored with a chain length of 1
+            if back >= from_info.chain_length {
+                return Ok(None);
+            }
+            let info = for_path_to_nth_ancestor(connection, &from, back, |_| {})?;
+            connection.get_block(&info.block_hash).map(Some)
+        })
+        .await
+    }
+
+    /// get the checkpoints to send to a peer for `find_closest_ancestor`:
+    /// the ancestors of `from` 1, 2, 4, 8... blocks back, up to `count`
+    /// checkpoints. The genesis block is always the last checkpoint.
+    pub async fn checkpoints(
+        &self,
+        from: HeaderHash,
+        count: usize,
+    ) -> Result<Vec<HeaderHash>, StorageError> {
+        self.run_read(move |connection| {
+            let mut checkpoints = Vec::with_capacity(count);
+            if count == 0 {
+                return Ok(checkpoints);
+            }
+
+            let from_info = connection.get_block_info(&from)?;
+            // the block0 is stored with a chain length of 1
+            let genesis_distance = from_info.chain_length - 1;
+            let mut current = from_info;
+            let mut distance = 0;
+            let mut next_distance = 1;
+            while checkpoints.len() + 1 < count && next_distance < genesis_distance {
+                current = for_path_to_nth_ancestor(
+                    connection,
+                    &current.block_hash,
+                    next_distance - distance,
+                    |_| {},
+                )?;
+                checkpoints.push(current.block_hash);
+                distance = next_distance;
+                next_distance *= 2;
+            }
+
+            let genesis = for_path_to_nth_ancestor(
+                connection,
+                &current.block_hash,
+                genesis_distance - distance,
+                |_| {},
+            )?;
+            checkpoints.push(genesis.block_hash);
+            Ok(checkpoints)
+        })
+        .await
+    }
+
+    /// get the blocks with the given `chain_length` (the chain length of
+    /// the header, 0 for the block0).
+    ///
+    /// The backend has no index of the blocks by chain length and cannot
+    /// list the blocks of the other branches: only the block of the main
+    /// branch is returned, found by walking back from the HEAD.
+    pub async fn get_by_chain_length(&self, chain_length: u32) -> Result<Vec<Block>, StorageError> {
+        self.run_read(move |connection| {
+            let head = match connection.get_tag(MAIN_BRANCH_TAG)? {
+                None => return Ok(Vec::new()),
+                Some(head) => head,
+            };
+            let head_info = connection.get_block_info(&head)?;
+            // the block0 is stored with a chain length of 1
+            let depth = u64::from(chain_length) + 1;
+            if depth > head_info.chain_length {
+                return Ok(Vec::new());
+            }
+            let info = for_path_to_nth_ancestor(
+                connection,
+                &head,
+                head_info.chain_length - depth,
+                |_| {},
+            )?;
+            connection
+                .get_block(&info.block_hash)
+                .map(|(block, _block_info)| vec![block])
+        })
+        .await
+    }
+
+    pub async fn block_exists(&self, header_hash: HeaderHash) -> Result<bool, StorageError> {
+        let known_absent = self
+            .bloom_filter
+            .read()
+            .unwrap()
+            .as_ref()
+            .map_or(false, |bloom_filter| !bloom_filter.contains(&header_hash));
+        if known_absent {
+            return Ok(false);
+        }
+
+        self.run_read(
+            move |connection| match connection.block_exists(&header_hash) {
+                Err(StorageError::BlockNotFound) => Ok(false),
+                Ok(r) => Ok(r),
+                Err(e) => Err(e),
+            },
+        )
+        .await
+    }
+
+    pub async fn put_block(&self, block: Block) -> Result<(), StorageError> {
+        let _write_lock = self.lock_for_write().await?;
+        let started = Instant::now();
+        let header_hash = block.header.hash();
+        let options = self.write_options();
+        let journal = self.journal();
+        // the block is stored even if it cannot be recorded in the indexes
+        let (stored, res) = self
+            .run(move |connection| {
+                match write_block(connection, journal.as_ref(), &block, &options) {
+                    Ok(()) => Ok((true, record_block(connection, &block, &options))),
+                    Err(StorageError::BlockAlreadyPresent) => {
+                        Ok((true, Err(StorageError::BlockAlreadyPresent)))
+                    }
+                    Err(StorageError::BlockNotFound) => {
+                        Ok((false, Err(StorageError::MissingParent)))
+                    }
+                    Err(e) => Ok((false, Err(e))),
+                }
+            })
+            .await?;
+        if stored {
+            self.bloom_insert(&header_hash);
+            self.observer.on_put(&header_hash, started.elapsed());
+        }
+        res
+    }
+
+    /// Same as `put_block` but a block already present in the storage is not
+    /// an error, the outcome tells whether the block was new.
+    pub async fn put_block_checked(&self, block: Block) -> Result<PutOutcome, StorageError> {
+        match self.put_block(block).await {
+            Ok(()) => Ok(PutOutcome::Inserted),
+            Err(StorageError::BlockAlreadyPresent) => Ok(PutOutcome::AlreadyPresent),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn bloom_insert(&self, header_hash: &HeaderHash) {
+        if let Some(bloom_filter) = self.bloom_filter.write().unwrap().as_mut() {
+            bloom_filter.insert(header_hash);
+        }
+    }
+
+    /// Populate the bloom filter set up with `Storage::with_bloom_filter`
+    /// with all the stored blocks, `block_exists` then answers "no" without
+    /// querying the storage. Does nothing if no filter was set up.
+    ///
+    /// The filter is populated from the list of the stored blocks. A
+    /// storage created before its blocks were listed fails with
+    /// `BlockListIncomplete`: the filter would not know all the stored
+    /// blocks, it is not enabled.
+    pub async fn enable_bloom_filter(&self) -> Result<(), StorageError> {
+        let config = match self.bloom_config {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        // hold the write lock so no block is stored while the filter is
+        // being populated
+        let _write_lock = self.write_lock.lock().await;
+        let block_list = self.block_list.clone();
+        let bloom_filter = self
+            .run(move |connection| {
+                let mut bloom_filter = BloomFilter::new(config);
+                block_list
+                    .lock()
+                    .unwrap()
+                    .for_each(connection, |header_hash| bloom_filter.insert(header_hash))?;
+                Ok(bloom_filter)
+            })
+            .await?;
+        *self.bloom_filter.write().unwrap() = Some(bloom_filter);
+        Ok(())
+    }
+
+    /// Store the block and point the `tag` to it.
+    ///
+    /// Both writes are performed with the same connection while holding
+    /// the write lock so no other write can happen in between. This is not
+    /// atomic: the storage backend exposes neither transactions nor a way
+    /// to remove a block, so if the tag cannot be written (or the node
+    /// stops in between) the block stays in the storage, unreferenced by
+    /// the tag like a block of a side branch.
+    pub async fn put_block_and_tag(&self, block: Block, tag: String) -> Result<(), StorageError> {
+        let _write_lock = self.lock_for_write().await?;
+        let started = Instant::now();
+        let header_hash = block.header.hash();
+        let options = self.write_options();
+        let journal = self.journal();
+        // the block is stored even if it cannot be recorded in the indexes
+        // or the tag cannot be written
+        let (stored, res) = self
+            .run(move |connection| {
+                match write_block(connection, journal.as_ref(), &block, &options) {
+                    Ok(()) | Err(StorageError::BlockAlreadyPresent) => {}
+                    Err(StorageError::BlockNotFound) => {
+                        return Ok((false, Err(StorageError::MissingParent)))
+                    }
+                    Err(e) => return Ok((false, Err(e))),
+                }
+                let res = record_block(connection, &block, &options)
+                    .and_then(|()| connection.put_tag(&tag, &header_hash));
+                Ok((true, res))
+            })
+            .await?;
+        if stored {
+            self.bloom_insert(&header_hash);
+            self.observer.on_put(&header_hash, started.elapsed());
+        }
+        res
+    }
+
+    /// Return values:
+    /// - `Ok(Some(distance))` - `from` is ancestor of `to`, returns the
+    ///   number of blocks between them
+    /// - `Ok(None)` - `from` is not ancestor of `to`
+    /// - `Err(BlockNotFound)` - `from` or `to` was not found
+    /// - `Err(_)` - some other storage error
+    pub async fn distance(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<Option<u64>, StorageError> {
+        self.run_read(move |connection| connection.is_ancestor(&from, &to))
+            .await
+    }
+
+    /// Return values:
+    /// - `Ok(stream)` - `from` is ancestor of `to`, returns blocks between them
+    /// - `Err(CannotIterate)` - `from` is not ancestor of `to`
+    /// - `Err(BlockNotFound)` - `from` or `to` was not found
+    /// - `Err(_)` - some other storage error
+    pub async fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<BlockStream, StorageError> {
+        self.stream_from_to_state(from, to, None).await
+    }
+
+    /// Same as `stream_from_to` but the stream stops after `limit` blocks
+    /// even if `to` has not been reached. The iteration can be resumed
+    /// by using the hash of the last block received as the new `from`.
+    pub async fn stream_from_to_limited(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+        limit: usize,
+    ) -> Result<BlockStream, StorageError> {
+        self.stream_from_to_state(from, to, Some(limit as u64))
+            .await
+    }
+
+    /// Same as `stream_from_to` but only the blocks matching the `predicate`
+    /// are yielded.
+    ///
+    /// This is only a convenience: every block of the branch is still read
+    /// from the storage and decoded before the `predicate` is applied.
+    pub async fn stream_from_to_filtered<F>(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+        predicate: F,
+    ) -> Result<BlockStream, StorageError>
+    where
+        F: Fn(&Block) -> bool + Send + 'static,
+    {
+        let stream = self.stream_from_to_state(from, to, None).await?;
+        Ok(BlockStream::new(
+            stream.try_filter(move |block| future::ready(predicate(block))),
+        ))
+    }
+
+    /// Same as `stream_from_to` but only the headers of the blocks are
+    /// yielded.
+    ///
+    /// The backend stores the blocks whole so each block is still read and
+    /// decoded, the bodies are dropped as soon as the block is read.
+    pub async fn stream_headers_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<impl Stream<Item = Result<Header, StorageError>>, StorageError> {
+        let stream = self.stream_from_to_state(from, to, None).await?;
+        Ok(stream.map_ok(|block| block.header))
+    }
+
+    /// Same as `stream_from_to` but every block comes with its `BlockInfo`.
+    pub async fn stream_from_to_with_info(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<
+        impl Stream<Item = Result<(Block, BlockInfo<HeaderHash>), StorageError>>,
+        StorageError,
+    > {
+        let init_state = self.iter_state_from_to(from, to, None).await?;
+        Ok(init_state.into_stream(self.clone()))
+    }
+
+    async fn stream_from_to_state(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+        limit: Option<u64>,
+    ) -> Result<BlockStream, StorageError> {
+        let init_state = self.iter_state_from_to(from, to, limit).await?;
+        let cursor = BlockCursor {
+            last: from,
+            depth: init_state.cur_length,
+        };
+        Ok(self.block_stream(init_state, cursor, "stream_from_to", to))
+    }
+
+    fn block_stream(
+        &self,
+        init_state: BlockIterState,
+        cursor: BlockCursor,
+        operation: &'static str,
+        to: HeaderHash,
+    ) -> BlockStream {
+        let mut log = self.operation_log(operation, to, init_state.cur_length);
+        BlockStream::new(init_state.into_stream(self.clone()).map_ok(
+            move |(block, _block_info)| {
+                if let Some(log) = log.as_mut() {
+                    log.blocks += 1;
+                }
+                block
+            },
+        ))
+        .with_cursor(cursor)
+    }
+
+    fn operation_log(
+        &self,
+        operation: &'static str,
+        to: HeaderHash,
+        from_depth: u64,
+    ) -> Option<OperationLog> {
+        self.logger.as_ref().map(|logger| OperationLog {
+            logger: logger.clone(),
+            operation,
+            to,
+            from_depth,
+            blocks: 0,
+            started: Instant::now(),
+        })
+    }
+
+    /// Resume a stream of the blocks up to `to` from the position of a
+    /// previous stream. Fails with `CannotIterate` if the cursor is no
+    /// longer on the branch of `to` (e.g. after a reorg).
+    pub async fn resume_stream(
+        &self,
+        cursor: BlockCursor,
+        to: HeaderHash,
+    ) -> Result<BlockStream, StorageError> {
+        let depth = cursor.depth;
+        let init_state = self.iter_state_from_to(cursor.last, to, None).await?;
+        if init_state.cur_length != depth {
+            return Err(StorageError::CannotIterate);
+        }
+        Ok(self.block_stream(init_state, cursor, "resume_stream", to))
+    }
+
+    async fn iter_state_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+        limit: Option<u64>,
+    ) -> Result<BlockIterState, StorageError> {
+        self.run_read(move |connection| match connection.is_ancestor(&from, &to) {
+            Ok(Some(distance)) => match connection.get_block_info(&to) {
+                Ok(to_info) => Ok(BlockIterState::new(to_info, distance).limit(limit)),
+                Err(e) => Err(e),
+            },
+            Ok(None) => Err(StorageError::CannotIterate),
+            Err(e) => Err(e),
+        })
+        .await
+    }
+
+    /// Stream the blocks from `from` back to its ancestor `to`, both
+    /// included, or back to the block0 if `to` is `None`.
+    pub async fn stream_from_to_reversed(
+        &self,
+        from: HeaderHash,
+        to: Option<HeaderHash>,
+    ) -> Result<BlockStream, StorageError> {
+        self.stream_from_to_reversed_limited(from, to, None).await
+    }
+
+    /// Same as `stream_from_to_reversed` but stops after `max_depth` blocks
+    /// even if `to` (or the block0) has not been reached yet.
+    pub async fn stream_from_to_reversed_limited(
+        &self,
+        from: HeaderHash,
+        to: Option<HeaderHash>,
+        max_depth: Option<u64>,
+    ) -> Result<BlockStream, StorageError> {
+        self.run_read(move |connection| match to {
+            Some(to) => match connection.is_ancestor(&to, &from)? {
+                Some(_) => Ok(()),
+                None => Err(StorageError::CannotIterate),
+            },
+            None => connection.get_block_info(&from).map(|_| ()),
+        })
+        .await?;
+
+        let init_state = BlockIterReversedState {
+            next: Some(from),
+            to,
+            remaining: max_depth,
+            buffer: VecDeque::new(),
+            prefetch: self.reversed_prefetch,
+        };
+        let pool = self.pool.clone();
+
+        Ok(BlockStream::new(stream::unfold(
+            (init_state, pool),
+            |(mut state, pool)| async move {
+                if !state.has_next() {
+                    return None;
+                }
+                let res = state.get_next(pool.clone()).await;
+                Some((res, (state, pool)))
+            },
+        )))
+    }
+
+    /// Stream a branch ending at `to` and starting from the ancestor
+    /// at `depth` or at the first ancestor since genesis block
+    /// if `depth` is given as `None`.
+    ///
+    /// The blocks are read from the storage in batches of `send_batch_size`
+    /// blocks, the storage connection is released between each batch so
+    /// a long branch does not starve the other storage queries.
+    pub async fn send_branch<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: Pin<Box<S>>,
+    ) -> Result<(), S::Error>
+    where
+        S: Sink<Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        self.send_branch_map(
+            to,
+            depth,
+            sink,
+            self.send_config(),
+            &CancellationToken::new(),
+            identity,
+        )
+        .await
+    }
+
+    /// Same as `send_branch` but with an explicit `SendConfig` instead of
+    /// the one the storage was configured with.
+    pub async fn send_branch_with_config<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: Pin<Box<S>>,
+        config: SendConfig,
+    ) -> Result<(), S::Error>
+    where
+        S: Sink<Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        self.send_branch_map(to, depth, sink, config, &CancellationToken::new(), identity)
+            .await
+    }
+
+    /// Same as `send_branch` but only the headers of the blocks are sent
+    /// to the sink.
+    pub async fn send_header_branch<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: Pin<Box<S>>,
+    ) -> Result<(), S::Error>
+    where
+        S: Sink<Result<Header, E>>,
+        E: From<StorageError>,
+    {
+        self.send_branch_map(
+            to,
+            depth,
+            sink,
+            self.send_config(),
+            &CancellationToken::new(),
+            |block| block.header,
+        )
+        .await
+    }
+
+    /// Same as `send_branch_with_config` but the sending stops as soon as
+    /// the `cancel` token is cancelled. The blocks already sent are flushed
+    /// and the sink is closed, a cancelled sending is not an error.
+    pub async fn send_branch_cancellable<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: Pin<Box<S>>,
+        config: SendConfig,
+        cancel: CancellationToken,
+    ) -> Result<(), S::Error>
+    where
+        S: Sink<Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        self.send_branch_map(to, depth, sink, config, &cancel, identity)
+            .await
+    }
+
+    fn send_config(&self) -> SendConfig {
+        SendConfig {
+            batch_size: self.send_batch_size,
+            max_blocks: None,
+        }
+    }
+
+    async fn send_branch_map<S, E, T, F>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: Pin<Box<S>>,
+        config: SendConfig,
+        cancel: &CancellationToken,
+        f: F,
+    ) -> Result<(), S::Error>
+    where
+        S: Sink<Result<T, E>>,
+        E: From<StorageError>,
+        F: Fn(Block) -> T,
+    {
+        let mut sink = sink;
+
+        match self.branch_iter_state(to, depth).await {
+            Ok(iter) => {
+                let mut log = self.operation_log("send_branch", to, iter.cur_length);
+                let mut iter = iter.limit(config.max_blocks);
+                'send: while iter.has_next() && !cancel.is_cancelled() {
+                    let batch = iter
+                        .get_next_batch(self.pool.clone(), config.batch_size.get())
+                        .await;
+                    for item in batch {
+                        if cancel.is_cancelled() {
+                            break 'send;
+                        }
+                        if let (Some(log), Ok(_)) = (log.as_mut(), &item) {
+                            log.blocks += 1;
+                        }
+                        let item = item.map(|(block, _block_info)| f(block));
+                        sink.send(item.map_err(Into::into)).await?;
+                    }
+                }
+                sink.close().await?;
+            }
+            Err(e) => {
+                sink.send_all(&mut stream::once(Box::pin(async { Ok(Err(e.into())) })))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Export a branch ending at `to` and starting from the ancestor
+    /// at `depth` (or from the first block after the genesis block if
+    /// `depth` is `None`) to the given `writer`.
+    ///
+    /// The blocks are written one by one in the backup format, from the
+    /// ancestor to the descendant, without loading the whole branch in
+    /// memory.
+    pub async fn export_branch<W>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        writer: W,
+    ) -> Result<(), StorageError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut writer = writer;
+        let mut iter = self.branch_iter_state(to, depth).await?;
+
+        backup::write_header(&mut writer).await?;
+        while iter.has_next() {
+            let batch = iter
+                .get_next_batch(self.pool.clone(), self.send_batch_size.get())
+                .await;
+            for item in batch {
+                let (block, _block_info) = item?;
+                backup::write_block(&mut writer, &block).await?;
+            }
+        }
+        writer.flush().await.map_err(BackupError::from)?;
+
+        Ok(())
+    }
+
+    /// Export the blocks from the block0 up to `up_to`, both included, to a
+    /// new archive file at `path`: the blocks in the format of
+    /// `export_branch`, followed by their checksum. The archive can seed
+    /// the storage of a new node with `import_archive`.
+    ///
+    /// The archive is not compressed. The file is written in the blocking
+    /// thread pool, one batch of blocks at a time.
+    pub async fn export_archive(
+        &self,
+        path: PathBuf,
+        up_to: HeaderHash,
+    ) -> Result<(), StorageError> {
+        let depth = self
+            .depth_of(up_to)
+            .await?
+            .ok_or(StorageError::BlockNotFound)?;
+        // the block0 is stored with a chain length of 1, going `depth`
+        // blocks back from `up_to` includes the block0
+        let mut iter = self.branch_iter_state(up_to, Some(depth)).await?;
+
+        let archive = run_blocking_storage(move || {
+            let file = std::fs::File::create(path).map_err(BackupError::from)?;
+            Ok((
+                AllowStdIo::new(BufWriter::new(file)),
+                backup::Checksum::default(),
+            ))
+        })
+        .await?;
+        let (mut archive, ()) = blocking_archive_io(archive, |(writer, _)| {
+            block_on(backup::write_header(writer))
+        })
+        .await?;
+        while iter.has_next() {
+            let blocks = iter
+                .get_next_batch(self.pool.clone(), self.send_batch_size.get())
+                .await
+                .into_iter()
+                .map(|item| item.map(|(block, _block_info)| block))
+                .collect::<Result<Vec<_>, _>>()?;
+            archive = blocking_archive_io(archive, move |(writer, checksum)| {
+                for block in blocks.iter() {
+                    block_on(backup::write_archive_block(writer, block, checksum))?;
+                }
+                Ok(())
+            })
+            .await?
+            .0;
+        }
+        blocking_archive_io(archive, |(writer, checksum)| {
+            block_on(backup::write_checksum(writer, checksum))?;
+            block_on(writer.flush())?;
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Import the blocks of an archive created with `export_archive`, the
+    /// first block of the archive must be `block0`. Every block must be
+    /// the child of the previous block of the archive, the blocks are not
+    /// validated otherwise: the ledger checks them when loading the
+    /// blockchain from the storage. The file is read in the blocking
+    /// thread pool, one batch of blocks at a time.
+    ///
+    /// The HEAD tag is moved to the last block of the archive unless the
+    /// current HEAD is already further ahead. The checksum of the archive
+    /// is checked before: the blocks of a corrupted archive may be stored
+    /// but they are not on the main branch. Returns the last block of the
+    /// archive.
+    pub async fn import_archive(
+        &self,
+        path: PathBuf,
+        block0: HeaderHash,
+    ) -> Result<HeaderHash, StorageError> {
+        let archive = run_blocking_storage(move || {
+            let file = std::fs::File::open(path).map_err(BackupError::from)?;
+            Ok((
+                AllowStdIo::new(BufReader::new(file)),
+                backup::Checksum::default(),
+            ))
+        })
+        .await?;
+        let (mut archive, ()) =
+            blocking_archive_io(archive, |(reader, _)| block_on(backup::read_header(reader)))
+                .await?;
+
+        let batch_size = self.send_batch_size.get();
+        let mut last = None;
+        let mut depth = 0;
+        loop {
+            let (next, blocks) = blocking_archive_io(archive, move |(reader, checksum)| {
+                let mut blocks = Vec::with_capacity(batch_size);
+                while blocks.len() < batch_size {
+                    match block_on(backup::read_archive_block(reader, checksum))? {
+                        Some(block) => blocks.push(block),
+                        None => break,
+                    }
+                }
+                Ok(blocks)
+            })
+            .await?;
+            archive = next;
+            let is_last_batch = blocks.len() < batch_size;
+
+            for block in blocks {
+                let header_hash = block.header.hash();
+                match last {
+                    None if header_hash != block0 => {
+                        return Err(BackupError::UnexpectedBlock0.into())
+                    }
+                    Some(parent) if block.header.block_parent_hash() != parent => {
+                        return Err(StorageError::MissingParent)
+                    }
+                    _ => {}
+                }
+
+                match self.put_block(block).await {
+                    Ok(()) | Err(StorageError::BlockAlreadyPresent) => {}
+                    Err(e) => return Err(e),
+                }
+                last = Some(header_hash);
+                depth += 1;
+            }
+
+            if is_last_batch {
+                break;
+            }
+        }
+        let last = last.ok_or(BackupError::Empty)?;
+        blocking_archive_io(archive, |(reader, checksum)| {
+            block_on(backup::check_checksum(reader, checksum))
+        })
+        .await?;
+
+        let head = self.get_tag(MAIN_BRANCH_TAG.to_owned()).await?;
+        let head_depth = match head {
+            Some(head) => self.depth_of(head).await?.unwrap_or(0),
+            None => 0,
+        };
+        // the block0 is stored with a chain length of 1, same as `depth`
+        if depth > head_depth {
+            self.put_tag(MAIN_BRANCH_TAG.to_owned(), last).await?;
+        }
+
+        Ok(last)
+    }
+
+    /// Import the blocks of a backup previously created with
+    /// `export_branch`. Returns the number of blocks that were not
+    /// already present in the storage.
+    ///
+    /// The parent of every block must either be already present in the
+    /// storage or be imported earlier from the same backup, otherwise
+    /// the import stops with `CannotIterate`.
+    pub async fn import_stream<R>(&self, reader: R) -> Result<u64, StorageError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut reader = reader;
+        let mut imported = HashSet::new();
+        let mut count = 0;
+
+        backup::read_header(&mut reader).await?;
+        while let Some(block) = backup::read_block(&mut reader).await? {
+            let parent = block.header.block_parent_hash();
+            let is_block0 = u32::from(block.header.chain_length()) == 0;
+
+            if !is_block0 && !imported.contains(&parent) && !self.block_exists(parent).await? {
+                return Err(StorageError::CannotIterate);
+            }
+
+            let header_hash = block.header.hash();
+            match self.put_block(block).await {
+                Ok(()) => count += 1,
+                Err(StorageError::BlockAlreadyPresent) => {}
+                Err(e) => return Err(e),
+            }
+            imported.insert(header_hash);
+        }
+
+        Ok(count)
+    }
+
+    /// the resources used by the storage. The usage is computed at most
+    /// once every `USAGE_CACHE_TTL`, the latest value is returned in the
+    /// meantime.
+    pub async fn usage(&self) -> Result<StorageUsage, StorageError> {
+        if let Some((computed_at, usage)) = self.usage.lock().unwrap().as_ref() {
+            if computed_at.elapsed() < USAGE_CACHE_TTL {
+                return Ok(usage.clone());
+            }
+        }
+
+        let tip_depth = self
+            .run_read(|connection| match connection.get_tag(MAIN_BRANCH_TAG)? {
+                Some(tip) => connection
+                    .get_block_info(&tip)
+                    .map(|info| info.chain_length),
+                None => Ok(0),
+            })
+            .await?;
+
+        // the block being written, if any, is already listed
+        let block_list = self.block_list.clone();
+        let block_count = self
+            .run_read(move |connection| block_list.lock().unwrap().len(connection))
+            .await?;
+
+        let bytes_on_disk = match self.file_path.clone() {
+            None => None,
+            Some(path) => Some(
+                run_blocking_storage(move || {
+                    // the backend may keep a write-ahead log and a shared
+                    // memory index next to the main database file
+                    ["", "-wal", "-shm"]
+                        .iter()
+                        .map(|suffix| {
+                            let mut path = path.clone().into_os_string();
+                            path.push(suffix);
+                            match std::fs::metadata(path) {
+                                Ok(metadata) => Ok(metadata.len()),
+                                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+                                Err(e) => Err(StorageError::BackendError(Box::new(e))),
+                            }
+                        })
+                        .sum::<Result<u64, StorageError>>()
+                })
+                .await?,
+            ),
+        };
+
+        let usage = StorageUsage {
+            bytes_on_disk,
+            block_count,
+            tip_depth,
+        };
+        *self.usage.lock().unwrap() = Some((Instant::now(), usage.clone()));
+        Ok(usage)
+    }
+
+    /// Reclaim the free space of the storage. Compare the `usage` before and
+    /// after to see how much space was reclaimed.
+    ///
+    /// The current backend does not expose a way to vacuum the database,
+    /// this is a no-op: no space is reclaimed.
+    pub async fn compact(&self) -> Result<(), StorageError> {
+        if let Some(logger) = self.logger.as_ref() {
+            debug!(logger, "the storage backend does not support compaction");
+        }
+        Ok(())
+    }
+
+    /// Walk back from the blocks pointed by the given `tags` to the block0
+    /// and check the consistency of every block on the way.
+    ///
+    /// The storage backend does not allow to list the tags, the caller
+    /// needs to provide the tags to verify (e.g. `MAIN_BRANCH_TAG`).
+    /// The problems found are collected in the report, the walk does not
+    /// stop on the first issue. The walk is not bounded by the timeout of
+    /// the reads.
+    pub async fn verify(&self, tags: Vec<String>) -> Result<VerifyReport, StorageError> {
+        self.run_read_with_timeout(None, move |connection| {
+            let mut report = VerifyReport::default();
+            let mut visited = HashSet::new();
+
+            for tag in tags.iter() {
+                let mut current = match connection.get_tag(tag)? {
+                    None => continue,
+                    Some(header_hash) => header_hash,
+                };
+                let mut current_info = match connection.get_block_info(&current) {
+                    Err(StorageError::BlockNotFound) => {
+                        report.dangling_tags.push((tag.clone(), current));
+                        continue;
+                    }
+                    res => res?,
+                };
+
+                while visited.insert(current) {
+                    report.blocks_checked += 1;
+
+                    let (block, _) = connection.get_block(&current)?;
+                    let header_hash = block.header.hash();
+                    if header_hash != current {
+                        report.hash_mismatches.push((current, header_hash));
+                    }
+                    let stored_parent = current_info.parent_id();
+                    let header_parent = block.header.block_parent_hash();
+                    if stored_parent != header_parent {
+                        report
+                            .parent_mismatches
+                            .push((current, stored_parent, header_parent));
+                    }
+
+                    if u32::from(block.header.chain_length()) == 0 {
+                        // reached the block0
+                        break;
+                    }
+
+                    let parent_info = match connection.get_block_info(&stored_parent) {
+                        Err(StorageError::BlockNotFound) => {
+                            report.dangling_parents.push((current, stored_parent));
+                            break;
+                        }
+                        res => res?,
+                    };
+                    if parent_info.chain_length + 1 != current_info.chain_length {
+                        report.depth_discontinuities.push((
+                            current,
+                            current_info.chain_length,
+                            parent_info.chain_length,
+                        ));
+                    }
+
+                    current = stored_parent;
+                    current_info = parent_info;
+                }
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    /// `verify` the main branch, from the HEAD to the block0
+    pub async fn verify_integrity(&self) -> Result<VerifyReport, StorageError> {
+        self.verify(vec![MAIN_BRANCH_TAG.to_owned()]).await
+    }
+
+    async fn branch_iter_state(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+    ) -> Result<BlockIterState, StorageError> {
+        self.run_read(move |connection| {
+            connection.get_block_info(&to).map(|to_info| {
+                let depth = depth.unwrap_or(to_info.chain_length - 1);
+                BlockIterState::new(to_info, depth)
+            })
+        })
+        .await
+    }
+
+    /// find the checkpoint closest to the `descendant`, the checkpoints
+    /// that are not in the storage are reported as unknown. Fails with
+    /// `BlockNotFound` if the `descendant` is not in the storage.
+    pub async fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> Result<ClosestAncestor, StorageError> {
+        if !self.block_exists(descendant).await? {
+            return Err(StorageError::BlockNotFound);
+        }
+        // the blocks are never removed from the storage, the descendant
+        // being present a missing block is the checkpoint
+        closest_ancestor(checkpoints, FIND_ANCESTOR_CONCURRENCY, |checkpoint| {
+            self.run_read(move |connection| connection.is_ancestor(&checkpoint, &descendant))
+        })
+        .await
+    }
+}
+
+impl Storage {
+    pub fn new(storage: NodeStorage) -> Self {
+        Self {
+            inner: Storage03::new(storage),
+        }
+    }
+
+    /// open the storage persisted in the file at `path` without writing to
+    /// it: SQLite opens the file read-only and the write operations fail
+    /// with `StorageReadOnly`. Another node can keep writing to the same
+    /// file without contending for the write lock of the storage.
+    pub fn open_read_only(path: PathBuf) -> Self {
+        let mut storage = Self::new(BlockStore::file(read_only_uri(&path)));
+        storage.inner.read_only = true;
+        storage.with_file_path(path)
+    }
+
+    /// set the number of blocks read from the storage at once when sending
+    /// a branch. Storage backends with a high latency will benefit from
+    /// larger batches.
+    pub fn with_send_batch_size(mut self, send_batch_size: NonZeroUsize) -> Self {
+        self.inner.send_batch_size = send_batch_size;
+        self
+    }
+
+    /// retry on transient errors, according to the given `policy`, every
+    /// read operation sent to the backend through `run_read`: `get`,
+    /// `get_with_info`, `get_block_info`, `block_exists`, the setup of
+    /// `stream_from_to` and the other queries of the storage. Every attempt
+    /// waits for its own read permit and has its own timeout. The blocks of
+    /// a stream, read one at a time, are not retried. Write operations are
+    /// never retried.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        assert!(policy.max_attempts > 0);
+        self.inner.retry_policy = policy;
+        self
+    }
+
+    /// fail the read operations not completed within `timeout` with a
+    /// `StorageTimeout` error. The block streams apply the `timeout` to the
+    /// reading of every block.
+    ///
+    /// The timeouts are not retried by the default `RetryPolicy`, see
+    /// `is_timeout` to retry them.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.inner.timeout = Some(timeout);
+        self
+    }
+
+    /// log the duration and the number of blocks of the `send_branch` and
+    /// `stream_from_to` operations at the debug level
+    pub fn with_logger(mut self, logger: Logger) -> Self {
+        self.inner.logger = Some(logger);
+        self
+    }
+
+    /// run at most `max_concurrent_reads` read operations concurrently, the
+    /// other read operations being queued. The blocks of the streams count
+    /// as read operations, the write operations are never queued.
+    pub fn with_max_concurrent_reads(mut self, max_concurrent_reads: usize) -> Self {
+        self.inner.read_limit = Some(Arc::new(ReadLimit::new(max_concurrent_reads)));
+        self
+    }
+
+    /// number of read operations waiting to run, always 0 if the reads are
+    /// not bounded with `with_max_concurrent_reads`
+    pub fn read_queue_depth(&self) -> usize {
+        self.inner
+            .read_limit
+            .as_ref()
+            .map_or(0, |read_limit| read_limit.queued())
+    }
+
+    /// keep the blocks read with `get` in a cache. The size of a block is
+    /// the size of its serialization, computed when it is cached.
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        self.inner.cache = Some(Arc::new(std::sync::Mutex::new(Cache::new(config))));
+        self
+    }
+
+    /// counters of the block cache, `None` if the cache is not enabled
+    pub fn cache_metrics(&self) -> Option<CacheMetrics> {
+        self.inner.cache_metrics()
+    }
+
+    /// index the fragments of the blocks stored from now on, so they can be
+    /// found with `get_fragment_location`. The index is kept in a file next
+    /// to the file set with `with_file_path`, which has to be called first,
+    /// and is all held in memory once loaded.
+    pub fn with_fragment_index(mut self) -> Self {
+        let fragment_index = FragmentIndex::new(self.inner.file_path.as_deref());
+        self.inner.fragment_index = Some(Arc::new(std::sync::Mutex::new(fragment_index)));
+        self
+    }
+
+    /// index the first block of every epoch of the blocks stored from now
+    /// on, so it can be found with `get_epoch_start`. The index follows
+    /// the main branch when it moves to another branch. The index is kept
+    /// in a file next to the file set with `with_file_path`, which has to
+    /// be called first.
+    pub fn with_epoch_index(mut self) -> Self {
+        let epoch_index = EpochIndex::new(self.inner.file_path.as_deref());
+        self.inner.epoch_index = Some(Arc::new(std::sync::Mutex::new(epoch_index)));
+        self
+    }
+
+    /// record the block being written in a journal next to the file set
+    /// with `with_file_path`, which has to be called first, so `recover`
+    /// can tell on start up whether a write was interrupted. Each block
+    /// write costs the creation, sync and removal of the journal file.
+    pub fn with_journal(mut self) -> Self {
+        self.inner.journal = self.inner.file_path.as_deref().map(Journal::new);
+        self
+    }
+
+    /// keep the checksum of the blocks stored from now on and check it when
+    /// they are read with `get`. A corrupted block is reported with a
+    /// `ChecksumMismatch` error instead of being served. The checksums are
+    /// kept in a file next to the file set with `with_file_path`, which has
+    /// to be called first, and are all held in memory once loaded.
+    pub fn with_checksum(mut self) -> Self {
+        let checksums = Checksums::new(self.inner.file_path.as_deref());
+        self.inner.checksums = Some(Arc::new(std::sync::Mutex::new(checksums)));
+        self
+    }
+
+    /// answer from a bloom filter sized with `config` whether a block is
+    /// stored, when it is not, instead of querying the storage. The filter
+    /// is only used once populated with `enable_bloom_filter`.
+    pub fn with_bloom_filter(mut self, config: BloomConfig) -> Self {
+        self.inner.bloom_config = Some(config);
+        self
+    }
+
+    /// keep the blocks whose parent is missing in a pool, see `add_orphan`
+    pub fn with_orphan_pool(mut self, config: OrphanConfig) -> Self {
+        self.inner.orphans = Some(Arc::new(std::sync::Mutex::new(OrphanPool::new(config))));
+        self
+    }
+
+    pub fn add_orphan(&self, block: Block) {
+        self.inner.add_orphan(block)
+    }
+
+    pub fn resolve_orphans(&self, parent: HeaderHash) -> Vec<Block> {
+        self.inner.resolve_orphans(parent)
+    }
+
+    pub fn metrics(&self) -> StorageMetricsSnapshot {
+        self.inner.metrics()
+    }
+
+    /// set the path of the file the storage is persisted in, this is used
+    /// to report the disk usage of the storage and to keep the list of the
+    /// stored blocks next to it.
+    pub fn with_file_path(mut self, file_path: PathBuf) -> Self {
+        let block_list = BlockList::new(Some(&file_path), self.inner.read_only);
+        self.inner.block_list = Arc::new(std::sync::Mutex::new(block_list));
+        self.inner.file_path = Some(file_path);
+        self
+    }
+
+    pub fn get_tag(
+        &self,
+        tag: String,
+    ) -> impl Future01<Item = Option<HeaderHash>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get_tag(tag).await }))
+    }
+
+    pub fn contains_tag(&self, tag: String) -> impl Future01<Item = bool, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.contains_tag(tag).await }))
+    }
+
+    pub fn put_tag(
+        &self,
+        tag: String,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.put_tag(tag, header_hash).await },
+        ))
+    }
+
+    pub fn recover(&self) -> impl Future01<Item = Recovery, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.recover().await }))
+    }
+
+    pub fn check_version(&self, expected: u32) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.check_version(expected).await }))
+    }
+
+    pub fn put_blocks(
+        &self,
+        blocks: Vec<Block>,
+    ) -> impl Future01<Item = usize, Error = PutBlocksError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.put_blocks(blocks).await }))
+    }
+
+    pub fn put_tags(
+        &self,
+        tags: Vec<(String, HeaderHash)>,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.put_tags(tags).await }))
+    }
+
+    pub fn get(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = Option<Block>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get(header_hash).await }))
+    }
+
+    pub fn get_blocks(
+        &self,
+        header_hashes: Vec<HeaderHash>,
+    ) -> impl Future01<Item = Vec<Option<Block>>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.get_blocks(header_hashes).await },
+        ))
+    }
+
+    pub fn get_with_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = Option<(Block, BlockInfo<HeaderHash>)>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.get_with_info(header_hash).await },
+        ))
+    }
+
+    pub fn get_block_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = Option<BlockInfo<HeaderHash>>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.get_block_info(header_hash).await },
+        ))
+    }
+
+    pub fn depth_of(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = Option<u64>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.depth_of(header_hash).await }))
+    }
+
+    pub fn checkpoints(
+        &self,
+        from: HeaderHash,
+        count: usize,
+    ) -> impl Future01<Item = Vec<HeaderHash>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.checkpoints(from, count).await },
+        ))
+    }
+
+    pub fn nth_ancestor(
+        &self,
+        from: HeaderHash,
+        back: u64,
+    ) -> impl Future01<Item = Option<(Block, BlockInfo<HeaderHash>)>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.nth_ancestor(from, back).await },
+        ))
+    }
+
+    pub fn block_exists(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = bool, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.block_exists(header_hash).await },
+        ))
+    }
+
+    pub fn get_epoch_start(
+        &self,
+        epoch: Epoch,
+    ) -> impl Future01<Item = Option<HeaderHash>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get_epoch_start(epoch).await }))
+    }
+
+    pub fn get_fragment_location(
+        &self,
+        fragment_id: FragmentId,
+    ) -> impl Future01<Item = Option<(HeaderHash, usize)>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.get_fragment_location(fragment_id).await
+        }))
+    }
+
+    pub fn put_block(&self, block: Block) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.put_block(block).await }))
+    }
+
+    pub fn put_block_checked(
+        &self,
+        block: Block,
+    ) -> impl Future01<Item = PutOutcome, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.put_block_checked(block).await },
+        ))
+    }
+
+    pub fn put_block_and_tag(
+        &self,
+        block: Block,
+        tag: String,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.put_block_and_tag(block, tag).await
+        }))
+    }
+
+    pub fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> impl Future01<Item = BlockStream, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.stream_from_to(from, to).await },
+        ))
+    }
+
+    pub fn stream_from_to_filtered<F>(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+        predicate: F,
+    ) -> impl Future01<Item = BlockStream, Error = StorageError>
+    where
+        F: Fn(&Block) -> bool + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.stream_from_to_filtered(from, to, predicate).await
+        }))
+    }
+
+    pub fn stream_from_to_with_info(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> impl Future01<
+        Item = impl Stream01<Item = (Block, BlockInfo<HeaderHash>), Error = StorageError>,
+        Error = StorageError,
+    > {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .stream_from_to_with_info(from, to)
+                .map_ok(|stream| Compat::new(Box::pin(stream)))
+                .await
+        }))
+    }
+
+    pub fn stream_headers_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> impl Future01<Item = impl Stream01<Item = Header, Error = StorageError>, Error = StorageError>
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .stream_headers_from_to(from, to)
+                .map_ok(|stream| Compat::new(Box::pin(stream)))
+                .await
+        }))
+    }
+
+    pub fn stream_from_to_limited(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+        limit: usize,
+    ) -> impl Future01<Item = BlockStream, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.stream_from_to_limited(from, to, limit).await
+        }))
+    }
+
+    pub fn resume_stream(
+        &self,
+        cursor: BlockCursor,
+        to: HeaderHash,
+    ) -> impl Future01<Item = BlockStream, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.resume_stream(cursor, to).await },
+        ))
+    }
+
+    pub fn stream_from_to_reversed(
+        &self,
+        from: HeaderHash,
+        to: Option<HeaderHash>,
+    ) -> impl Future01<Item = BlockStream, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.stream_from_to_reversed(from, to).await
+        }))
+    }
+
+    pub fn stream_from_to_reversed_limited(
+        &self,
+        from: HeaderHash,
+        to: Option<HeaderHash>,
+        max_depth: Option<u64>,
+    ) -> impl Future01<Item = BlockStream, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .stream_from_to_reversed_limited(from, to, max_depth)
+                .await
+        }))
+    }
+
+    pub fn distance(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> impl Future01<Item = Option<u64>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.distance(from, to).await }))
+    }
+
+    pub fn send_branch<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: S,
+    ) -> impl Future01<Item = (), Error = S::SinkError>
+    where
+        S: Sink01<SinkItem = Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .send_branch(to, depth, Box::pin(sink.sink_compat()))
+                .await
+        }))
+    }
+
+    pub fn send_branch_with_config<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: S,
+        config: SendConfig,
+    ) -> impl Future01<Item = (), Error = S::SinkError>
+    where
+        S: Sink01<SinkItem = Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .send_branch_with_config(to, depth, Box::pin(sink.sink_compat()), config)
+                .await
+        }))
+    }
+
+    pub fn send_branch_cancellable<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: S,
+        config: SendConfig,
+        cancel: CancellationToken,
+    ) -> impl Future01<Item = (), Error = S::SinkError>
+    where
+        S: Sink01<SinkItem = Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .send_branch_cancellable(to, depth, Box::pin(sink.sink_compat()), config, cancel)
+                .await
+        }))
+    }
+
+    pub fn send_header_branch<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: S,
+    ) -> impl Future01<Item = (), Error = S::SinkError>
+    where
+        S: Sink01<SinkItem = Result<Header, E>>,
+        E: From<StorageError>,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .send_header_branch(to, depth, Box::pin(sink.sink_compat()))
+                .await
+        }))
+    }
+
+    pub fn export_branch<W>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        writer: W,
+    ) -> impl Future01<Item = (), Error = StorageError>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.export_branch(to, depth, writer).await
+        }))
+    }
+
+    pub fn export_archive(
+        &self,
+        path: PathBuf,
+        up_to: HeaderHash,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.export_archive(path, up_to).await },
+        ))
+    }
+
+    pub fn import_archive(
+        &self,
+        path: PathBuf,
+        block0: HeaderHash,
+    ) -> impl Future01<Item = HeaderHash, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.import_archive(path, block0).await },
+        ))
+    }
+
+    pub fn import_stream<R>(&self, reader: R) -> impl Future01<Item = u64, Error = StorageError>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.import_stream(reader).await }))
+    }
+
+    pub fn usage(&self) -> impl Future01<Item = StorageUsage, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.usage().await }))
+    }
+
+    pub fn enable_bloom_filter(&self) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.enable_bloom_filter().await }))
+    }
+
+    pub fn compact(&self) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.compact().await }))
+    }
+
+    pub fn verify(
+        &self,
+        tags: Vec<String>,
+    ) -> impl Future01<Item = VerifyReport, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.verify(tags).await }))
+    }
+
+    pub fn get_by_chain_length(
+        &self,
+        chain_length: u32,
+    ) -> impl Future01<Item = Vec<Block>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.get_by_chain_length(chain_length).await
+        }))
+    }
+
+    pub fn verify_integrity(&self) -> impl Future01<Item = VerifyReport, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.verify_integrity().await }))
+    }
+
+    pub fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> impl Future01<Item = ClosestAncestor, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.find_closest_ancestor(checkpoints, descendant).await
+        }))
+    }
+}
+
+impl BlockStream {
+    fn new<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Block, StorageError>> + Send + 'static,
+    {
+        BlockStream {
+            inner: Compat::new(Box::pin(stream)),
+            cursor: None,
+        }
+    }
+
+    /// track the position of the stream, starting at `cursor`
+    fn with_cursor(self, cursor: BlockCursor) -> Self {
+        BlockStream {
+            cursor: Some(cursor),
+            ..self
+        }
+    }
+
+    /// the position of the stream, `None` if the stream cannot be resumed
+    /// (only the streams of `stream_from_to` and `stream_from_to_limited`
+    /// can).
+    pub fn cursor(&self) -> Option<BlockCursor> {
+        self.cursor
+    }
+
+    fn advance_cursor(&mut self, block: &Block) {
+        if let Some(cursor) = self.cursor.as_mut() {
+            cursor.last = block.header.hash();
+            // the block0 is stored with a chain length of 1
+            cursor.depth = u64::from(u32::from(block.header.chain_length())) + 1;
+        }
+    }
+}
+
+impl Stream for BlockStream {
+    type Item = Result<Block, StorageError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.get_mut().as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(block))) = &poll {
+            self.advance_cursor(block);
+        }
+        poll
+    }
+}
+
+impl Stream01 for BlockStream {
+    type Item = Block;
+    type Error = StorageError;
+
+    fn poll(&mut self) -> Poll01<Option<Self::Item>, Self::Error> {
+        let poll = self.inner.poll();
+        if let Ok(Async::Ready(Some(block))) = &poll {
+            self.advance_cursor(block);
+        }
+        poll
+    }
+}
+
+/// what is recorded when a block is stored
+#[derive(Clone)]
+struct WriteOptions {
+    fragment_index: Option<Arc<std::sync::Mutex<FragmentIndex>>>,
+    epoch_index: Option<Arc<std::sync::Mutex<EpochIndex>>>,
+    checksums: Option<Arc<std::sync::Mutex<Checksums>>>,
+    block_list: Arc<std::sync::Mutex<BlockList>>,
+}
+
+/// write the block, listed in the block list and recorded in the `journal`
+/// for the duration of the write
+fn write_block(
+    connection: &mut NodeStorageConnection,
+    journal: Option<&Journal>,
+    block: &Block,
+    options: &WriteOptions,
+) -> Result<(), StorageError> {
+    let mut block_list = options.block_list.lock().unwrap();
+    let listed = block_list.push(connection, &block.header.hash())?;
+    let res = journal::put_block(connection, journal, block);
+    if listed && res.is_err() {
+        // best effort, a block listed but not stored only costs a false
+        // positive of the bloom filter
+        let _ = block_list.pop();
+    }
+    res
+}
+
+/// update the indexes and the checksums for a block that has just been
+/// stored
+fn record_block(
+    connection: &mut NodeStorageConnection,
+    block: &Block,
+    options: &WriteOptions,
+) -> Result<(), StorageError> {
+    if let Some(epoch_index) = options.epoch_index.as_ref() {
+        epoch_index.lock().unwrap().index_block(connection, block)?;
+    }
+    if let Some(fragment_index) = options.fragment_index.as_ref() {
+        fragment_index.lock().unwrap().index_block(block)?;
+    }
+    if let Some(checksums) = options.checksums.as_ref() {
+        checksums.lock().unwrap().put(block)?;
+    }
+    Ok(())
+}
+
+/// find the checkpoint closest to the descendant, `probe` giving the
+/// distance of a checkpoint to the descendant or `BlockNotFound` if the
+/// checkpoint is unknown. The checkpoints are probed concurrently but the
+/// result is the one of probing them in order.
+///
+/// The search stops at the first checkpoint that is the descendant itself
+/// since no other checkpoint can be closer. The checkpoints after it are
+/// neither reported as unknown nor checked for errors.
+async fn closest_ancestor<F, Fut>(
+    checkpoints: Vec<HeaderHash>,
+    concurrency: usize,
+    probe: F,
+) -> Result<ClosestAncestor, StorageError>
+where
+    F: Fn(HeaderHash) -> Fut,
+    Fut: Future<Output = Result<Option<u64>, StorageError>>,
+{
+    let mut probes = stream::iter(
+        checkpoints
+            .into_iter()
+            .map(|checkpoint| probe(checkpoint).map(move |res| (checkpoint, res))),
+    )
+    .buffered(concurrency);
+
+    let mut ancestor = None;
+    let mut closest_found = std::u64::MAX;
+    let mut unknown_checkpoints = Vec::new();
+    while let Some((checkpoint, res)) = probes.next().await {
+        match res {
+            Ok(None) => {}
+            Ok(Some(distance)) => {
+                if closest_found > distance {
+                    ancestor = Some(checkpoint);
+                    closest_found = distance;
+                }
+                if distance == 0 {
+                    break;
+                }
+            }
+            // Checkpoints sent by a peer may not be present locally
+            Err(StorageError::BlockNotFound) => unknown_checkpoints.push(checkpoint),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(ClosestAncestor {
+        ancestor: ancestor.map(|header_hash| Ancestor {
+            header_hash,
+            distance: closest_found,
+        }),
+        unknown_checkpoints,
+    })
+}
+
+impl BlockIterState {
+    fn new(to_info: BlockInfo<HeaderHash>, distance: u64) -> Self {
+        BlockIterState {
+            to_length: to_info.chain_length,
+            cur_length: to_info.chain_length - distance,
+            remaining: None,
+            pending_infos: vec![to_info],
+        }
+    }
+
+    /// stop the iteration after `limit` blocks, if any
+    fn limit(self, limit: Option<u64>) -> Self {
+        BlockIterState {
+            remaining: limit,
+            ..self
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        self.cur_length < self.to_length && self.remaining != Some(0)
+    }
+
+    fn into_stream(
+        self,
+        storage: Storage03,
+    ) -> impl Stream<Item = Result<(Block, BlockInfo<HeaderHash>), StorageError>> {
+        stream::unfold((self, storage), |(mut state, storage)| async move {
+            if !state.has_next() {
+                return None;
+            }
+            let started = Instant::now();
+            // on timeout the state is left empty and the stream ends
+            let res = limit::with_read_limit(
+                storage.read_limit.as_deref(),
+                timeout::with_timeout(storage.timeout, state.get_next(storage.pool.clone())),
+            )
+            .await;
+            if let Ok((_block, block_info)) = &res {
+                storage
+                    .observer
+                    .on_stream_block(&block_info.block_hash, started.elapsed());
+            }
+            Some((res, (state, storage)))
+        })
+    }
+
+    async fn get_next(
+        &mut self,
+        pool: Pool<ConnectionManager>,
+    ) -> Result<(Block, BlockInfo<HeaderHash>), StorageError> {
+        self.get_next_batch(pool, 1)
+            .await
+            .pop()
+            .expect("at least one block in the batch")
+    }
+
+    /// Fetch up to `batch_size` blocks using a single storage connection.
+    ///
+    /// The batch stops early after the first error, the error being the
+    /// last element of the returned vector.
+    async fn get_next_batch(
+        &mut self,
+        pool: Pool<ConnectionManager>,
+        batch_size: usize,
+    ) -> Vec<Result<(Block, BlockInfo<HeaderHash>), StorageError>> {
+        assert!(self.has_next());
+
+        let mut state = std::mem::replace(
+            self,
+            BlockIterState {
+                to_length: 0,
+                cur_length: 0,
+                remaining: None,
+                pending_infos: Vec::new(),
+            },
+        );
+
+        let res = run_blocking_with_connection(&pool, move |store| {
+            let mut batch = Vec::with_capacity(batch_size);
+            while state.has_next() && batch.len() < batch_size {
+                let res = state.get_next_blocking(store);
+                let is_err = res.is_err();
+                batch.push(res);
+                if is_err {
+                    break;
+                }
+            }
+            Ok((state, batch))
+        })
+        .await;
+
+        match res {
+            Ok((state, batch)) => {
+                *self = state;
+                batch
+            }
+            Err(e) => vec![Err(e)],
+        }
+    }
+
+    fn get_next_blocking(
+        &mut self,
+        store: &mut NodeStorageConnection,
+    ) -> Result<(Block, BlockInfo<HeaderHash>), StorageError> {
+        self.cur_length += 1;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= 1;
+        }
+
+        let block_info = self.pending_infos.pop().unwrap();
+
+        let cur_depth = self.cur_length;
+
+        if block_info.chain_length == cur_depth {
+            // We've seen this block on a previous ancestor traversal.
+            store.get_block(&block_info.block_hash)
+        } else {
+            // We don't have this block yet, so search back from
+            // the furthest block that we do have.
+            assert!(cur_depth < block_info.chain_length);
+            let depth = block_info.chain_length;
+            let parent = block_info.parent_id();
+            let mut pending_infos = Vec::new();
+            pending_infos.push(block_info);
+            let block_info =
+                for_path_to_nth_ancestor(store, &parent, depth - cur_depth - 1, |new_info| {
+                    pending_infos.push(new_info.clone());
+                })?;
+
+            self.pending_infos.append(&mut pending_infos);
+
+            store.get_block(&block_info.block_hash)
+        }
+    }
+}
+
+impl BlockIterReversedState {
+    fn has_next(&self) -> bool {
+        !self.buffer.is_empty() || (self.next.is_some() && self.remaining != Some(0))
+    }
+
+    async fn get_next(&mut self, pool: Pool<ConnectionManager>) -> Result<Block, StorageError> {
+        if self.buffer.is_empty() {
+            self.fill_buffer(pool).await?;
+        }
+        Ok(self.buffer.pop_front().unwrap())
+    }
+
+    /// read up to `prefetch` blocks back from `next` with a single
+    /// connection. If reading a block fails after some blocks were read,
+    /// the read blocks are kept and the failing block is read again once
+    /// they have been yielded.
+    async fn fill_buffer(&mut self, pool: Pool<ConnectionManager>) -> Result<(), StorageError> {
+        // on error `next` is left empty so the iteration stops
+        let from = self.next.take().unwrap();
+        let to = self.to;
+        let count = match self.remaining {
+            Some(remaining) => std::cmp::min(remaining, self.prefetch as u64) as usize,
+            None => self.prefetch,
+        };
+        let blocks = run_blocking_with_connection(&pool, move |store| {
+            let mut blocks = Vec::with_capacity(count);
+            let mut next = Some(from);
+            while let Some(hash) = next.take() {
+                let block = match store.get_block(&hash) {
+                    Ok((block, _block_info)) => block,
+                    Err(e) if blocks.is_empty() => return Err(e),
+                    Err(_) => break,
+                };
+                let chain_length = u32::from(block.header.chain_length());
+                if !Self::ends_at(to.as_ref(), &hash, chain_length) {
+                    next = Some(block.header.block_parent_hash());
+                }
+                blocks.push(block);
+                if blocks.len() == count {
+                    break;
+                }
+            }
+            Ok(blocks)
+        })
+        .await?;
+
+        for block in blocks {
+            self.advance(
+                block.header.hash(),
+                block.header.block_parent_hash(),
+                u32::from(block.header.chain_length()),
+            );
+            self.buffer.push_back(block);
+        }
+        Ok(())
+    }
+
+    /// move to the parent of the block that has just been yielded, unless
+    /// it was the last one to yield
+    fn advance(&mut self, hash: HeaderHash, parent: HeaderHash, chain_length: u32) {
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+        self.next = if self.is_last(&hash, chain_length) {
+            None
+        } else {
+            Some(parent)
+        };
+    }
+
+    /// the iteration ends once `to` or the block0 has been yielded. The
+    /// `chain_length` is the one of the block's header, 0 for the block0
+    /// (not the chain length of the storage which starts at 1).
+    fn is_last(&self, hash: &HeaderHash, chain_length: u32) -> bool {
+        Self::ends_at(self.to.as_ref(), hash, chain_length)
+    }
+
+    fn ends_at(to: Option<&HeaderHash>, hash: &HeaderHash, chain_length: u32) -> bool {
+        to == Some(hash) || chain_length == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockDate, BlockVersion, Contents, HeaderBuilderNew};
+
+    fn hash(n: u8) -> HeaderHash {
+        HeaderHash::hash_bytes(&[n])
+    }
+
+    /// an empty block at the given date, a block0 without `parent`
+    fn block(parent: Option<&Block>, epoch: u32, slot_id: u32) -> Block {
+        let contents = Contents::empty();
+        let builder = HeaderBuilderNew::new(BlockVersion::Genesis, &contents);
+        let builder = match parent {
+            None => builder.set_genesis(),
+            Some(parent) => builder.set_parent(
+                &parent.header.hash(),
+                parent.header.chain_length().increase(),
+            ),
+        };
+        let header = builder
+            .set_date(BlockDate { epoch, slot_id })
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    /// a chain of `length` empty blocks, starting with a block0
+    fn chain(length: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(length as usize);
+        for slot_id in 0..length {
+            let next = block(blocks.last(), 0, slot_id);
+            blocks.push(next);
+        }
+        blocks
+    }
+
+    #[test]
+    fn send_long_branch_in_batches() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(5000);
+        let block0 = blocks[0].header.hash();
+        let tip = blocks.last().unwrap().header.hash();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            let (sender, receiver) = futures03::channel::mpsc::channel(1);
+            let config = SendConfig {
+                batch_size: NonZeroUsize::new(32).unwrap(),
+                max_blocks: None,
+            };
+            let send = {
+                let storage = storage.clone();
+                tokio02::spawn(async move {
+                    storage
+                        .send_branch_with_config(tip, None, Box::pin(sender), config)
+                        .await
+                })
+            };
+
+            // the sending waits for the receiver, the storage still serves
+            // the other reads
+            assert!(storage.get(block0).await.unwrap().is_some());
+
+            let received: Vec<Result<Block, StorageError>> = receiver.collect().await;
+            send.await.unwrap().unwrap();
+            // the block0 is not sent
+            assert_eq!(received.len(), 4999);
+            assert!(received.iter().all(Result::is_ok));
+        })
+    }
+
+    #[test]
+    fn cancel_send_branch_partway() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(1000);
+        let tip = blocks.last().unwrap().header.hash();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            let (sender, mut receiver) = futures03::channel::mpsc::channel(1);
+            let cancel = CancellationToken::new();
+            let send = {
+                let storage = storage.clone();
+                let cancel = cancel.clone();
+                tokio02::spawn(async move {
+                    storage
+                        .send_branch_cancellable::<_, StorageError>(
+                            tip,
+                            None,
+                            Box::pin(sender),
+                            SendConfig::default(),
+                            cancel,
+                        )
+                        .await
+                })
+            };
+
+            for _ in 0..10 {
+                assert!(receiver.next().await.unwrap().is_ok());
+            }
+            cancel.cancel();
+            // the stream ends once the sink is closed
+            let rest: Vec<Result<Block, StorageError>> = receiver.collect().await;
+            send.await.unwrap().unwrap();
+            assert!(rest.iter().all(Result::is_ok));
+            // only the blocks buffered in the channel (one per sender plus
+            // one) and the block being sent are received after the cancel
+            assert!(rest.len() <= 3);
+        })
+    }
+
+    #[test]
+    fn archive_checksum_is_checked() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let blocks = chain(100);
+        let block0 = blocks[0].header.hash();
+        let tip = blocks.last().unwrap().header.hash();
+        let path = std::env::temp_dir().join(format!("jormungandr-archive-{}", tip));
+
+        rt.block_on_std(async move {
+            let storage = Storage::new(BlockStore::memory()).inner;
+            storage.put_blocks(blocks).await.unwrap();
+            storage.export_archive(path.clone(), tip).await.unwrap();
+
+            let imported = Storage::new(BlockStore::memory()).inner;
+            let last = imported.import_archive(path.clone(), block0).await;
+            assert_eq!(last.unwrap(), tip);
+
+            let mut bytes = std::fs::read(&path).unwrap();
+            *bytes.last_mut().unwrap() ^= 1;
+            std::fs::write(&path, bytes).unwrap();
+            let corrupted = Storage::new(BlockStore::memory()).inner;
+            let error = corrupted.import_archive(path.clone(), block0).await;
+            std::fs::remove_file(&path).unwrap();
+            let error = match error {
+                Err(StorageError::BackendError(error)) => error,
+                _ => panic!("the corrupted archive was imported"),
+            };
+            match error.downcast_ref::<BackupError>() {
+                Some(BackupError::ChecksumMismatch) => {}
+                _ => panic!("unexpected error: {}", error),
+            }
+            assert_eq!(
+                corrupted.get_tag(MAIN_BRANCH_TAG.to_owned()).await.unwrap(),
+                None
+            );
+        })
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let blocks = chain(100);
+        let block0 = blocks[0].clone();
+        let block0_hash = block0.header.hash();
+        let tip = blocks.last().unwrap().header.hash();
+        let hashes = |blocks: Vec<Block>| -> Vec<HeaderHash> {
+            blocks.iter().map(|block| block.header.hash()).collect()
+        };
+
+        rt.block_on_std(async move {
+            let storage = Storage::new(BlockStore::memory()).inner;
+            storage.put_blocks(blocks).await.unwrap();
+            let mut backup = Vec::new();
+            storage.export_branch(tip, None, &mut backup).await.unwrap();
+            let exported: Vec<Block> = storage
+                .stream_from_to(block0_hash, tip)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert!(!exported.is_empty());
+
+            // a new storage with only the block0, the branch does not
+            // include it
+            let imported = Storage::new(BlockStore::memory()).inner;
+            imported.put_block(block0).await.unwrap();
+            let count = imported.import_stream(&backup[..]).await.unwrap();
+            assert_eq!(count, 99);
+            let streamed: Vec<Block> = imported
+                .stream_from_to(block0_hash, tip)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(hashes(streamed), hashes(exported));
+        })
+    }
+
+    #[test]
+    fn closest_ancestor_of_a_missing_descendant() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(10);
+        let checkpoints = vec![blocks[2].header.hash(), hash(0)];
+        let descendant = blocks[8].header.hash();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+
+            let closest = storage
+                .find_closest_ancestor(checkpoints.clone(), descendant)
+                .await
+                .unwrap();
+            let ancestor = closest.ancestor.unwrap();
+            assert_eq!(ancestor.header_hash, checkpoints[0]);
+            assert_eq!(ancestor.distance, 6);
+            assert_eq!(closest.unknown_checkpoints, vec![hash(0)]);
+
+            match storage.find_closest_ancestor(checkpoints, hash(1)).await {
+                Err(StorageError::BlockNotFound) => {}
+                _ => panic!("the missing descendant is not reported"),
+            }
+        })
+    }
+
+    #[test]
+    fn get_blocks_uses_the_cache() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory())
+            .with_cache(CacheConfig::with_max_bytes(1 << 20))
+            .with_checksum()
+            .inner;
+        let blocks = chain(3);
+        let hashes: Vec<HeaderHash> = blocks.iter().map(|block| block.header.hash()).collect();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            storage.get(hashes[1]).await.unwrap().unwrap();
+            let hits = storage.cache_metrics().unwrap().hits;
+
+            let got = storage
+                .get_blocks(vec![hashes[0], hash(0), hashes[1], hashes[2]])
+                .await
+                .unwrap();
+            let got: Vec<Option<HeaderHash>> = got
+                .iter()
+                .map(|block| block.as_ref().map(|block| block.header.hash()))
+                .collect();
+            assert_eq!(
+                got,
+                vec![Some(hashes[0]), None, Some(hashes[1]), Some(hashes[2])]
+            );
+            assert_eq!(storage.cache_metrics().unwrap().hits, hits + 1);
+
+            // the blocks read are cached
+            storage
+                .get_blocks(vec![hashes[0], hashes[2]])
+                .await
+                .unwrap();
+            assert_eq!(storage.cache_metrics().unwrap().hits, hits + 3);
+        })
+    }
+
+    #[test]
+    fn moving_a_tag_does_not_serve_a_cached_block() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory())
+            .with_cache(CacheConfig::with_max_bytes(1 << 20))
+            .inner;
+        let blocks = chain(3);
+        let hashes: Vec<HeaderHash> = blocks.iter().map(|block| block.header.hash()).collect();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            storage.put_tag("head".to_owned(), hashes[1]).await.unwrap();
+            let tagged = storage.get_tag("head".to_owned()).await.unwrap().unwrap();
+            storage.get(tagged).await.unwrap().unwrap();
+            storage.get(tagged).await.unwrap().unwrap();
+            let metrics = storage.cache_metrics().unwrap();
+            assert_eq!(metrics.insertions, 1);
+            assert_eq!(metrics.hits, 1);
+
+            // the tags are not cached, the block under the moved tag is read
+            // from the storage
+            storage.put_tag("head".to_owned(), hashes[2]).await.unwrap();
+            let tagged = storage.get_tag("head".to_owned()).await.unwrap().unwrap();
+            assert_eq!(tagged, hashes[2]);
+            let block = storage.get(tagged).await.unwrap().unwrap();
+            assert_eq!(block.header.hash(), hashes[2]);
+            let metrics = storage.cache_metrics().unwrap();
+            assert_eq!(metrics.insertions, 2);
+            assert_eq!(metrics.hits, 1);
+        })
+    }
+
+    #[test]
+    fn checksums_are_kept_next_to_the_storage_file() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let blocks = chain(3);
+        let hashes: Vec<HeaderHash> = blocks.iter().map(|block| block.header.hash()).collect();
+        let path = std::env::temp_dir().join(format!("jormungandr-checksums-{}", hashes[2]));
+        let checksums_path = PathBuf::from(format!("{}.checksums", path.display()));
+        let mut storage = Storage::new(BlockStore::memory())
+            .with_file_path(path.clone())
+            .with_checksum()
+            .inner;
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+
+            // corrupt the checksum of the last block and reload the file
+            let mut bytes = std::fs::read(&checksums_path).unwrap();
+            *bytes.last_mut().unwrap() ^= 1;
+            std::fs::write(&checksums_path, bytes).unwrap();
+            let checksums = Checksums::new(Some(&path));
+            storage.checksums = Some(Arc::new(std::sync::Mutex::new(checksums)));
+
+            let valid = storage.get(hashes[0]).await;
+            let corrupted = storage.get(hashes[2]).await;
+            std::fs::remove_file(&checksums_path).unwrap();
+            std::fs::remove_file(format!("{}.blocks", path.display())).unwrap();
+            assert!(valid.unwrap().is_some());
+            let error = match corrupted {
+                Err(StorageError::BackendError(error)) => error,
+                _ => panic!("the checksum was not checked"),
+            };
+            assert!(error.downcast_ref::<ChecksumMismatch>().is_some());
+        })
+    }
+
+    #[test]
+    fn bloom_filter_knows_the_side_branches() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let config = BloomConfig {
+            capacity: 100,
+            false_positive_rate: 0.01,
+        };
+        let storage = Storage::new(BlockStore::memory())
+            .with_bloom_filter(config)
+            .inner;
+        let blocks = chain(3);
+        let tip = blocks[2].header.hash();
+        let side = block(Some(&blocks[1]), 0, 5);
+        let side_hash = side.header.hash();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            storage.put_block(side).await.unwrap();
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), tip)
+                .await
+                .unwrap();
+            storage.enable_bloom_filter().await.unwrap();
+
+            assert!(storage.block_exists(side_hash).await.unwrap());
+            assert!(storage.block_exists(tip).await.unwrap());
+            assert!(!storage.block_exists(hash(0)).await.unwrap());
+        })
+    }
+
+    #[test]
+    fn usage_counts_the_side_branches() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(3);
+        let tip = blocks[2].header.hash();
+        let side = block(Some(&blocks[1]), 0, 5);
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            storage.put_block(side).await.unwrap();
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), tip)
+                .await
+                .unwrap();
+
+            let usage = storage.usage().await.unwrap();
+            assert_eq!(usage.block_count, Some(4));
+            assert_eq!(usage.tip_depth, 3);
+        })
+    }
+
+    #[test]
+    fn block_list_drops_an_interrupted_write() {
+        use std::io::Write as _;
+
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let blocks = chain(3);
+        let block0 = blocks[0].clone();
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-block-list-{}",
+            blocks[2].header.hash()
+        ));
+        let blocks_path = PathBuf::from(format!("{}.blocks", path.display()));
+        let storage = Storage::new(BlockStore::memory())
+            .with_file_path(path.clone())
+            .inner;
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            // a block already stored is not listed again
+            let outcome = storage.put_block_checked(block0).await.unwrap();
+            assert_eq!(outcome, PutOutcome::AlreadyPresent);
+            // the record of a block whose write was interrupted by a crash
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&blocks_path)
+                .unwrap();
+            file.write_all(hash(0).as_ref()).unwrap();
+
+            let len = storage
+                .run(move |connection| BlockList::new(Some(&path), false).len(connection))
+                .await;
+            std::fs::remove_file(&blocks_path).unwrap();
+            assert_eq!(len.unwrap(), Some(3));
+        })
+    }
+
+    #[test]
+    fn compact_without_backend_support_is_a_no_op() {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(2);
+        let tip = blocks[1].header.hash();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            storage.compact().await.unwrap();
+            assert!(storage.get(tip).await.unwrap().is_some());
+        })
+    }
+
+    #[test]
+    fn put_blocks_reports_the_blocks_stored_before_a_failure() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let mut blocks = chain(4);
+        let block0_hash = blocks[0].header.hash();
+        // the parent of the last block is missing from the batch
+        blocks.remove(2);
+
+        rt.block_on_std(async move {
+            let error = storage.put_blocks(blocks.clone()).await.unwrap_err();
+            assert_eq!(error.stored, 2);
+            match error.source {
+                StorageError::MissingParent => {}
+                _ => panic!("the missing parent is not reported"),
+            }
+            assert!(storage.block_exists(block0_hash).await.unwrap());
+
+            let stored = storage.put_blocks(blocks[..2].to_vec()).await.unwrap();
+            assert_eq!(stored, 2);
+        })
+    }
+
+    #[test]
+    fn put_block_and_tag_points_the_tag_to_the_block() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(2);
+        let block0 = blocks[0].clone();
+        let block0_hash = block0.header.hash();
+        let next = blocks[1].clone();
+        let next_hash = next.header.hash();
+
+        rt.block_on_std(async move {
+            storage
+                .put_block_and_tag(block0.clone(), MAIN_BRANCH_TAG.to_owned())
+                .await
+                .unwrap();
+            storage
+                .put_block_and_tag(next, MAIN_BRANCH_TAG.to_owned())
+                .await
+                .unwrap();
+            let tip = storage.get_tag(MAIN_BRANCH_TAG.to_owned()).await.unwrap();
+            assert_eq!(tip, Some(next_hash));
+
+            // a block already stored is tagged all the same
+            storage
+                .put_block_and_tag(block0, MAIN_BRANCH_TAG.to_owned())
+                .await
+                .unwrap();
+            let tip = storage.get_tag(MAIN_BRANCH_TAG.to_owned()).await.unwrap();
+            assert_eq!(tip, Some(block0_hash));
+        })
+    }
+
+    #[test]
+    fn fragment_location_without_index_fails() {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+
+        rt.block_on_std(async move {
+            let error = storage.get_fragment_location(hash(0)).await.unwrap_err();
+            assert!(is_fragment_index_disabled(&error));
+        })
+    }
+
+    #[test]
+    fn epoch_index_follows_the_main_branch() {
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).with_epoch_index().inner;
+        let block0 = block(None, 0, 0);
+        let common = block(Some(&block0), 0, 1);
+        // the chain forks across the boundary of the epoch 1
+        let lost = block(Some(&common), 1, 0);
+        let main_parent = block(Some(&common), 0, 2);
+        let main = block(Some(&main_parent), 1, 1);
+        let (lost_hash, main_hash) = (lost.header.hash(), main.header.hash());
+        let block0_hash = block0.header.hash();
+
+        rt.block_on_std(async move {
+            storage
+                .put_blocks(vec![block0, common, lost])
+                .await
+                .unwrap();
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), lost_hash)
+                .await
+                .unwrap();
+            assert_eq!(storage.get_epoch_start(1).await.unwrap(), Some(lost_hash));
+
+            storage.put_blocks(vec![main_parent, main]).await.unwrap();
+            assert_eq!(storage.get_epoch_start(1).await.unwrap(), Some(lost_hash));
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), main_hash)
+                .await
+                .unwrap();
+            assert_eq!(storage.get_epoch_start(1).await.unwrap(), Some(main_hash));
+            assert_eq!(storage.get_epoch_start(0).await.unwrap(), Some(block0_hash));
+        })
+    }
+
+    #[test]
+    fn read_only_uri_escapes_the_path() {
+        assert_eq!(
+            read_only_uri(Path::new("/var/lib/node/blocks.sqlite")),
+            PathBuf::from("file:/var/lib/node/blocks.sqlite?mode=ro")
+        );
+        assert_eq!(
+            read_only_uri(Path::new("/tmp/a?b#c%d/blocks.sqlite")),
+            PathBuf::from("file:/tmp/a%3fb%23c%25d/blocks.sqlite?mode=ro")
+        );
+    }
+
+    /// walk back a chain of `length` blocks from its tip, returning the
+    /// chain lengths of the yielded blocks
+    fn walk_reversed(length: u8, to: Option<u8>, max_depth: Option<u64>) -> Vec<u8> {
+        let mut state = BlockIterReversedState {
+            next: Some(hash(length - 1)),
+            to: to.map(hash),
+            remaining: max_depth,
+            buffer: VecDeque::new(),
+            prefetch: DEFAULT_REVERSED_PREFETCH,
+        };
+        let mut yielded = Vec::new();
+        while state.has_next() {
+            let current = (0..length).find(|n| state.next == Some(hash(*n))).unwrap();
+            yielded.push(current);
+            state.next = None;
+            state.advance(hash(current), hash(current.wrapping_sub(1)), current as u32);
+        }
+        yielded
+    }
+
+    #[test]
+    fn reversed_limit_larger_than_chain_stops_at_block0() {
+        assert_eq!(walk_reversed(4, None, Some(10)), vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn reversed_limit_stops_early() {
+        assert_eq!(walk_reversed(4, None, Some(2)), vec![3, 2]);
+        assert_eq!(walk_reversed(4, Some(0), Some(2)), vec![3, 2]);
+        assert_eq!(walk_reversed(4, None, Some(0)), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn reversed_stops_at_to() {
+        assert_eq!(walk_reversed(4, Some(1), None), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn reversed_to_block0_includes_block0() {
+        assert_eq!(walk_reversed(4, Some(0), None), vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn reversed_without_to_stops_at_block0() {
+        assert_eq!(walk_reversed(4, None, None), vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn reversed_single_block_chain() {
+        assert_eq!(walk_reversed(1, None, None), vec![0]);
+        assert_eq!(walk_reversed(1, Some(0), None), vec![0]);
+    }
+
+    /// stream back a chain of `length` blocks stored in memory from its
+    /// tip, returning the chain lengths of the yielded blocks
+    fn stream_reversed(
+        length: u32,
+        to: Option<usize>,
+        max_depth: Option<u64>,
+        prefetch: usize,
+    ) -> Vec<u32> {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        let mut storage = Storage::new(BlockStore::memory()).inner;
+        storage.reversed_prefetch = prefetch;
+        let blocks = chain(length);
+        let hashes: Vec<HeaderHash> = blocks.iter().map(|block| block.header.hash()).collect();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+            let streamed: Vec<Block> = storage
+                .stream_from_to_reversed_limited(
+                    *hashes.last().unwrap(),
+                    to.map(|n| hashes[n]),
+                    max_depth,
+                )
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            streamed
+                .iter()
+                .map(|block| u32::from(block.header.chain_length()))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn stream_reversed_across_prefetches() {
+        let all: Vec<u32> = (0..10).rev().collect();
+        assert_eq!(stream_reversed(10, None, None, 3), all);
+        assert_eq!(stream_reversed(10, Some(0), None, 3), all);
+        assert_eq!(stream_reversed(10, None, None, 64), all);
+    }
+
+    #[test]
+    fn stream_reversed_stops_within_a_prefetch() {
+        assert_eq!(
+            stream_reversed(10, Some(4), None, 3),
+            vec![9, 8, 7, 6, 5, 4]
+        );
+        assert_eq!(stream_reversed(10, None, Some(5), 3), vec![9, 8, 7, 6, 5]);
+        assert_eq!(stream_reversed(10, Some(0), Some(0), 3), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn stream_reversed_single_block_chain() {
+        assert_eq!(stream_reversed(1, None, None, 3), vec![0]);
+        assert_eq!(stream_reversed(1, Some(0), None, 3), vec![0]);
+    }
+
+    /// `find_closest_ancestor` as it was before the checkpoints were
+    /// probed concurrently
+    fn find_closest_ancestor_sequential(
+        connection: &mut NodeStorageConnection,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> Result<Option<Ancestor>, StorageError> {
+        let mut ancestor = None;
+        let mut closest_found = std::u64::MAX;
+        for checkpoint in checkpoints {
+            match connection.is_ancestor(&checkpoint, &descendant) {
+                Ok(None) => {}
+                Ok(Some(distance)) => {
+                    if closest_found > distance {
+                        ancestor = Some(checkpoint);
+                        closest_found = distance;
+                    }
+                }
+                Err(StorageError::BlockNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ancestor.map(|header_hash| Ancestor {
+            header_hash,
+            distance: closest_found,
+        }))
+    }
+
+    #[test]
+    fn concurrent_closest_ancestor_matches_sequential() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let mut rt = runtime::Builder::new().core_threads(2).build().unwrap();
+        let storage = Storage::new(BlockStore::memory()).inner;
+        let blocks = chain(50);
+        let hashes: Vec<HeaderHash> = blocks.iter().map(|block| block.header.hash()).collect();
+
+        let cases: Vec<(Vec<HeaderHash>, HeaderHash)> = (0..200)
+            .map(|_| {
+                let len = rng.gen_range(0, 20);
+                let checkpoints = (0..len)
+                    .map(|_| match rng.gen_range(0, 4) {
+                        0 => hash(rng.gen()),
+                        _ => hashes[rng.gen_range(0, hashes.len())],
+                    })
+                    .collect();
+                (checkpoints, hashes[rng.gen_range(0, hashes.len())])
+            })
+            .collect();
+
+        rt.block_on_std(async move {
+            storage.put_blocks(blocks).await.unwrap();
+
+            for (checkpoints, descendant) in cases {
+                let sequential = {
+                    let checkpoints = checkpoints.clone();
+                    storage
+                        .run(move |connection| {
+                            find_closest_ancestor_sequential(connection, checkpoints, descendant)
+                        })
+                        .await
+                        .unwrap()
+                };
+                let concurrent = storage
+                    .find_closest_ancestor(checkpoints.clone(), descendant)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    concurrent.ancestor.map(|a| (a.header_hash, a.distance)),
+                    sequential.map(|a| (a.header_hash, a.distance))
+                );
+
+                // the search stops at the descendant itself
+                let probed = match checkpoints.iter().position(|c| *c == descendant) {
+                    Some(position) => &checkpoints[..=position],
+                    None => &checkpoints[..],
+                };
+                let unknown: Vec<HeaderHash> = probed
+                    .iter()
+                    .filter(|checkpoint| !hashes.contains(checkpoint))
+                    .cloned()
+                    .collect();
+                assert_eq!(concurrent.unknown_checkpoints, unknown);
+            }
+        })
+    }
+}
@@ -0,0 +1,18 @@
+use crate::blockcfg::HeaderHash;
+use std::time::Duration;
+
+/// hook called on the storage operations, allows to collect metrics
+/// about the storage. All the methods do nothing by default.
+///
+/// The hooks are called from the storage tasks, they should return
+/// quickly.
+pub trait StorageObserver: Send + Sync {
+    /// a block has been looked up, `found` tells whether it was present
+    fn on_get(&self, _header_hash: &HeaderHash, _found: bool, _duration: Duration) {}
+
+    /// a block has been written, or was already present
+    fn on_put(&self, _header_hash: &HeaderHash, _duration: Duration) {}
+
+    /// a block has been read by a block stream
+    fn on_stream_block(&self, _header_hash: &HeaderHash, _duration: Duration) {}
+}
@@ -0,0 +1,131 @@
+//! pool of the blocks whose parent is not in the storage yet
+//!
+//! The blocks wait in the pool until their parent is stored, they can
+//! then be stored without being downloaded again.
+
+use crate::blockcfg::HeaderHash;
+use linked_hash_map::LinkedHashMap;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct OrphanConfig {
+    /// maximum number of blocks in the pool, the oldest blocks are evicted
+    /// first
+    pub capacity: usize,
+    /// evict the blocks inserted longer ago than `ttl`
+    pub ttl: Duration,
+}
+
+pub struct OrphanPool<V> {
+    config: OrphanConfig,
+    // in insertion order: `(parent, block, inserted at)`
+    entries: LinkedHashMap<HeaderHash, (HeaderHash, V, Instant)>,
+    children: HashMap<HeaderHash, Vec<HeaderHash>>,
+}
+
+impl<V> OrphanPool<V> {
+    pub fn new(config: OrphanConfig) -> Self {
+        assert!(config.capacity > 0);
+        OrphanPool {
+            config,
+            entries: LinkedHashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    /// add the block `header_hash` whose parent is `parent`, a block
+    /// already in the pool is left as is
+    pub fn insert(&mut self, header_hash: HeaderHash, parent: HeaderHash, value: V) {
+        self.purge_expired();
+        if self.entries.contains_key(&header_hash) {
+            return;
+        }
+        self.entries
+            .insert(header_hash, (parent, value, Instant::now()));
+        self.children.entry(parent).or_default().push(header_hash);
+
+        while self.entries.len() > self.config.capacity {
+            if let Some((header_hash, (parent, _, _))) = self.entries.pop_front() {
+                self.forget_child(&parent, &header_hash);
+            }
+        }
+    }
+
+    /// remove and return the descendants of `parent` in the pool, each
+    /// block coming after its parent
+    pub fn resolve(&mut self, parent: &HeaderHash) -> Vec<V> {
+        self.purge_expired();
+        let mut resolved = Vec::new();
+        let mut parents = vec![*parent];
+        while let Some(parent) = parents.pop() {
+            for header_hash in self.children.remove(&parent).unwrap_or_default() {
+                if let Some((_, value, _)) = self.entries.remove(&header_hash) {
+                    resolved.push(value);
+                    parents.push(header_hash);
+                }
+            }
+        }
+        resolved
+    }
+
+    fn purge_expired(&mut self) {
+        loop {
+            match self.entries.front() {
+                Some((_, (_, _, inserted_at))) if inserted_at.elapsed() > self.config.ttl => {}
+                _ => break,
+            }
+            if let Some((header_hash, (parent, _, _))) = self.entries.pop_front() {
+                self.forget_child(&parent, &header_hash);
+            }
+        }
+    }
+
+    fn forget_child(&mut self, parent: &HeaderHash, header_hash: &HeaderHash) {
+        if let Some(children) = self.children.get_mut(parent) {
+            children.retain(|child| child != header_hash);
+            if children.is_empty() {
+                self.children.remove(parent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u8) -> HeaderHash {
+        HeaderHash::hash_bytes(&[n])
+    }
+
+    fn pool(capacity: usize, ttl: Duration) -> OrphanPool<u8> {
+        OrphanPool::new(OrphanConfig { capacity, ttl })
+    }
+
+    #[test]
+    fn resolves_descendants_after_their_parent() {
+        let mut pool = pool(10, Duration::from_secs(60));
+        pool.insert(hash(3), hash(2), 3);
+        pool.insert(hash(2), hash(1), 2);
+        pool.insert(hash(5), hash(4), 5);
+
+        assert_eq!(pool.resolve(&hash(1)), vec![2, 3]);
+        assert_eq!(pool.resolve(&hash(1)), Vec::<u8>::new());
+        assert_eq!(pool.resolve(&hash(4)), vec![5]);
+    }
+
+    #[test]
+    fn evicts_oldest_and_expired() {
+        let mut pool = pool(2, Duration::from_secs(60));
+        pool.insert(hash(1), hash(0), 1);
+        pool.insert(hash(2), hash(0), 2);
+        pool.insert(hash(3), hash(0), 3);
+        assert_eq!(pool.resolve(&hash(0)), vec![2, 3]);
+
+        let mut pool = self::pool(2, Duration::from_secs(0));
+        pool.insert(hash(1), hash(0), 1);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(pool.resolve(&hash(0)), Vec::<u8>::new());
+    }
+}
@@ -0,0 +1,76 @@
+use super::{is_timeout, StorageError};
+use futures03::Future;
+use std::time::{Duration, Instant};
+use tokio02::time::delay_for;
+
+/// retry policy of the storage read operations. Write operations are
+/// never retried.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// no new attempt is made past this duration since the first attempt
+    pub max_duration: Duration,
+    /// delay before the first retry, doubled on each new retry
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// the errors worth retrying on
+    pub is_retryable: fn(&StorageError) -> bool,
+}
+
+/// retry only on the errors of the backend (lock contention, I/O errors...)
+/// and not on the errors about the content of the storage nor on the
+/// timeouts.
+pub fn is_backend_error(error: &StorageError) -> bool {
+    match error {
+        StorageError::BackendError(_) => !is_timeout(error),
+        _ => false,
+    }
+}
+
+impl RetryPolicy {
+    /// do not retry at all
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub(super) async fn run<F, Fut, R>(&self, mut f: F) -> Result<R, StorageError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, StorageError>>,
+    {
+        let started = Instant::now();
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            match f().await {
+                Err(e)
+                    if attempt < self.max_attempts
+                        && (self.is_retryable)(&e)
+                        && started.elapsed() + backoff < self.max_duration =>
+                {
+                    delay_for(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, self.max_backoff);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            max_duration: Duration::from_secs(2),
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(500),
+            is_retryable: is_backend_error,
+        }
+    }
+}
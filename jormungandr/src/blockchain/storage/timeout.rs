@@ -0,0 +1,37 @@
+use super::StorageError;
+use futures03::Future;
+use std::time::Duration;
+use thiserror::Error;
+use tokio02::time;
+
+/// a storage operation did not complete within the configured timeout
+#[derive(Debug, Error)]
+#[error("storage operation timed out after {0:?}")]
+pub struct StorageTimeout(pub Duration);
+
+impl From<StorageTimeout> for StorageError {
+    fn from(error: StorageTimeout) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+pub fn is_timeout(error: &StorageError) -> bool {
+    match error {
+        StorageError::BackendError(e) => e.downcast_ref::<StorageTimeout>().is_some(),
+        _ => false,
+    }
+}
+
+/// run the storage operation, failing with a `StorageTimeout` if it did not
+/// complete within `timeout`
+pub(super) async fn with_timeout<F, R>(timeout: Option<Duration>, f: F) -> Result<R, StorageError>
+where
+    F: Future<Output = Result<R, StorageError>>,
+{
+    match timeout {
+        None => f.await,
+        Some(timeout) => time::timeout(timeout, f)
+            .await
+            .unwrap_or_else(|_elapsed| Err(StorageTimeout(timeout).into())),
+    }
+}
@@ -0,0 +1,124 @@
+//! version of the format of the storage
+//!
+//! The version is written in decimal in a file next to the storage file.
+//! A storage without file (in memory) is always created with the current
+//! version and has no version file.
+
+use super::StorageError;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// the current version of the format of the storage
+pub const STORAGE_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum VersionError {
+    #[error("incompatible storage version {found:?}, expected version {expected}")]
+    IncompatibleVersion {
+        /// `None` if the version file does not hold a valid version
+        found: Option<u32>,
+        expected: u32,
+    },
+}
+
+impl From<VersionError> for StorageError {
+    fn from(error: VersionError) -> Self {
+        StorageError::BackendError(Box::new(error))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionFile {
+    path: PathBuf,
+}
+
+impl VersionFile {
+    /// the version file of the storage persisted in `storage_path`
+    pub fn new(storage_path: &Path) -> Self {
+        let mut path = storage_path.as_os_str().to_owned();
+        path.push(".version");
+        VersionFile { path: path.into() }
+    }
+
+    /// the version written in the file, `None` if there is no file
+    fn read(&self) -> io::Result<Option<Option<u32>>> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(Some(decode(&content))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write the version in a temporary file moved over the version file,
+    /// so a crash does not leave a truncated version
+    fn write(&self, version: u32) -> io::Result<()> {
+        let mut temporary = self.path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(encode(version).as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    /// check the storage has the `expected` version. A storage without
+    /// version file (new or created before the version was recorded) is
+    /// stamped with the `expected` version, unless it is read-only.
+    pub fn check(&self, expected: u32, read_only: bool) -> Result<(), StorageError> {
+        match self.read().map_err(backend_error)? {
+            None if read_only => Ok(()),
+            None => self.write(expected).map_err(backend_error),
+            Some(Some(found)) if found == expected => Ok(()),
+            Some(found) => Err(VersionError::IncompatibleVersion { found, expected }.into()),
+        }
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
+
+fn encode(version: u32) -> String {
+    format!("{}\n", version)
+}
+
+fn decode(content: &str) -> Option<u32> {
+    content.trim_end_matches('\n').parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        assert_eq!(decode(&encode(STORAGE_VERSION)), Some(STORAGE_VERSION));
+        assert_eq!(decode("JSTV"), None);
+        assert_eq!(decode(""), None);
+    }
+
+    #[test]
+    fn stamp_and_check() {
+        let path = std::env::temp_dir().join(format!("jormungandr-version-{}", std::process::id()));
+        let version_file = VersionFile::new(&path);
+
+        version_file.check(STORAGE_VERSION, true).unwrap();
+        assert_eq!(version_file.read().unwrap(), None);
+        version_file.check(STORAGE_VERSION, false).unwrap();
+        version_file.check(STORAGE_VERSION, true).unwrap();
+        let res = version_file.check(STORAGE_VERSION + 1, false);
+        fs::remove_file(&version_file.path).unwrap();
+        match res {
+            Err(StorageError::BackendError(error)) => match error.downcast_ref::<VersionError>() {
+                Some(VersionError::IncompatibleVersion { found, .. }) => {
+                    assert_eq!(*found, Some(STORAGE_VERSION))
+                }
+                None => panic!("unexpected error: {}", error),
+            },
+            _ => panic!("the version was not checked"),
+        }
+    }
+}
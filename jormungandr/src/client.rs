@@ -1,11 +1,12 @@
 use crate::blockcfg::{Block, Header, HeaderHash};
-use crate::blockchain::{Storage, Tip};
+use crate::blockchain::{ClosestAncestor, Storage, Tip};
 use crate::intercom::{ClientMsg, Error, ReplySendError, ReplyStreamHandle};
 use crate::network::p2p::{P2pTopology, Peer, PeersResponse};
 use crate::utils::task::{Input, TokioServiceInfo};
 use chain_core::property::HasHeader;
 
 use futures::future::Either;
+use slog::Logger;
 use tokio::prelude::*;
 use tokio::timer::Timeout;
 
@@ -85,8 +86,8 @@ pub fn handle_input(
             );
         }
         ClientMsg::GetHeadersRange(checkpoints, to, handle) => {
-            let fut = handle_get_headers_range(task_data, checkpoints, to, handle);
             let logger = info.logger().new(o!("request" => "GetHeadersRange"));
+            let fut = handle_get_headers_range(task_data, checkpoints, to, handle, logger.clone());
             info.spawn(
                 "GetHeadersRange",
                 Timeout::new(fut, Duration::from_secs(PROCESS_TIMEOUT_GET_HEADERS_RANGE)).map_err(
@@ -117,8 +118,8 @@ pub fn handle_input(
             );
         }
         ClientMsg::PullBlocksToTip(from, handle) => {
-            let fut = handle_pull_blocks_to_tip(task_data, from, handle);
             let logger = info.logger().new(o!("request" => "PullBlocksToTip"));
+            let fut = handle_pull_blocks_to_tip(task_data, from, handle, logger.clone());
             info.spawn(
                 "PullBlocksToTip",
                 Timeout::new(fut, Duration::from_secs(PROCESS_TIMEOUT_PULL_BLOCKS_TO_TIP)).map_err(
@@ -161,20 +162,17 @@ fn handle_get_headers_range(
     checkpoints: Vec<HeaderHash>,
     to: HeaderHash,
     handle: ReplyStreamHandle<Header>,
+    logger: Logger,
 ) -> impl Future<Item = (), Error = ()> {
     let storage = task_data.storage.clone();
     storage
         .find_closest_ancestor(checkpoints, to)
         .then(move |res| match res {
-            Ok(maybe_ancestor) => {
-                let depth = maybe_ancestor.map(|ancestor| ancestor.distance);
+            Ok(closest) => {
+                log_unknown_checkpoints(&logger, &closest);
+                let depth = closest.ancestor.map(|ancestor| ancestor.distance);
                 let fut = storage
-                    .send_branch(
-                        to,
-                        depth,
-                        handle
-                            .with(|res: Result<Block, Error>| Ok(res.map(|block| block.header()))),
-                    )
+                    .send_header_branch(to, depth, handle)
                     .then(|_: Result<_, ReplySendError>| Ok(()));
                 Either::A(fut)
             }
@@ -182,6 +180,17 @@ fn handle_get_headers_range(
         })
 }
 
+fn log_unknown_checkpoints(logger: &Logger, closest: &ClosestAncestor) {
+    if !closest.unknown_checkpoints.is_empty() {
+        debug!(
+            logger,
+            "peer sent checkpoints unknown to this node";
+            "count" => closest.unknown_checkpoints.len(),
+            "first" => %closest.unknown_checkpoints[0],
+        );
+    }
+}
+
 fn get_blocks(storage: Storage, ids: Vec<HeaderHash>) -> impl Stream<Item = Block, Error = Error> {
     stream::iter_ok(ids).and_then(move |id| {
         storage
@@ -219,6 +228,7 @@ fn handle_pull_blocks_to_tip(
     task_data: &TaskData,
     checkpoints: Vec<HeaderHash>,
     handle: ReplyStreamHandle<Block>,
+    logger: Logger,
 ) -> impl Future<Item = (), Error = ()> {
     let storage = task_data.storage.clone();
     task_data
@@ -228,8 +238,9 @@ fn handle_pull_blocks_to_tip(
             let tip_hash = tip.hash();
             storage
                 .find_closest_ancestor(checkpoints, tip_hash)
-                .map(move |maybe_ancestor| {
-                    let depth = maybe_ancestor.map(|ancestor| ancestor.distance);
+                .map(move |closest| {
+                    log_unknown_checkpoints(&logger, &closest);
+                    let depth = closest.ancestor.map(|ancestor| ancestor.distance);
                     (storage, tip_hash, depth)
                 })
         })
@@ -2,6 +2,7 @@ use crate::{
     blockcfg::{Value, ValueError},
     fragment::{Fragment, FragmentId},
 };
+use chain_impl_mockchain::transaction::Transaction;
 use std::time::SystemTime;
 
 pub struct PoolEntry {
@@ -29,15 +30,12 @@ pub struct PoolEntry {
 }
 
 impl PoolEntry {
-    pub fn new(fragment: &Fragment) -> Self {
+    /// `fragment_fee` is the fee of the fragment checked against the
+    /// ledger, see `implicit_fee`
+    pub fn new(fragment: &Fragment, fragment_fee: Value) -> Self {
         let raw = fragment.to_raw();
         let fragment_size = raw.size_bytes_plus_size();
         let fragment_ref = raw.id();
-        // TODO: the fragment fee is not yet computed. Yet we should
-        // have an explicit fee in the message. So we need to be able
-        // to extract this information without the need to compute the
-        // fee from the ledger's fee settings.
-        let fragment_fee = Value::zero();
 
         PoolEntry {
             fragment_ref: fragment_ref,
@@ -69,6 +67,12 @@ impl PoolEntry {
     pub fn fragment_size(&self) -> &usize {
         &self.fragment_size
     }
+    /// fee paid per 1000 bytes of the fragment
+    #[inline]
+    pub fn fee_rate(&self) -> u64 {
+        let size = self.fragment_size.max(1) as u128;
+        (u128::from(self.fragment_fee.0) * 1000 / size) as u64
+    }
     #[inline]
     pub fn received_at(&self) -> &SystemTime {
         &self.received_at
@@ -90,3 +94,29 @@ impl PoolEntry {
         self.ancestors_size + self.fragment_size
     }
 }
+
+/// the fee left by the fragment: the difference between its inputs and
+/// its outputs. Fragments without inputs, or with inputs smaller than
+/// their outputs, do not pay any fee.
+///
+/// The values of the inputs are the ones declared by the fragment, they
+/// are only known to be right once the fragment applies to the ledger.
+pub(super) fn implicit_fee(fragment: &Fragment) -> Value {
+    fn fee<T>(tx: &Transaction<T>) -> Result<Value, ValueError> {
+        tx.total_input()? - tx.total_output()?
+    }
+
+    let fee = match fragment {
+        Fragment::Transaction(tx) => fee(tx),
+        Fragment::OwnerStakeDelegation(tx) => fee(tx),
+        Fragment::StakeDelegation(tx) => fee(tx),
+        Fragment::PoolRegistration(tx) => fee(tx),
+        Fragment::PoolRetirement(tx) => fee(tx),
+        Fragment::PoolUpdate(tx) => fee(tx),
+        Fragment::Initial(_)
+        | Fragment::OldUtxoDeclaration(_)
+        | Fragment::UpdateProposal(_)
+        | Fragment::UpdateVote(_) => return Value::zero(),
+    };
+    fee.unwrap_or_else(|_| Value::zero())
+}
@@ -0,0 +1,291 @@
+//! append-only journal of the fragments of the mempool
+//!
+//! Every fragment accepted in the pool is appended to the journal with its
+//! origin, the journal is replayed when the node starts so the pending
+//! fragments survive a restart. The journal is compacted on garbage
+//! collection: the fragments no longer in the pool are dropped from it.
+//!
+//! An entry is made of the origin (1 byte), the size of the serialized
+//! fragment (4 bytes, big endian) and the serialized fragment. An entry
+//! truncated by a crash while it was written is ignored.
+//!
+//! The journal is written by a dedicated thread, see `JournalWriter`, so
+//! the pool does not wait for the disk while it is locked.
+
+use crate::fragment::{Fragment, FragmentId};
+use chain_core::property::{Deserialize as _, Fragment as _, Serialize as _};
+use futures03::{
+    channel::{mpsc, oneshot},
+    executor::block_on_stream,
+    future::Future,
+};
+use jormungandr_lib::interfaces::FragmentOrigin;
+use slog::Logger;
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    thread,
+};
+
+const ORIGIN_NETWORK: u8 = 0;
+const ORIGIN_REST: u8 = 1;
+
+pub struct Journal {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Journal {
+    /// open the journal at `path`, creating it if it does not exist, and
+    /// return the fragments it contains in the order they were appended
+    pub fn open(path: PathBuf) -> io::Result<(Self, Vec<(FragmentOrigin, Fragment)>)> {
+        let entries = if path.exists() {
+            read_entries(BufReader::new(File::open(&path)?))?
+        } else {
+            Vec::new()
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let journal = Journal {
+            path,
+            file: BufWriter::new(file),
+        };
+        Ok((journal, entries))
+    }
+
+    pub fn append<'a>(
+        &mut self,
+        origin: FragmentOrigin,
+        fragments: impl IntoIterator<Item = &'a Fragment>,
+    ) -> io::Result<()> {
+        for fragment in fragments {
+            write_entry(&mut self.file, origin, fragment)?;
+        }
+        self.file.flush()
+    }
+
+    /// rewrite the journal with only the fragments for which `retain`
+    /// returns true
+    pub fn compact<F>(&mut self, retain: F) -> io::Result<()>
+    where
+        F: Fn(&FragmentId) -> bool,
+    {
+        self.file.flush()?;
+        let entries = read_entries(BufReader::new(File::open(&self.path)?))?;
+
+        let tmp = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for (origin, fragment) in entries.iter() {
+            if retain(&fragment.id()) {
+                write_entry(&mut writer, *origin, fragment)?;
+            }
+        }
+        writer.flush()?;
+        fs::rename(&tmp, &self.path)?;
+
+        let file = OpenOptions::new().append(true).open(&self.path)?;
+        self.file = BufWriter::new(file);
+        Ok(())
+    }
+}
+
+enum Command {
+    Append(FragmentOrigin, Vec<Fragment>),
+    Compact(HashSet<FragmentId>, oneshot::Sender<io::Result<()>>),
+}
+
+/// handle to the journal written by a dedicated thread. The writes are
+/// done in the order they are requested, without blocking the caller.
+#[derive(Clone)]
+pub struct JournalWriter {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl JournalWriter {
+    /// start the thread writing the `journal`, it stops once all the
+    /// handles are dropped
+    pub fn spawn(journal: Journal, logger: Logger) -> io::Result<Self> {
+        let (commands, receiver) = mpsc::unbounded();
+        thread::Builder::new()
+            .name("mempool-journal".to_owned())
+            .spawn(move || {
+                let mut journal = journal;
+                for command in block_on_stream(receiver) {
+                    match command {
+                        Command::Append(origin, fragments) => {
+                            if let Err(e) = journal.append(origin, &fragments) {
+                                warn!(logger, "cannot append the fragments to the mempool journal";
+                                    "reason" => %e,
+                                );
+                            }
+                        }
+                        Command::Compact(retained, reply) => {
+                            let res = journal.compact(|fragment_id| retained.contains(fragment_id));
+                            let _ = reply.send(res);
+                        }
+                    }
+                }
+            })?;
+        Ok(JournalWriter { commands })
+    }
+
+    /// append the `fragments`, a failure is logged by the writer
+    pub fn append(&self, origin: FragmentOrigin, fragments: Vec<Fragment>) {
+        let _ = self
+            .commands
+            .unbounded_send(Command::Append(origin, fragments));
+    }
+
+    /// rewrite the journal with only the `retained` fragments, after the
+    /// writes requested before this call. The request is sent right away,
+    /// the returned future waits for the result.
+    pub fn compact(&self, retained: HashSet<FragmentId>) -> impl Future<Output = io::Result<()>> {
+        let (reply, result) = oneshot::channel();
+        let sent = self
+            .commands
+            .unbounded_send(Command::Compact(retained, reply));
+        async move {
+            sent.map_err(|_| writer_stopped())?;
+            result.await.map_err(|_| writer_stopped())?
+        }
+    }
+}
+
+fn writer_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "the mempool journal writer stopped")
+}
+
+fn write_entry<W: Write>(
+    mut writer: W,
+    origin: FragmentOrigin,
+    fragment: &Fragment,
+) -> io::Result<()> {
+    let bytes = fragment.serialize_as_vec()?;
+    let origin = match origin {
+        FragmentOrigin::Network => ORIGIN_NETWORK,
+        FragmentOrigin::Rest => ORIGIN_REST,
+    };
+    writer.write_all(&[origin])?;
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&bytes)
+}
+
+fn read_entries<R: Read>(mut reader: R) -> io::Result<Vec<(FragmentOrigin, Fragment)>> {
+    let mut entries = Vec::new();
+    loop {
+        let mut header = [0u8; 5];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let origin = match header[0] {
+            ORIGIN_NETWORK => FragmentOrigin::Network,
+            ORIGIN_REST => FragmentOrigin::Rest,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid fragment origin in the mempool journal",
+                ))
+            }
+        };
+        let size = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let mut bytes = vec![0u8; size];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        entries.push((origin, Fragment::deserialize(bytes.as_slice())?));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::pool::tests::spending;
+    use futures03::executor::block_on;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("jormungandr-{}-{}", name, std::process::id()))
+    }
+
+    fn ids(entries: &[(FragmentOrigin, Fragment)]) -> Vec<(FragmentOrigin, FragmentId)> {
+        entries
+            .iter()
+            .map(|(origin, fragment)| (*origin, fragment.id()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_and_truncated_entry() {
+        let path = temp_path("journal-round-trip");
+        let first = spending(&[(FragmentId::hash_bytes(&[1]), 0)], 10);
+        let second = spending(&[(FragmentId::hash_bytes(&[2]), 0)], 20);
+
+        let (mut journal, entries) = Journal::open(path.clone()).unwrap();
+        assert!(entries.is_empty());
+        journal
+            .append(FragmentOrigin::Rest, &[first.clone()])
+            .unwrap();
+        journal
+            .append(FragmentOrigin::Network, &[second.clone()])
+            .unwrap();
+        drop(journal);
+
+        let (journal, entries) = Journal::open(path.clone()).unwrap();
+        assert_eq!(
+            ids(&entries),
+            vec![
+                (FragmentOrigin::Rest, first.id()),
+                (FragmentOrigin::Network, second.id())
+            ]
+        );
+        drop(journal);
+
+        // a crash while the second entry was written
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+        let (_, entries) = Journal::open(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ids(&entries), vec![(FragmentOrigin::Rest, first.id())]);
+    }
+
+    #[test]
+    fn writer_compacts_after_the_earlier_appends() {
+        let path = temp_path("journal-writer");
+        let first = spending(&[(FragmentId::hash_bytes(&[1]), 0)], 10);
+        let second = spending(&[(FragmentId::hash_bytes(&[2]), 0)], 20);
+        let third = spending(&[(FragmentId::hash_bytes(&[3]), 0)], 30);
+
+        let (journal, _) = Journal::open(path.clone()).unwrap();
+        let logger = Logger::root(slog::Discard, o!());
+        let writer = JournalWriter::spawn(journal, logger).unwrap();
+        writer.append(FragmentOrigin::Rest, vec![first.clone(), second]);
+        let retained = vec![first.id()].into_iter().collect();
+        let compacted = writer.compact(retained);
+        // not in the snapshot given to the compaction but appended after it
+        writer.append(FragmentOrigin::Network, vec![third.clone()]);
+        block_on(compacted).unwrap();
+        // waits for the last append
+        block_on(writer.compact(vec![first.id(), third.id()].into_iter().collect())).unwrap();
+        drop(writer);
+
+        let (_, entries) = Journal::open(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            ids(&entries),
+            vec![
+                (FragmentOrigin::Rest, first.id()),
+                (FragmentOrigin::Network, third.id())
+            ]
+        );
+    }
+}
@@ -42,6 +42,11 @@ impl Logs {
             .await
     }
 
+    pub async fn get(&self, fragment_id: FragmentId) -> Result<Option<FragmentLog>, ()> {
+        self.run_on_inner(move |inner| inner.get(&fragment_id.into()).cloned())
+            .await
+    }
+
     pub async fn modify(
         &mut self,
         fragment_id: FragmentId,
@@ -92,7 +97,7 @@ pub(super) mod internal {
     };
     use jormungandr_lib::{
         crypto::hash::Hash,
-        interfaces::{FragmentLog, FragmentOrigin, FragmentStatus},
+        interfaces::{FragmentLog, FragmentOrigin, FragmentRejectionCause, FragmentStatus},
     };
     use std::{
         collections::hash_map::{Entry, HashMap},
@@ -118,8 +123,16 @@ pub(super) mod internal {
             }
         }
 
+        /// a fragment rejected because the pool was full is not known: it
+        /// can be submitted again
         pub fn exists(&self, fragment_id: &Hash) -> bool {
-            self.entries.contains_key(fragment_id)
+            self.entries
+                .get(fragment_id)
+                .map_or(false, |(log, _)| !rejected_pool_full(log))
+        }
+
+        pub fn get(&self, fragment_id: &Hash) -> Option<&FragmentLog> {
+            self.entries.get(fragment_id).map(|(log, _)| log)
         }
 
         pub fn exist_all(&self, fragment_ids: impl IntoIterator<Item = Hash>) -> Vec<bool> {
@@ -135,12 +148,20 @@ pub(super) mod internal {
                 false
             } else {
                 let fragment_id = *log.fragment_id();
-                let entry = match self.entries.entry(fragment_id) {
-                    Entry::Occupied(_) => return false,
-                    Entry::Vacant(entry) => entry,
-                };
-                let delay = self.expirations.insert(fragment_id, self.ttl);
-                entry.insert((log, delay));
+                match self.entries.entry(fragment_id) {
+                    Entry::Occupied(mut entry) => {
+                        if !rejected_pool_full(&entry.get().0) {
+                            return false;
+                        }
+                        entry.get_mut().0 = log;
+                        self.expirations
+                            .reset_at(&entry.get().1, Instant::now() + self.ttl);
+                    }
+                    Entry::Vacant(entry) => {
+                        let delay = self.expirations.insert(fragment_id, self.ttl);
+                        entry.insert((log, delay));
+                    }
+                }
                 true
             }
         }
@@ -208,4 +229,54 @@ pub(super) mod internal {
             self.entries.values().map(|(v, _)| v)
         }
     }
+
+    fn rejected_pool_full(log: &FragmentLog) -> bool {
+        match log.status() {
+            FragmentStatus::Rejected {
+                cause: FragmentRejectionCause::PoolFull,
+                ..
+            } => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jormungandr_lib::interfaces::{FragmentOrigin, FragmentRejectionCause};
+    use tokio_compat::runtime;
+
+    fn rejected(fragment_id: FragmentId, cause: FragmentRejectionCause) -> FragmentLog {
+        let mut log = FragmentLog::new(fragment_id.into(), FragmentOrigin::Rest);
+        log.modify(FragmentStatus::Rejected {
+            reason: "rejected".to_owned(),
+            cause,
+        });
+        log
+    }
+
+    #[test]
+    fn fragment_rejected_by_a_full_pool_is_not_known() {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        rt.block_on_std(async {
+            let mut logs = Logs::new(10, Duration::from_secs(60));
+            let pool_full = FragmentId::hash_bytes(&[1]);
+            let not_balanced = FragmentId::hash_bytes(&[2]);
+            logs.insert(rejected(pool_full, FragmentRejectionCause::PoolFull))
+                .await
+                .unwrap();
+            logs.insert(rejected(not_balanced, FragmentRejectionCause::NotBalanced))
+                .await
+                .unwrap();
+            assert!(!logs.exists(pool_full).await.unwrap());
+            assert!(logs.exists(not_balanced).await.unwrap());
+
+            // submitted again once the pool has room
+            let resubmitted = FragmentLog::new(pool_full.into(), FragmentOrigin::Rest);
+            assert!(logs.insert(resubmitted).await.unwrap());
+            assert!(logs.exists(pool_full).await.unwrap());
+            assert!(logs.get(pool_full).await.unwrap().unwrap().is_pending());
+        })
+    }
 }
@@ -1,10 +1,12 @@
 mod entry;
+mod journal;
 mod logs;
 mod pool;
 mod process;
 pub mod selection;
 
 pub use self::entry::PoolEntry;
+pub use self::journal::Journal;
 pub use self::logs::Logs;
 pub use self::pool::Pool;
 pub use self::process::Process;
@@ -1,15 +1,22 @@
+use super::{entry::implicit_fee, selection::rejected};
 use crate::{
-    blockcfg::{BlockDate, Ledger, LedgerParameters},
-    fragment::{selection::FragmentSelectionAlgorithm, Fragment, FragmentId, Logs},
+    blockcfg::{BlockDate, Ledger, LedgerParameters, Value},
+    blockchain::{Ref, Tip},
+    fragment::{
+        journal::JournalWriter, selection::FragmentSelectionAlgorithm, Fragment, FragmentId,
+        Journal, Logs,
+    },
     intercom::{NetworkMsg, PropagateMsg},
     utils::async_msg::MessageBox,
 };
 use chain_core::property::Fragment as _;
-use chain_impl_mockchain::transaction::Transaction;
+use chain_impl_mockchain::transaction::{InputEnum, Transaction};
 use futures03::{compat::*, future, sink::SinkExt};
-use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin, FragmentStatus};
+use jormungandr_lib::interfaces::{
+    FragmentLog, FragmentOrigin, FragmentRejectionCause, FragmentSelectionStrategy, FragmentStatus,
+};
 use slog::Logger;
-use std::{sync::Arc, time::Duration};
+use std::{io, sync::Arc, time::Duration};
 use tokio02::{sync::Mutex, time};
 
 #[derive(Clone)]
@@ -17,31 +24,62 @@ pub struct Pool {
     logs: Logs,
     pool: Arc<Mutex<internal::Pool>>,
     network_msg_box: MessageBox<NetworkMsg>,
+    selection_strategy: FragmentSelectionStrategy,
+    journal: Option<JournalWriter>,
 }
 
 impl Pool {
+    /// `min_fee_bump` enables the replacement of the pending fragments by
+    /// fragments spending the same inputs with a fee higher by at least
+    /// this amount
     pub fn new(
         max_entries: usize,
         ttl: Duration,
+        min_fee_bump: Option<u64>,
         logs: Logs,
         network_msg_box: MessageBox<NetworkMsg>,
+        selection_strategy: FragmentSelectionStrategy,
     ) -> Self {
         Pool {
             logs,
-            pool: Arc::new(Mutex::new(internal::Pool::new(max_entries, ttl))),
+            pool: Arc::new(Mutex::new(internal::Pool::new(
+                max_entries,
+                ttl,
+                min_fee_bump,
+            ))),
             network_msg_box,
+            selection_strategy,
+            journal: None,
         }
     }
 
+    /// record the fragments accepted in the pool to `journal`, written by
+    /// a dedicated thread logging to `logger`
+    pub fn set_journal(&mut self, journal: Journal, logger: Logger) -> io::Result<()> {
+        self.journal = Some(JournalWriter::spawn(journal, logger)?);
+        Ok(())
+    }
+
+    pub fn has_journal(&self) -> bool {
+        self.journal.is_some()
+    }
+
     pub fn logs(&self) -> &Logs {
         &self.logs
     }
 
-    /// Returns number of registered fragments
+    /// order in which the fragments are selected for a block
+    pub fn selection_strategy(&self) -> FragmentSelectionStrategy {
+        self.selection_strategy
+    }
+
+    /// Returns number of registered fragments. The fragments replacing
+    /// pending ones are checked against the ledger of `blockchain_tip`.
     pub async fn insert_and_propagate_all(
         &mut self,
         origin: FragmentOrigin,
         mut fragments: Vec<Fragment>,
+        blockchain_tip: &Tip,
         logger: Logger,
     ) -> Result<usize, ()> {
         fragments.retain(is_fragment_valid);
@@ -52,17 +90,34 @@ impl Pool {
         let mut network_msg_box = self.network_msg_box.clone().sink_compat();
         let fragment_ids = fragments.iter().map(Fragment::id).collect::<Vec<_>>();
         let fragments_exist_in_logs = self.logs.exist_all(fragment_ids).await?;
+        let tip = blockchain_tip.get_ref_std().await;
         let mut pool = self.pool.lock().await;
-        let new_fragments = fragments
-            .into_iter()
-            .zip(fragments_exist_in_logs)
-            .filter(|(_, exists_in_logs)| !exists_in_logs)
-            .map(|(fragment, _)| fragment);
-        let new_fragments = pool.insert_all(new_fragments);
+        let mut new_fragments = Vec::new();
+        let mut rejected_logs = Vec::new();
+        for (fragment, exists_in_logs) in fragments.into_iter().zip(fragments_exist_in_logs) {
+            if exists_in_logs {
+                continue;
+            }
+            let fragment_id = fragment.id();
+            match insert_or_replace(&mut pool, fragment, &tip) {
+                Ok(Some(fragment)) => new_fragments.push(fragment),
+                Ok(None) => {}
+                Err(status) => {
+                    let mut log = FragmentLog::new(fragment_id.into(), origin);
+                    log.modify(status);
+                    rejected_logs.push(log);
+                }
+            }
+        }
+        let replaced = pool.take_replaced();
+        if let Some(journal) = self.journal.as_ref() {
+            journal.append(origin, new_fragments.clone());
+        }
         let count = new_fragments.len();
         let fragment_logs = new_fragments
             .iter()
             .map(move |fragment| FragmentLog::new(fragment.id().into(), origin))
+            .chain(rejected_logs)
             .collect::<Vec<_>>();
         for fragment in new_fragments.into_iter() {
             let fragment_msg = NetworkMsg::Propagate(PropagateMsg::Fragment(fragment));
@@ -72,6 +127,7 @@ impl Pool {
                 .map_err(|e| error!(logger, "cannot propagate fragment to network: {}", e))?;
         }
         logs.insert_all(fragment_logs).await?;
+        mark_replaced(&mut logs, replaced).await?;
         Ok(count)
     }
 
@@ -85,11 +141,72 @@ impl Pool {
         self.logs.modify_all(fragment_ids, status).await
     }
 
+    /// insert the fragments replayed from the journal, without propagating
+    /// them. The fragments are revalidated against the ledger of
+    /// `blockchain_tip`: the ones that do not apply anymore (e.g. already
+    /// in a block) are dropped. Returns the number of fragments restored.
+    pub async fn restore(
+        &mut self,
+        entries: Vec<(FragmentOrigin, Fragment)>,
+        blockchain_tip: &Tip,
+    ) -> Result<usize, ()> {
+        let tip = blockchain_tip.get_ref_std().await;
+        let ledger_params = tip.epoch_ledger_parameters();
+        let block_date = tip.block_date();
+        let mut ledger_simulation = tip.ledger().as_ref().clone();
+        let mut fragment_logs = Vec::new();
+        let replaced = {
+            let mut pool = self.pool.lock().await;
+            for (origin, fragment) in entries {
+                if !is_fragment_valid(&fragment) {
+                    continue;
+                }
+                // the replacements are checked against the ledger of the tip
+                if pool.conflicts(&fragment).is_empty() {
+                    match ledger_simulation.apply_fragment(ledger_params, &fragment, block_date) {
+                        Ok(ledger_new) => ledger_simulation = ledger_new,
+                        Err(_) => continue,
+                    }
+                }
+                if let Ok(Some(fragment)) = insert_or_replace(&mut pool, fragment, &tip) {
+                    fragment_logs.push(FragmentLog::new(fragment.id().into(), origin));
+                }
+            }
+            pool.take_replaced()
+        };
+        let count = fragment_logs.len();
+        self.logs.insert_all(fragment_logs).await?;
+        mark_replaced(&mut self.logs, replaced).await?;
+        Ok(count)
+    }
+
+    /// drop from the journal the fragments no longer in the pool
+    pub async fn compact_journal(&self) -> io::Result<()> {
+        let journal = match self.journal.as_ref() {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        // requested under the lock so the fragments appended after the
+        // snapshot of the pool are kept
+        let compacted = {
+            let pool = self.pool.lock().await;
+            journal.compact(pool.fragment_ids())
+        };
+        compacted.await
+    }
+
     pub async fn poll_purge(&mut self) -> Result<(), time::Error> {
-        {
+        let expired = {
             let mut pool = self.pool.lock().await;
-            future::poll_fn(move |cx| pool.poll_purge(cx)).await?;
-        }
+            future::poll_fn(|cx| pool.poll_purge(cx)).await?;
+            pool.take_expired()
+        };
+        let status = FragmentStatus::Rejected {
+            reason: "the fragment expired in the pool".to_owned(),
+            cause: FragmentRejectionCause::Expired,
+        };
+        // modifying the logs does not fail
+        let _ = self.logs.modify_all(expired, status).await;
         self.logs.poll_purge().await
     }
 
@@ -111,6 +228,97 @@ impl Pool {
     }
 }
 
+/// insert the fragment in the pool, replacing the pending fragments
+/// spending the same inputs if any. Returns `None` if the fragment is
+/// already pending, the status of the fragment if it is rejected.
+///
+/// The fees are checked against the ledger of the `tip`: the ledger checks
+/// the declared values of the inputs, so the implicit fee of an accepted
+/// fragment is the fee it actually pays. A fragment the ledger refuses,
+/// such as one spending the outputs of a pending fragment, pays no fee
+/// and has the lowest priority in the pool. A replacement is checked
+/// before any pending fragment is removed.
+fn insert_or_replace(
+    pool: &mut internal::Pool,
+    fragment: Fragment,
+    tip: &Ref,
+) -> Result<Option<Fragment>, FragmentStatus> {
+    if pool.contains(&fragment.id()) {
+        return Ok(None);
+    }
+    let ledger = tip.ledger();
+    let checked_fee = |fragment: &Fragment| {
+        ledger
+            .apply_fragment(tip.epoch_ledger_parameters(), fragment, tip.block_date())
+            .map(|_| implicit_fee(fragment))
+    };
+    let conflicts = pool.conflicts(&fragment);
+    if conflicts.is_empty() {
+        let fee = checked_fee(&fragment).unwrap_or_else(|_| Value::zero());
+        return pool
+            .insert(fragment, fee)
+            .map(Some)
+            .ok_or_else(|| FragmentStatus::Rejected {
+                reason: "the fragment pool is full".to_owned(),
+                cause: FragmentRejectionCause::PoolFull,
+            });
+    }
+
+    let fee = checked_fee(&fragment).map_err(|error| rejected(&error))?;
+    let replaced_fee = conflicts
+        .iter()
+        .filter_map(|fragment_id| pool.get(fragment_id))
+        .map(|replaced| checked_fee(replaced).map_or(0, |fee| fee.0))
+        .sum();
+    pool.replace(fragment, fee, Value(replaced_fee))
+        .map(Some)
+        .map_err(|required| FragmentStatus::Rejected {
+            reason: format!(
+                "the fee {} is lower than the {} required to replace the pending fragments",
+                fee.0, required.0
+            ),
+            cause: FragmentRejectionCause::InsufficientFeeBump,
+        })
+}
+
+/// mark the logs of the `(replaced, by)` fragments as replaced
+async fn mark_replaced(logs: &mut Logs, replaced: Vec<(FragmentId, FragmentId)>) -> Result<(), ()> {
+    for (fragment_id, by) in replaced {
+        let status = FragmentStatus::Replaced { by: by.into() };
+        logs.modify(fragment_id, status).await?;
+    }
+    Ok(())
+}
+
+/// the UTxOs spent by the fragment, as transaction id and output index
+pub(super) fn utxo_inputs(fragment: &Fragment) -> Vec<(FragmentId, u8)> {
+    fn inputs<E>(tx: &Transaction<E>) -> Vec<(FragmentId, u8)> {
+        tx.as_slice()
+            .inputs()
+            .iter()
+            .filter_map(|input| match input.to_enum() {
+                InputEnum::UtxoInput(pointer) => {
+                    Some((pointer.transaction_id, pointer.output_index))
+                }
+                InputEnum::AccountInput(..) => None,
+            })
+            .collect()
+    }
+
+    match fragment {
+        Fragment::Transaction(ref tx) => inputs(tx),
+        Fragment::StakeDelegation(ref tx) => inputs(tx),
+        Fragment::OwnerStakeDelegation(ref tx) => inputs(tx),
+        Fragment::PoolRegistration(ref tx) => inputs(tx),
+        Fragment::PoolRetirement(ref tx) => inputs(tx),
+        Fragment::PoolUpdate(ref tx) => inputs(tx),
+        Fragment::Initial(_)
+        | Fragment::OldUtxoDeclaration(_)
+        | Fragment::UpdateProposal(_)
+        | Fragment::UpdateVote(_) => Vec::new(),
+    }
+}
+
 fn is_fragment_valid(fragment: &Fragment) -> bool {
     match fragment {
         // never valid in the pool, only acceptable in genesis
@@ -141,73 +349,180 @@ pub(super) mod internal {
         task::{Context, Poll},
     };
     use std::{
-        collections::{hash_map::Entry, HashMap, VecDeque},
+        cmp::Reverse,
+        collections::{BTreeMap, HashMap, HashSet, VecDeque},
         pin::Pin,
         sync::Arc,
     };
     use tokio02::time::{delay_queue, DelayQueue};
 
+    /// position of an entry in the fee rate ordering: the highest fee
+    /// rate first, then the oldest entry first
+    type FeeRateKey = (Reverse<u64>, u64);
+
     pub struct Pool {
         max_entries: usize,
-        entries: HashMap<FragmentId, (Arc<PoolEntry>, Fragment, delay_queue::Key)>,
+        entries: HashMap<FragmentId, (Arc<PoolEntry>, Fragment, delay_queue::Key, FeeRateKey)>,
         entries_by_time: VecDeque<FragmentId>,
+        entries_by_fee_rate: BTreeMap<FeeRateKey, FragmentId>,
+        next_sequence: u64,
+        /// the pending fragment spending each UTxO, only maintained
+        /// when the replacement of fragments is enabled
+        entries_by_input: HashMap<(FragmentId, u8), FragmentId>,
+        /// minimum fee bump to replace pending fragments, `None` if the
+        /// replacement of fragments is disabled
+        min_fee_bump: Option<u64>,
+        /// the fragments replaced since the last `take_replaced`, with the
+        /// fragment that replaced them
+        replaced: Vec<(FragmentId, FragmentId)>,
+        /// the fragments expired since the last `take_expired`
+        expired: Vec<FragmentId>,
         expirations: Pin<Box<DelayQueue<FragmentId>>>,
         ttl: Duration,
     }
 
     impl Pool {
-        pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        pub fn new(max_entries: usize, ttl: Duration, min_fee_bump: Option<u64>) -> Self {
             Pool {
                 max_entries,
                 entries: HashMap::new(),
                 entries_by_time: VecDeque::new(),
+                entries_by_fee_rate: BTreeMap::new(),
+                next_sequence: 0,
+                entries_by_input: HashMap::new(),
+                min_fee_bump,
+                replaced: Vec::new(),
+                expired: Vec::new(),
                 expirations: Box::pin(DelayQueue::new()),
                 ttl,
             }
         }
 
-        /// Returns clone of fragment if it was registered
-        pub fn insert(&mut self, fragment: Fragment) -> Option<Fragment> {
-            if self.max_entries < self.entries.len() {
-                None
+        /// Returns clone of fragment if it was registered. The fragment
+        /// must not spend the inputs of pending fragments, see `replace`.
+        /// The fragments are prioritized by the `fee` checked against the
+        /// ledger.
+        pub fn insert(&mut self, fragment: Fragment, fee: Value) -> Option<Fragment> {
+            if self.entries.len() >= self.max_entries {
+                return None;
+            }
+            if self.entries.contains_key(&fragment.id()) {
+                return None;
+            }
+            Some(self.insert_entry(fragment, fee))
+        }
+
+        /// replace the pending fragments spending the same inputs as
+        /// `fragment` by it, if its `fee` exceeds their `replaced_fee` by
+        /// the minimum fee bump. The replacement does not need a free
+        /// entry since it removes at least one fragment. Returns the fee
+        /// required to replace them otherwise.
+        pub fn replace(
+            &mut self,
+            fragment: Fragment,
+            fee: Value,
+            replaced_fee: Value,
+        ) -> Result<Fragment, Value> {
+            let min_fee_bump = self.min_fee_bump.unwrap_or(0).max(1);
+            let required = replaced_fee.0.saturating_add(min_fee_bump);
+            if fee.0 < required {
+                return Err(Value(required));
+            }
+            let fragment_id = fragment.id();
+            for replaced_id in self.conflicts(&fragment) {
+                self.remove(&replaced_id);
+                self.replaced.push((replaced_id, fragment_id));
+            }
+            Ok(self.insert_entry(fragment, fee))
+        }
+
+        /// the pending fragments spending the inputs of `fragment`, always
+        /// empty if the replacement of fragments is disabled
+        pub fn conflicts(&self, fragment: &Fragment) -> Vec<FragmentId> {
+            if self.min_fee_bump.is_none() {
+                return Vec::new();
+            }
+            let conflicts: HashSet<FragmentId> = utxo_inputs(fragment)
+                .iter()
+                .filter_map(|input| self.entries_by_input.get(input).cloned())
+                .collect();
+            conflicts.into_iter().collect()
+        }
+
+        fn insert_entry(&mut self, fragment: Fragment, fee: Value) -> Fragment {
+            let fragment_id = fragment.id();
+            let pool_entry = Arc::new(PoolEntry::new(&fragment, fee));
+            let inputs = if self.min_fee_bump.is_some() {
+                utxo_inputs(&fragment)
             } else {
-                let fragment_id = fragment.id();
-                let entry = match self.entries.entry(fragment_id) {
-                    Entry::Occupied(_) => return None,
-                    Entry::Vacant(vacant) => vacant,
-                };
-                let pool_entry = Arc::new(PoolEntry::new(&fragment));
-                let delay = self.expirations.insert(fragment_id, self.ttl);
-                entry.insert((pool_entry, fragment.clone(), delay));
-                self.entries_by_time.push_back(fragment_id);
-                Some(fragment)
+                Vec::new()
+            };
+            let delay = self.expirations.insert(fragment_id, self.ttl);
+            let fee_rate_key = (Reverse(pool_entry.fee_rate()), self.next_sequence);
+            self.next_sequence += 1;
+            self.entries.insert(
+                fragment_id,
+                (pool_entry, fragment.clone(), delay, fee_rate_key),
+            );
+            self.entries_by_time.push_back(fragment_id);
+            self.entries_by_fee_rate.insert(fee_rate_key, fragment_id);
+            for input in inputs {
+                self.entries_by_input.insert(input, fragment_id);
             }
+            fragment
+        }
+
+        /// returns the fragments expired since the last call
+        pub fn take_expired(&mut self) -> Vec<FragmentId> {
+            std::mem::replace(&mut self.expired, Vec::new())
+        }
+
+        /// returns the fragments replaced since the last call, with the
+        /// fragment that replaced them
+        pub fn take_replaced(&mut self) -> Vec<(FragmentId, FragmentId)> {
+            std::mem::replace(&mut self.replaced, Vec::new())
         }
 
-        /// Returns clones of registered fragments
-        pub fn insert_all(
+        /// remove the fragment from the fee rate and inputs indexes
+        fn unindex(
             &mut self,
-            fragments: impl IntoIterator<Item = Fragment>,
-        ) -> Vec<Fragment> {
-            fragments
-                .into_iter()
-                .take(
-                    self.max_entries
-                        .checked_sub(self.entries.len())
-                        .unwrap_or(0),
-                )
-                .filter_map(|fragment| self.insert(fragment))
-                .collect()
+            fragment_id: &FragmentId,
+            fragment: &Fragment,
+            fee_rate_key: &FeeRateKey,
+        ) {
+            self.entries_by_fee_rate.remove(fee_rate_key);
+            if self.min_fee_bump.is_some() {
+                for input in utxo_inputs(fragment) {
+                    if self.entries_by_input.get(&input) == Some(fragment_id) {
+                        self.entries_by_input.remove(&input);
+                    }
+                }
+            }
+        }
+
+        pub fn fragment_ids(&self) -> HashSet<FragmentId> {
+            self.entries.keys().cloned().collect()
+        }
+
+        pub fn contains(&self, fragment_id: &FragmentId) -> bool {
+            self.entries.contains_key(fragment_id)
+        }
+
+        pub fn get(&self, fragment_id: &FragmentId) -> Option<&Fragment> {
+            self.entries
+                .get(fragment_id)
+                .map(|(_, fragment, _, _)| fragment)
         }
 
         pub fn remove(&mut self, fragment_id: &FragmentId) -> Option<Fragment> {
-            if let Some((_, fragment, cache_key)) = self.entries.remove(fragment_id) {
+            if let Some((_, fragment, cache_key, fee_rate_key)) = self.entries.remove(fragment_id) {
                 self.entries_by_time
                     .iter()
                     .position(|id| id == fragment_id)
                     .map(|position| {
                         self.entries_by_time.remove(position);
                     });
+                self.unindex(fragment_id, &fragment, &fee_rate_key);
                 self.expirations.remove(&cache_key);
                 Some(fragment)
             } else {
@@ -224,19 +539,30 @@ pub(super) mod internal {
 
         pub fn remove_oldest(&mut self) -> Option<Fragment> {
             let fragment_id = self.entries_by_time.pop_front()?;
-            let (_, fragment, cache_key) = self
+            let (_, fragment, cache_key, fee_rate_key) = self
                 .entries
                 .remove(&fragment_id)
                 .expect("Pool lost fragment ID consistency");
+            self.unindex(&fragment_id, &fragment, &fee_rate_key);
             self.expirations.remove(&cache_key);
             Some(fragment)
         }
 
+        pub fn remove_highest_fee_rate(&mut self) -> Option<Fragment> {
+            let fragment_id = *self.entries_by_fee_rate.values().next()?;
+            self.remove(&fragment_id)
+        }
+
         pub fn poll_purge(&mut self, cx: &mut Context) -> Poll<Result<(), time::Error>> {
             loop {
                 match self.expirations.as_mut().poll_next(cx) {
                     Poll::Ready(Some(Ok(entry))) => {
-                        self.entries.remove(entry.get_ref());
+                        if let Some((_, fragment, _, fee_rate_key)) =
+                            self.entries.remove(entry.get_ref())
+                        {
+                            self.unindex(entry.get_ref(), &fragment, &fee_rate_key);
+                            self.expired.push(*entry.get_ref());
+                        }
                         self.entries_by_time
                             .iter()
                             .position(|id| id == entry.get_ref())
@@ -256,3 +582,160 @@ pub(super) mod internal {
         }
     }
 }
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::blockcfg::HeaderHash;
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::transaction::{Input, TxBuilder, UtxoPointer, Witness};
+    use tokio_compat::runtime;
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        rt.block_on_std(future)
+    }
+
+    /// a transaction without output spending the outputs `(transaction,
+    /// index)` of `value`
+    fn transaction(inputs: &[(u8, u8)], value: u64) -> Fragment {
+        let inputs: Vec<(FragmentId, u8)> = inputs
+            .iter()
+            .map(|(transaction, index)| (FragmentId::hash_bytes(&[*transaction]), *index))
+            .collect();
+        spending(&inputs, value)
+    }
+
+    /// a transaction without output spending the outputs `(transaction
+    /// id, index)` of `value`
+    pub(in crate::fragment) fn spending(inputs: &[(FragmentId, u8)], value: u64) -> Fragment {
+        let inputs: Vec<Input> = inputs
+            .iter()
+            .map(|(transaction_id, index)| {
+                Input::from_utxo(UtxoPointer::new(*transaction_id, *index, Value(value)))
+            })
+            .collect();
+        let builder = TxBuilder::new().set_nopayload().set_ios(&inputs, &[]);
+        let sign_data = builder.get_auth_data_for_witness().hash();
+        let key = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let witnesses: Vec<Witness> = inputs
+            .iter()
+            .map(|_| Witness::new_utxo(&HeaderHash::hash_bytes(&[0]), &sign_data, |d| key.sign(d)))
+            .collect();
+        Fragment::Transaction(builder.set_witnesses(&witnesses).set_payload_auth(&()))
+    }
+
+    fn pool(max_entries: usize, min_fee_bump: Option<u64>) -> internal::Pool {
+        internal::Pool::new(max_entries, Duration::from_secs(3600), min_fee_bump)
+    }
+
+    #[test]
+    fn replacement_requires_the_fee_bump() {
+        run(async {
+            let mut pool = pool(10, Some(10));
+            let original = transaction(&[(0, 0)], 100);
+            let replacement = transaction(&[(0, 0), (1, 0)], 100);
+            assert!(pool.insert(original.clone(), Value(100)).is_some());
+            assert_eq!(pool.conflicts(&replacement), vec![original.id()]);
+
+            let replaced = pool.replace(replacement.clone(), Value(109), Value(100));
+            assert_eq!(replaced.err(), Some(Value(110)));
+            assert!(pool.contains(&original.id()));
+            assert!(pool.take_replaced().is_empty());
+
+            assert!(pool
+                .replace(replacement.clone(), Value(110), Value(100))
+                .is_ok());
+            assert!(!pool.contains(&original.id()));
+            assert!(pool.contains(&replacement.id()));
+            assert_eq!(
+                pool.take_replaced(),
+                vec![(original.id(), replacement.id())]
+            );
+            assert_eq!(pool.conflicts(&original), vec![replacement.id()]);
+        })
+    }
+
+    #[test]
+    fn replacement_in_a_full_pool() {
+        run(async {
+            let mut pool = pool(1, Some(1));
+            let original = transaction(&[(0, 0)], 100);
+            assert!(pool.insert(original.clone(), Value(100)).is_some());
+            assert!(pool
+                .insert(transaction(&[(1, 0)], 100), Value(100))
+                .is_none());
+
+            let replacement = transaction(&[(0, 0)], 200);
+            assert!(pool
+                .replace(replacement.clone(), Value(200), Value(100))
+                .is_ok());
+            assert!(pool.contains(&replacement.id()));
+        })
+    }
+
+    #[test]
+    fn no_conflicts_without_replacement() {
+        run(async {
+            let mut pool = pool(10, None);
+            let original = transaction(&[(0, 0)], 100);
+            let replacement = transaction(&[(0, 0)], 200);
+            assert!(pool.insert(original.clone(), Value(100)).is_some());
+            assert!(pool.conflicts(&replacement).is_empty());
+            assert!(pool.insert(replacement.clone(), Value(200)).is_some());
+            assert!(pool.contains(&original.id()));
+        })
+    }
+
+    #[test]
+    fn fee_rate_order_uses_the_checked_fee() {
+        run(async {
+            let mut by_fee = pool(10, None);
+            let mut by_time = pool(10, None);
+            let low = transaction(&[(0, 0)], 100);
+            // declares a large fee the ledger did not accept
+            let unchecked = transaction(&[(1, 0)], 1_000_000);
+            let high = transaction(&[(2, 0)], 100);
+            let fees = vec![
+                (low.clone(), Value(10)),
+                (unchecked.clone(), Value::zero()),
+                (high.clone(), Value(100)),
+            ];
+            for (fragment, fee) in fees {
+                assert!(by_fee.insert(fragment.clone(), fee).is_some());
+                assert!(by_time.insert(fragment, fee).is_some());
+            }
+
+            let by_fee: Vec<FragmentId> = std::iter::from_fn(|| by_fee.remove_highest_fee_rate())
+                .map(|fragment| fragment.id())
+                .collect();
+            assert_eq!(by_fee, vec![high.id(), low.id(), unchecked.id()]);
+            let by_time: Vec<FragmentId> = std::iter::from_fn(|| by_time.remove_oldest())
+                .map(|fragment| fragment.id())
+                .collect();
+            assert_eq!(by_time, vec![low.id(), unchecked.id(), high.id()]);
+        })
+    }
+
+    #[test]
+    fn replaced_fragments_are_logged() {
+        run(async {
+            let mut logs = Logs::new(10, Duration::from_secs(3600));
+            let original = transaction(&[(0, 0)], 100).id();
+            let replacement = transaction(&[(0, 0)], 200).id();
+            let log = FragmentLog::new(original.into(), FragmentOrigin::Rest);
+            logs.insert(log).await.unwrap();
+
+            mark_replaced(&mut logs, vec![(original, replacement)])
+                .await
+                .unwrap();
+            let log = logs.get(original).await.unwrap().unwrap();
+            assert_eq!(
+                log.status(),
+                &FragmentStatus::Replaced {
+                    by: replacement.into()
+                }
+            );
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    fragment::{Logs, Pool},
+    blockchain::Tip,
+    fragment::{Journal, Logs, Pool},
     intercom::{NetworkMsg, TransactionMsg},
     stats_counter::StatsCounter,
     utils::{
@@ -8,6 +9,8 @@ use crate::{
     },
 };
 use futures03::{compat::*, stream::StreamExt};
+use jormungandr_lib::interfaces::Mempool;
+use slog::Logger;
 use std::time::Duration;
 
 pub struct Process {
@@ -17,19 +20,24 @@ pub struct Process {
 }
 
 impl Process {
-    pub fn new(
-        pool_max_entries: usize,
-        pool_ttl: Duration,
-        logs_max_entries: usize,
-        logs_ttl: Duration,
-        garbage_collection_interval: Duration,
-        network_msg_box: MessageBox<NetworkMsg>,
-    ) -> Self {
-        let logs = Logs::new(logs_max_entries, logs_ttl);
+    pub fn new(settings: &Mempool, network_msg_box: MessageBox<NetworkMsg>) -> Self {
+        let logs = Logs::new(settings.log_max_entries.into(), settings.log_ttl.into());
+        let min_fee_bump = if settings.replace_by_fee {
+            Some(settings.min_fee_bump.into())
+        } else {
+            None
+        };
         Process {
-            pool: Pool::new(pool_max_entries, pool_ttl, logs.clone(), network_msg_box),
+            pool: Pool::new(
+                settings.pool_max_entries.into(),
+                settings.fragment_ttl.into(),
+                min_fee_bump,
+                logs.clone(),
+                network_msg_box,
+                settings.selection_strategy,
+            ),
             logs,
-            garbage_collection_interval,
+            garbage_collection_interval: settings.garbage_collection_interval.into(),
         }
     }
 
@@ -40,11 +48,18 @@ impl Process {
         &self.pool
     }
 
+    /// record the fragments accepted in the pool to `journal`, written by
+    /// a dedicated thread logging to `logger`
+    pub fn set_journal(&mut self, journal: Journal, logger: Logger) -> std::io::Result<()> {
+        self.pool.set_journal(journal, logger)
+    }
+
     pub async fn start(
         self,
         service_info: TokioServiceInfo,
         stats_counter: StatsCounter,
         input: MessageQueue<TransactionMsg>,
+        blockchain_tip: Tip,
     ) -> Result<(), ()> {
         self.start_pool_garbage_collector(&service_info);
         let mut input = input.compat();
@@ -66,7 +81,12 @@ impl Process {
 
                     self.pool
                         .clone()
-                        .insert_and_propagate_all(origin, txs, service_info.logger().clone())
+                        .insert_and_propagate_all(
+                            origin,
+                            txs,
+                            &blockchain_tip,
+                            service_info.logger().clone(),
+                        )
                         .await
                         .map(move |count| stats_counter.add_tx_recv_cnt(count))?;
                 }
@@ -91,6 +111,18 @@ impl Process {
                 let mut pool = pool.clone();
                 async move { pool.poll_purge().await }
             },
-        )
+        );
+
+        if self.pool.has_journal() {
+            let pool = self.pool.clone();
+            service_info.run_periodic_std(
+                "mempool journal compaction",
+                self.garbage_collection_interval,
+                move || {
+                    let pool = pool.clone();
+                    async move { pool.compact_journal().await }
+                },
+            )
+        }
     }
 }
@@ -1,11 +1,12 @@
 use super::logs::internal::Logs;
-use super::pool::internal::Pool;
+use super::pool::{internal::Pool, utxo_inputs};
 use crate::{
-    blockcfg::{BlockDate, Contents, ContentsBuilder, Ledger, LedgerParameters},
-    fragment::FragmentId,
+    blockcfg::{BlockDate, Contents, ContentsBuilder, Ledger, LedgerParameters, Value},
+    fragment::{Fragment, FragmentId},
 };
 use chain_core::property::Fragment as _;
-use jormungandr_lib::interfaces::FragmentStatus;
+use chain_impl_mockchain::ledger;
+use jormungandr_lib::interfaces::{FragmentRejectionCause, FragmentStatus};
 
 pub enum SelectionOutput {
     Commit { fragment_id: FragmentId },
@@ -54,37 +55,325 @@ impl FragmentSelectionAlgorithm for OldestFirst {
         logs: &mut Logs,
         pool: &mut Pool,
     ) {
-        let mut ledger_simulation = ledger.clone();
-
-        while let Some(fragment) = pool.remove_oldest() {
-            let id = fragment.id();
-            let fragment_raw = fragment.to_raw(); // TODO: replace everything to FragmentRaw in the node
-            let fragment_size = fragment_raw.size_bytes_plus_size() as u32;
-            let total_size = self.current_total_size + fragment_size;
-
-            if total_size <= ledger_params.block_content_max_size {
-                match ledger_simulation.apply_fragment(ledger_params, &fragment, block_date) {
-                    Ok(ledger_new) => {
-                        self.builder.push(fragment);
-                        ledger_simulation = ledger_new;
-                    }
-                    Err(error) => {
-                        use std::error::Error as _;
-                        let error = if let Some(source) = error.source() {
-                            format!("{}: {}", error, source)
-                        } else {
-                            error.to_string()
-                        };
-                        logs.modify(&id.into(), FragmentStatus::Rejected { reason: error })
-                    }
-                }
+        select_in_order(
+            &mut self.builder,
+            &mut self.current_total_size,
+            ledger_params.block_content_max_size,
+            logs,
+            pool,
+            |pool| pool.remove_oldest(),
+            simulate(ledger, ledger_params, block_date),
+        )
+    }
+}
+
+/// select the fragments paying the highest fee per byte first, the fee
+/// being the one checked against the ledger when the fragment entered the
+/// pool
+pub struct HighestFeeRateFirst {
+    builder: ContentsBuilder,
+    current_total_size: u32,
+}
+
+impl HighestFeeRateFirst {
+    pub fn new() -> Self {
+        HighestFeeRateFirst {
+            builder: ContentsBuilder::new(),
+            current_total_size: 0,
+        }
+    }
+}
+
+impl FragmentSelectionAlgorithm for HighestFeeRateFirst {
+    fn finalize(self) -> Contents {
+        self.builder.into()
+    }
+
+    fn select(
+        &mut self,
+        ledger: &Ledger,
+        ledger_params: &LedgerParameters,
+        block_date: BlockDate,
+        logs: &mut Logs,
+        pool: &mut Pool,
+    ) {
+        select_in_order(
+            &mut self.builder,
+            &mut self.current_total_size,
+            ledger_params.block_content_max_size,
+            logs,
+            pool,
+            |pool| pool.remove_highest_fee_rate(),
+            simulate(ledger, ledger_params, block_date),
+        )
+    }
+}
 
-                self.current_total_size = total_size;
+/// apply the fragments in turn to a simulation of `ledger`
+fn simulate<'a>(
+    ledger: &Ledger,
+    ledger_params: &'a LedgerParameters,
+    block_date: BlockDate,
+) -> impl FnMut(&Fragment) -> Result<(), ledger::Error> + 'a {
+    let mut ledger_simulation = ledger.clone();
+    move |fragment| {
+        ledger_simulation =
+            ledger_simulation.apply_fragment(ledger_params, fragment, block_date)?;
+        Ok(())
+    }
+}
+
+/// fill the block with the fragments in the order they are returned by
+/// `next_fragment`, until the block is full or there is no more fragments.
+///
+/// A fragment `apply` refuses while it spends the outputs of a fragment
+/// still pending is deferred until that fragment is applied, the
+/// fragments still deferred at the end of the selection go back to the
+/// pool.
+fn select_in_order<F, A>(
+    builder: &mut ContentsBuilder,
+    current_total_size: &mut u32,
+    max_total_size: u32,
+    logs: &mut Logs,
+    pool: &mut Pool,
+    mut next_fragment: F,
+    mut apply: A,
+) where
+    F: FnMut(&mut Pool) -> Option<Fragment>,
+    A: FnMut(&Fragment) -> Result<(), ledger::Error>,
+{
+    let mut deferred = Vec::new();
 
-                if total_size == ledger_params.block_content_max_size {
-                    break;
+    while let Some(fragment) = next_fragment(pool) {
+        let id = fragment.id();
+        let total_size = *current_total_size + fragment_size(&fragment);
+
+        if total_size <= max_total_size {
+            match apply(&fragment) {
+                Ok(()) => {
+                    builder.push(fragment);
+                    *current_total_size = total_size;
+                    apply_deferred(
+                        builder,
+                        current_total_size,
+                        max_total_size,
+                        &mut deferred,
+                        &mut apply,
+                    );
+                }
+                Err(_) if spends_pending(pool, &deferred, &fragment) => deferred.push(fragment),
+                Err(error) => {
+                    logs.modify(&id.into(), rejected(&error));
+                    *current_total_size = total_size;
                 }
             }
+
+            if *current_total_size == max_total_size {
+                break;
+            }
+        }
+    }
+
+    for fragment in deferred {
+        // the fee of a fragment spending the outputs of a pending fragment
+        // cannot be checked against the ledger
+        pool.insert(fragment, Value::zero());
+    }
+}
+
+/// apply the deferred fragments until none of them applies
+fn apply_deferred<A>(
+    builder: &mut ContentsBuilder,
+    current_total_size: &mut u32,
+    max_total_size: u32,
+    deferred: &mut Vec<Fragment>,
+    apply: &mut A,
+) where
+    A: FnMut(&Fragment) -> Result<(), ledger::Error>,
+{
+    loop {
+        let total_size = *current_total_size;
+        let position = deferred.iter().position(|fragment| {
+            total_size + fragment_size(fragment) <= max_total_size && apply(fragment).is_ok()
+        });
+        match position {
+            Some(position) => {
+                let fragment = deferred.remove(position);
+                *current_total_size += fragment_size(&fragment);
+                builder.push(fragment);
+            }
+            None => break,
+        }
+    }
+}
+
+/// whether the fragment spends the outputs of a fragment still in the
+/// pool or deferred
+fn spends_pending(pool: &Pool, deferred: &[Fragment], fragment: &Fragment) -> bool {
+    utxo_inputs(fragment).iter().any(|(transaction_id, _)| {
+        pool.contains(transaction_id)
+            || deferred.iter().any(|parent| &parent.id() == transaction_id)
+    })
+}
+
+fn fragment_size(fragment: &Fragment) -> u32 {
+    let fragment_raw = fragment.to_raw(); // TODO: replace everything to FragmentRaw in the node
+    fragment_raw.size_bytes_plus_size() as u32
+}
+
+/// the status of a fragment the ledger refused with `error`
+pub(super) fn rejected(error: &ledger::Error) -> FragmentStatus {
+    use std::error::Error as _;
+    let reason = if let Some(source) = error.source() {
+        format!("{}: {}", error, source)
+    } else {
+        error.to_string()
+    };
+    FragmentStatus::Rejected {
+        reason,
+        cause: rejection_cause(error),
+    }
+}
+
+fn rejection_cause(error: &ledger::Error) -> FragmentRejectionCause {
+    match error {
+        ledger::Error::UtxoInvalidSignature { .. }
+        | ledger::Error::AccountInvalidSignature { .. }
+        | ledger::Error::MultisigInvalidSignature { .. } => {
+            FragmentRejectionCause::InvalidSignature
+        }
+        ledger::Error::NotBalanced { .. } => FragmentRejectionCause::NotBalanced,
+        _ => FragmentRejectionCause::Ledger,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pool::tests::spending;
+    use super::*;
+    use chain_impl_mockchain::utxo;
+    use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin};
+    use std::{collections::HashSet, time::Duration};
+    use tokio_compat::runtime;
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        rt.block_on_std(future)
+    }
+
+    /// a ledger in which the fragments apply once all the transactions
+    /// they spend are applied
+    fn simulated_ledger(
+        known: &[FragmentId],
+    ) -> impl FnMut(&Fragment) -> Result<(), ledger::Error> {
+        let mut applied: HashSet<FragmentId> = known.iter().cloned().collect();
+        move |fragment| {
+            let inputs = utxo_inputs(fragment);
+            if inputs
+                .iter()
+                .all(|(transaction_id, _)| applied.contains(transaction_id))
+            {
+                applied.insert(fragment.id());
+                Ok(())
+            } else {
+                Err(ledger::Error::UtxoError(utxo::Error::TransactionNotFound))
+            }
         }
     }
+
+    /// the fragments selected from a pool holding a child paying more than
+    /// its parent, the child being the oldest. The parent applies only if
+    /// `parent_applies`.
+    fn select_child_first<F>(
+        parent_in_pool: bool,
+        parent_applies: bool,
+        next_fragment: F,
+    ) -> (Vec<FragmentId>, Pool, Logs, Fragment)
+    where
+        F: FnMut(&mut Pool) -> Option<Fragment>,
+    {
+        let genesis = FragmentId::hash_bytes(&[0]);
+        let parent = spending(&[(genesis, 0)], 100);
+        let child = spending(&[(parent.id(), 0)], 100);
+        let mut pool = Pool::new(10, Duration::from_secs(3600), None);
+        let mut logs = Logs::new(10, Duration::from_secs(3600));
+        assert!(pool.insert(child.clone(), Value(100)).is_some());
+        logs.insert(FragmentLog::new(child.id().into(), FragmentOrigin::Rest));
+        if parent_in_pool {
+            assert!(pool.insert(parent.clone(), Value(10)).is_some());
+        }
+
+        let mut builder = ContentsBuilder::new();
+        select_in_order(
+            &mut builder,
+            &mut 0,
+            u32::max_value(),
+            &mut logs,
+            &mut pool,
+            next_fragment,
+            simulated_ledger(if parent_applies { &[genesis] } else { &[] }),
+        );
+        let contents: Contents = builder.into();
+        let selected = contents.iter().map(|fragment| fragment.id()).collect();
+        (selected, pool, logs, child)
+    }
+
+    #[test]
+    fn oldest_first_defers_the_child_of_a_pending_fragment() {
+        run(async {
+            let (selected, pool, logs, child) =
+                select_child_first(true, true, |pool| pool.remove_oldest());
+            assert_eq!(selected.len(), 2);
+            assert_eq!(selected[1], child.id());
+            assert!(!pool.contains(&child.id()));
+            assert!(logs.get(&child.id().into()).unwrap().is_pending());
+        })
+    }
+
+    #[test]
+    fn highest_fee_rate_first_defers_the_child_of_a_pending_fragment() {
+        run(async {
+            let (selected, pool, _, child) =
+                select_child_first(true, true, |pool| pool.remove_highest_fee_rate());
+            assert_eq!(selected.len(), 2);
+            assert_eq!(selected[1], child.id());
+            assert!(!pool.contains(&child.id()));
+        })
+    }
+
+    #[test]
+    fn child_of_an_unknown_fragment_is_rejected() {
+        run(async {
+            let (selected, pool, logs, child) =
+                select_child_first(false, true, |pool| pool.remove_oldest());
+            assert!(selected.is_empty());
+            assert!(!pool.contains(&child.id()));
+            assert!(logs.get(&child.id().into()).unwrap().is_rejected());
+        })
+    }
+
+    #[test]
+    fn deferred_fragments_go_back_to_the_pool() {
+        run(async {
+            let (selected, pool, logs, child) =
+                select_child_first(true, false, |pool| pool.remove_oldest());
+            assert!(selected.is_empty());
+            assert!(pool.contains(&child.id()));
+            assert!(logs.get(&child.id().into()).unwrap().is_pending());
+        })
+    }
+
+    #[test]
+    fn rejection_causes() {
+        let not_balanced = ledger::Error::NotBalanced {
+            inputs: Value(10),
+            outputs: Value(20),
+        };
+        assert_eq!(
+            rejection_cause(&not_balanced),
+            FragmentRejectionCause::NotBalanced
+        );
+        let not_found = ledger::Error::UtxoError(utxo::Error::TransactionNotFound);
+        assert_eq!(rejection_cause(&not_found), FragmentRejectionCause::Ledger);
+    }
 }
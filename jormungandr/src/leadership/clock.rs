@@ -0,0 +1,133 @@
+//! check of the system clock against NTP servers
+//!
+//! The offset of the system clock is measured with a SNTP request (RFC 4330)
+//! to the configured servers, the first server to answer is used. The
+//! leadership module refuses to run the events while the drift of the
+//! clock is larger than a slot: the blocks would be dated in the wrong
+//! slot.
+
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// seconds from the NTP epoch (1900) to the UNIX epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+const NTP_PACKET_SIZE: usize = 48;
+
+const NTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// drift of the system clock, from the latest check. `None` until the
+/// clock has been checked, or if the latest check failed.
+#[derive(Clone, Default)]
+pub struct ClockDrift(Arc<RwLock<Option<Duration>>>);
+
+impl ClockDrift {
+    pub fn get(&self) -> Option<Duration> {
+        *self.0.read().unwrap()
+    }
+
+    fn set(&self, drift: Duration) {
+        *self.0.write().unwrap() = Some(drift);
+    }
+
+    /// forget the drift, it cannot be trusted once a check has failed
+    pub fn clear(&self) {
+        *self.0.write().unwrap() = None;
+    }
+}
+
+/// offset of the system clock: how much the system clock is ahead of the
+/// server (`ahead` is true) or behind it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockOffset {
+    pub drift: Duration,
+    pub ahead: bool,
+}
+
+/// measure the offset of the system clock against the first of the
+/// `servers` (`host:port`) to answer, and record its drift in `drift`.
+/// The requests are blocking, this is meant to run in a blocking task.
+pub fn check_clock(servers: &[String], drift: &ClockDrift) -> io::Result<ClockOffset> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no NTP server configured");
+    for server in servers {
+        match sntp_offset(server) {
+            Ok(offset) => {
+                drift.set(offset.drift);
+                return Ok(offset);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn sntp_offset(server: &str) -> io::Result<ClockOffset> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(NTP_TIMEOUT))?;
+    socket.connect(server)?;
+
+    let mut request = [0u8; NTP_PACKET_SIZE];
+    // leap indicator 0, version 3, mode 3 (client)
+    request[0] = 0x1b;
+    let sent_at = SystemTime::now();
+    socket.send(&request)?;
+
+    let mut response = [0u8; NTP_PACKET_SIZE];
+    let size = socket.recv(&mut response)?;
+    let received_at = SystemTime::now();
+    if size < NTP_PACKET_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "truncated NTP response",
+        ));
+    }
+
+    let server_received_at = ntp_timestamp(&response[32..40])?;
+    let server_sent_at = ntp_timestamp(&response[40..48])?;
+
+    // offset = ((server_received_at - sent_at) + (server_sent_at - received_at)) / 2
+    let local = as_micros(sent_at) + as_micros(received_at);
+    let server = as_micros(server_received_at) + as_micros(server_sent_at);
+    let offset = (server - local) / 2;
+    Ok(ClockOffset {
+        drift: Duration::from_micros(offset.abs() as u64),
+        ahead: offset < 0,
+    })
+}
+
+fn ntp_timestamp(bytes: &[u8]) -> io::Result<SystemTime> {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+    let unix_seconds = seconds
+        .checked_sub(NTP_UNIX_OFFSET)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "NTP timestamp before 1970"))?;
+    let nanos = (fraction * 1_000_000_000) >> 32;
+    Ok(UNIX_EPOCH + Duration::new(unix_seconds, nanos as u32))
+}
+
+fn as_micros(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_micros() as i128,
+        Err(e) => -(e.duration().as_micros() as i128),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntp_timestamp_to_system_time() {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&((NTP_UNIX_OFFSET + 10) as u32).to_be_bytes());
+        bytes[4..].copy_from_slice(&(1u32 << 31).to_be_bytes());
+        assert_eq!(
+            ntp_timestamp(&bytes).unwrap(),
+            UNIX_EPOCH + Duration::from_millis(10_500)
+        );
+    }
+}
@@ -1,13 +1,118 @@
-use futures03::future::poll_fn;
+use crate::blockcfg::Epoch;
+use futures03::{
+    future::poll_fn,
+    stream::{self, Stream},
+};
 pub use jormungandr_lib::interfaces::LeadershipLogStatus;
-use jormungandr_lib::interfaces::{LeadershipLog, LeadershipLogId};
-use std::{sync::Arc, time::Duration};
-use tokio02::{sync::RwLock, time};
+use jormungandr_lib::{
+    interfaces::{EnclaveLeaderId, LeadershipLog, LeadershipLogId},
+    time::SystemTime,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    ops::RangeBounds,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio02::{
+    sync::{broadcast, RwLock},
+    time,
+};
+
+/// number of updates kept for the subscribers, a subscriber lagging
+/// further behind misses the oldest updates.
+const UPDATES_CAPACITY: usize = 256;
+
+/// update of a leadership log, sent to the subscribers of the [`Logs`]
+///
+/// [`Logs`]: ./struct.Logs.html
+pub type LogUpdate = (LeadershipLogId, LeadershipLogStatus);
 
 /// all leadership logs, allow for following up on the different entity
 /// of the blockchain
 #[derive(Clone)]
-pub struct Logs(Arc<RwLock<internal::Logs>>);
+pub struct Logs(Arc<RwLock<internal::Logs>>, broadcast::Sender<LogUpdate>);
+
+/// what to do when inserting a log of the same leader and date as a log
+/// that has not finished yet. The logs of different leaders scheduled for
+/// the same date are never duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// replace the existing log by the new one
+    Replace,
+    /// do not insert the new log, the returned handle updates the
+    /// existing log
+    UseExisting,
+}
+
+/// when to reset the expiration of a log to now + `ttl`.
+///
+/// On insertion, the log expires at its scheduled time + `ttl` (or now +
+/// `ttl` if it is scheduled in the past). The first reset replaces this
+/// expiration, even if it was later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtlReset {
+    /// on every change of the log (`mark_wake`, `set_status` and
+    /// `mark_finished`): a log keeping being updated never expires
+    EveryChange,
+    /// on the first change of the log only, the later changes leave the
+    /// expiration untouched. This keeps the retained window of the logs to
+    /// about `ttl`.
+    Once,
+}
+
+/// options of the [`Logs`]
+///
+/// [`Logs`]: ./struct.Logs.html
+#[derive(Debug, Clone)]
+pub struct LogsOptions {
+    pub duplicates: DuplicatePolicy,
+    pub ttl_reset: TtlReset,
+    /// keep the logs scheduled in the last `keep_epochs` epochs (the
+    /// latest epoch of the logs included) even once their TTL has expired.
+    /// `0` keeps the logs for their TTL only.
+    pub keep_epochs: u32,
+}
+
+impl Default for LogsOptions {
+    fn default() -> Self {
+        LogsOptions {
+            duplicates: DuplicatePolicy::Replace,
+            ttl_reset: TtlReset::EveryChange,
+            keep_epochs: 0,
+        }
+    }
+}
+
+/// summary of the leadership logs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LeadershipStats {
+    /// the logs of the events that have not started yet
+    pub pending: usize,
+    /// the logs of the events that have started but not finished yet
+    pub wakened: usize,
+    /// the logs of the events that have finished, whatever their status
+    pub finished: usize,
+    /// the logs with a `Rejected` status
+    pub rejected: usize,
+    /// the logs with a `Block` status
+    pub blocks: usize,
+    /// the logs with a `Missed` status
+    pub missed: usize,
+    /// scheduled time of the earliest scheduled log
+    pub earliest_scheduled_at: Option<SystemTime>,
+    /// scheduled time of the latest scheduled log
+    pub latest_scheduled_at: Option<SystemTime>,
+}
+
+/// the leadership log does not exist (anymore). A log may be purged once
+/// its TTL has expired while a handle to it is still in use.
+#[derive(Debug, Error)]
+#[error("leadership log not found, it may have been purged already")]
+pub struct LogNotFound;
 
 /// leadership log handle. will allow to update the status of the log
 /// without having to hold the [`Logs`]
@@ -22,32 +127,38 @@ pub struct LeadershipLogHandle {
 impl LeadershipLogHandle {
     /// make a leadership event as triggered.
     ///
-    /// This should be called when the leadership event has started.
+    /// This should be called when the leadership event has started, once.
     ///
-    /// # panic
+    /// # Errors
     ///
-    /// on non-release build, this function will panic if the log was already
-    /// marked as awaken.
+    /// fails with `LogNotFound` if the log has already been purged or
+    /// removed.
     ///
-    pub async fn mark_wake(&self) {
+    pub async fn mark_wake(&self) -> Result<(), LogNotFound> {
         self.logs.mark_wake(self.internal_id).await
     }
 
-    pub async fn set_status(&self, status: LeadershipLogStatus) {
+    pub async fn set_status(&self, status: LeadershipLogStatus) -> Result<(), LogNotFound> {
         self.logs.set_status(self.internal_id, status).await
     }
 
+    /// set the status of the log as rejected for the given `reason`.
+    pub async fn mark_rejected(&self, reason: String) -> Result<(), LogNotFound> {
+        self.set_status(LeadershipLogStatus::Rejected { reason })
+            .await
+    }
+
     /// make a leadership event as finished.
     ///
     /// This should be called when the leadership event has finished its
-    /// scheduled action.
+    /// scheduled action, once.
     ///
-    /// # panic
+    /// # Errors
     ///
-    /// on non-release build, this function will panic if the log was already
-    /// marked as finished.
+    /// fails with `LogNotFound` if the log has already been purged or
+    /// removed.
     ///
-    pub async fn mark_finished(&self) {
+    pub async fn mark_finished(&self) -> Result<(), LogNotFound> {
         self.logs.mark_finished(self.internal_id).await
     }
 }
@@ -59,9 +170,39 @@ impl Logs {
     /// the `ttl` can be any sensible value the user will see appropriate. The log will
     /// live at least its scheduled time + `ttl`.
     ///
-    /// On changes, the log's TTL will be reset to this `ttl`.
+    /// On changes, the log's TTL will be reset to this `ttl`, see
+    /// `TtlReset::EveryChange`.
     pub fn new(ttl: Duration) -> Self {
-        Logs(Arc::new(RwLock::new(internal::Logs::new(ttl))))
+        Self::new_with_options(ttl, LogsOptions::default())
+    }
+
+    /// same as `new` but with non default `options`
+    pub fn new_with_options(ttl: Duration, options: LogsOptions) -> Self {
+        let (updates, _) = broadcast::channel(UPDATES_CAPACITY);
+        Logs(
+            Arc::new(RwLock::new(internal::Logs::new(
+                ttl,
+                options,
+                updates.clone(),
+            ))),
+            updates,
+        )
+    }
+
+    /// stream of the updates of the logs (`mark_wake`, `set_status` and
+    /// `mark_finished`) from the moment of the subscription. A subscriber
+    /// too slow to keep up misses updates, it never slows down the updates
+    /// of the logs.
+    pub fn subscribe(&self) -> impl Stream<Item = LogUpdate> {
+        stream::unfold(self.1.subscribe(), |mut updates| async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => return Some((update, updates)),
+                    Err(broadcast::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::RecvError::Closed) => return None,
+                }
+            }
+        })
     }
 
     pub async fn insert(&self, log: LeadershipLog) -> Result<LeadershipLogHandle, ()> {
@@ -73,22 +214,86 @@ impl Logs {
         })
     }
 
-    async fn mark_wake(&self, leadership_log_id: LeadershipLogId) {
+    /// insert all the `logs` at once, returns the handles of the logs in the
+    /// order of `logs`. Same as calling `insert` for every log but the
+    /// logs are locked only once.
+    pub async fn insert_all(&self, logs: Vec<LeadershipLog>) -> Vec<LeadershipLogHandle> {
+        let mut inner = self.0.write().await;
+        logs.into_iter()
+            .map(|log| LeadershipLogHandle {
+                internal_id: inner.insert(log),
+                logs: self.clone(),
+            })
+            .collect()
+    }
+
+    async fn mark_wake(&self, leadership_log_id: LeadershipLogId) -> Result<(), LogNotFound> {
         let inner = self.0.clone();
-        inner.write().await.mark_wake(&leadership_log_id.into());
+        inner.write().await.mark_wake(&leadership_log_id.into())
     }
 
-    async fn set_status(&self, leadership_log_id: LeadershipLogId, status: LeadershipLogStatus) {
+    async fn set_status(
+        &self,
+        leadership_log_id: LeadershipLogId,
+        status: LeadershipLogStatus,
+    ) -> Result<(), LogNotFound> {
         let inner = self.0.clone();
         inner
             .write()
             .await
-            .set_status(&leadership_log_id.into(), status);
+            .set_status(&leadership_log_id.into(), status)
+    }
+
+    async fn mark_finished(&self, leadership_log_id: LeadershipLogId) -> Result<(), LogNotFound> {
+        let inner = self.0.clone();
+        inner.write().await.mark_finished(&leadership_log_id.into())
     }
 
-    async fn mark_finished(&self, leadership_log_id: LeadershipLogId) {
+    /// change the `ttl` of the logs. The expiration of the logs already
+    /// present is reset to now + `ttl`, the new logs use the new `ttl`.
+    pub async fn set_ttl(&self, ttl: Duration) {
         let inner = self.0.clone();
-        inner.write().await.mark_finished(&leadership_log_id.into());
+        inner.write().await.set_ttl(ttl);
+    }
+
+    /// set the status of the pending logs not woken `grace` after their
+    /// scheduled time to `Missed`, the subscribers are notified as for
+    /// `set_status`. Returns the logs newly marked as missed.
+    ///
+    /// The miss is final: the later updates of a missed log, for example
+    /// from its event running late, are ignored so the miss is counted
+    /// and notified only once.
+    pub async fn mark_missed(&self, grace: Duration) -> Vec<LeadershipLog> {
+        let inner = self.0.clone();
+        let mut guard = inner.write().await;
+        guard.mark_missed(grace)
+    }
+
+    /// set the status of the logs of `leader` not woken yet to `Rejected`
+    /// with the given `reason`, for example once the leader is removed from
+    /// the enclave. Returns the number of rejected logs.
+    pub async fn reject_pending_of(&self, leader: EnclaveLeaderId, reason: String) -> usize {
+        let inner = self.0.clone();
+        let mut guard = inner.write().await;
+        guard.reject_pending_of(leader, reason)
+    }
+
+    /// remove the log without waiting for its TTL to expire. Returns the
+    /// removed log, `None` if there is no such log (already removed or
+    /// purged).
+    pub async fn remove(&self, leadership_log_id: LeadershipLogId) -> Option<LeadershipLog> {
+        let inner = self.0.clone();
+        let mut guard = inner.write().await;
+        guard.remove(&leadership_log_id)
+    }
+
+    /// remove all the logs expiring before `cutoff` without waiting for
+    /// their TTL to expire, the logs kept by `LogsOptions::keep_epochs`
+    /// are removed too. Returns the number of removed logs.
+    pub async fn purge_before(&self, cutoff: Instant) -> usize {
+        let inner = self.0.clone();
+        let mut guard = inner.write().await;
+        guard.purge_before(cutoff)
     }
 
     pub async fn poll_purge(&mut self) -> Result<(), time::Error> {
@@ -102,39 +307,265 @@ impl Logs {
         let guard = inner.read().await;
         guard.logs().cloned().collect()
     }
+
+    /// get the log of the given id, `None` if there is no such log or if
+    /// it has already been purged.
+    pub async fn get(&self, leadership_log_id: LeadershipLogId) -> Option<LeadershipLog> {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+        guard.get(&leadership_log_id).cloned()
+    }
+
+    /// get the logs ordered by scheduled time, the logs scheduled at the
+    /// same time are ordered by their id.
+    pub async fn logs_sorted(&self) -> Vec<LeadershipLog> {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+        guard.logs_sorted().into_iter().cloned().collect()
+    }
+
+    /// get at most `limit` logs starting from the `offset`th log, in the
+    /// order of `logs_sorted`. Returns an empty list if `offset` is past
+    /// the last log.
+    pub async fn logs_page(&self, offset: usize, limit: usize) -> Vec<LeadershipLog> {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+        guard
+            .logs_sorted()
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// write a snapshot of the logs as a JSON array to `writer`. The logs
+    /// are written one by one without being collected first.
+    pub async fn export_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+
+        writer.write_all(b"[")?;
+        for (index, log) in guard.logs().enumerate() {
+            if index > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut writer, log)?;
+        }
+        writer.write_all(b"]")?;
+        writer.flush()
+    }
+
+    /// insert the logs of a JSON array written by `export_json`, returns
+    /// the number of logs read. The logs keep their status, the logs that
+    /// were pending when exported stay pending.
+    pub async fn import_json<R: Read>(&self, reader: R) -> io::Result<usize> {
+        let logs: Vec<LeadershipLog> = serde_json::from_reader(reader)?;
+        let count = logs.len();
+        let mut inner = self.0.write().await;
+        for log in logs {
+            inner.insert(log);
+        }
+        Ok(count)
+    }
+
+    /// write a snapshot of the logs to the file at `path`. The snapshot is
+    /// written to a temporary file first, the file at `path` is replaced
+    /// only once the snapshot is complete.
+    pub async fn save(&self, path: PathBuf) -> io::Result<()> {
+        let mut snapshot = Vec::new();
+        self.export_json(&mut snapshot).await?;
+        tokio02::task::spawn_blocking(move || {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, snapshot)?;
+            std::fs::rename(tmp, path)
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+    }
+
+    /// compute the summary of the logs
+    pub async fn stats(&self) -> LeadershipStats {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+        let mut stats = LeadershipStats::default();
+        for log in guard.logs() {
+            stats.add(log);
+        }
+        stats
+    }
+
+    /// compute the summary of the logs of every epoch, keyed by the epoch
+    /// of the scheduled date of the logs
+    pub async fn logs_by_epoch(&self) -> BTreeMap<Epoch, LeadershipStats> {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+        let mut epochs = BTreeMap::new();
+        for log in guard.logs() {
+            let epoch = log.scheduled_at_date().as_ref().epoch;
+            epochs
+                .entry(epoch)
+                .or_insert_with(LeadershipStats::default)
+                .add(log);
+        }
+        epochs
+    }
+
+    /// get the logs scheduled in the time window `range`, for example
+    /// `from..to` for the window starting at `from` (included) and ending
+    /// at `to` (excluded), in the order of `logs_sorted`. The logs already
+    /// purged are not returned.
+    pub async fn logs_in_range<R>(&self, range: R) -> Vec<LeadershipLog>
+    where
+        R: RangeBounds<SystemTime>,
+    {
+        let inner = self.0.clone();
+        let guard = inner.read().await;
+        guard
+            .logs_sorted()
+            .into_iter()
+            .filter(|log| range.contains(log.scheduled_at_time()))
+            .cloned()
+            .collect()
+    }
+
+    /// rate of the missed leadership events over the retained logs, see
+    /// `LeadershipStats::missed_rate`.
+    pub async fn missed_rate(&self) -> f64 {
+        self.stats().await.missed_rate()
+    }
+}
+
+impl LeadershipStats {
+    /// rate of the missed leadership events: the logs with a `Rejected`
+    /// or `Missed` status are missed, the logs with a `Block` status are
+    /// produced. The `Pending` logs are not counted as their outcome is not
+    /// known yet, nor the `DryRun` logs whose block was not submitted.
+    ///
+    /// Returns `0.0` if no log is either missed or produced.
+    pub fn missed_rate(&self) -> f64 {
+        let missed = self.rejected + self.missed;
+        let settled = missed + self.blocks;
+        if settled == 0 {
+            0.0
+        } else {
+            missed as f64 / settled as f64
+        }
+    }
+
+    fn add(&mut self, log: &LeadershipLog) {
+        if log.finished_at_time().is_some() {
+            self.finished += 1;
+        } else if log.wake_at_time().is_some() {
+            self.wakened += 1;
+        } else {
+            self.pending += 1;
+        }
+
+        match log.status() {
+            LeadershipLogStatus::Pending => (),
+            LeadershipLogStatus::Rejected { .. } => self.rejected += 1,
+            LeadershipLogStatus::Block { .. } => self.blocks += 1,
+            LeadershipLogStatus::Missed => self.missed += 1,
+            // neither produced nor missed, the node did not try to submit
+            LeadershipLogStatus::DryRun { .. } => (),
+        }
+
+        let scheduled_at = *log.scheduled_at_time();
+        self.earliest_scheduled_at = Some(
+            self.earliest_scheduled_at
+                .map_or(scheduled_at, |earliest| earliest.min(scheduled_at)),
+        );
+        self.latest_scheduled_at = Some(
+            self.latest_scheduled_at
+                .map_or(scheduled_at, |latest| latest.max(scheduled_at)),
+        );
+    }
 }
 
 pub(super) mod internal {
-    use super::{LeadershipLog, LeadershipLogId, LeadershipLogStatus};
+    use super::{
+        DuplicatePolicy, EnclaveLeaderId, Epoch, LeadershipLog, LeadershipLogId,
+        LeadershipLogStatus, LogNotFound, LogUpdate, LogsOptions, TtlReset,
+    };
     use futures03::{
         task::{Context, Poll},
         Stream,
     };
     use std::{
-        collections::HashMap,
+        collections::{HashMap, HashSet},
         pin::Pin,
         time::{Duration, Instant},
     };
-    use tokio02::time::{self, delay_queue, DelayQueue, Instant as TokioInstant};
+    use tokio02::{
+        sync::broadcast,
+        time::{self, delay_queue, DelayQueue, Instant as TokioInstant},
+    };
 
     pub struct Logs {
         entries: HashMap<LeadershipLogId, (LeadershipLog, delay_queue::Key)>,
         expirations: Pin<Box<DelayQueue<LeadershipLogId>>>,
         ttl: Duration,
+        options: LogsOptions,
+        updates: broadcast::Sender<LogUpdate>,
+        // the logs whose expiration has already been reset by a change
+        reset: HashSet<LeadershipLogId>,
+        // the latest epoch of the logs inserted
+        latest_epoch: Option<Epoch>,
     }
 
     impl Logs {
-        pub fn new(ttl: Duration) -> Self {
+        pub fn new(
+            ttl: Duration,
+            options: LogsOptions,
+            updates: broadcast::Sender<LogUpdate>,
+        ) -> Self {
             Logs {
                 entries: HashMap::new(),
                 expirations: Box::pin(DelayQueue::new()),
                 ttl,
+                options,
+                updates,
+                reset: HashSet::new(),
+                latest_epoch: None,
             }
         }
 
         pub fn insert(&mut self, log: LeadershipLog) -> LeadershipLogId {
             let id = log.leadership_log_id();
+            let epoch = log.scheduled_at_date().as_ref().epoch;
+
+            if self.options.duplicates == DuplicatePolicy::UseExisting {
+                let unfinished = self
+                    .entries
+                    .get(&id)
+                    .map_or(false, |(log, _)| log.finished_at_time().is_none());
+                if unfinished {
+                    return id;
+                }
+            }
+            // a finished log with the same id is replaced, whatever the
+            // policy
+            self.remove(&id);
+
+            let ttl = self.initial_ttl(&log);
+            let delay = self.expirations.insert(id.clone(), ttl);
 
+            self.entries.insert(id, (log, delay));
+            self.latest_epoch = self.latest_epoch.max(Some(epoch));
+            id
+        }
+
+        /// the log is in one of the last `keep_epochs` epochs
+        fn is_retained(&self, log: &LeadershipLog) -> bool {
+            let epoch = log.scheduled_at_date().as_ref().epoch;
+            self.latest_epoch.map_or(false, |latest| {
+                latest.saturating_sub(epoch) < self.options.keep_epochs
+            })
+        }
+
+        /// the log lives at least until its scheduled time + `ttl`
+        fn initial_ttl(&self, log: &LeadershipLog) -> Duration {
             let now = std::time::SystemTime::now();
             let minimal_duration = if &now < log.scheduled_at_time().as_ref() {
                 log.scheduled_at_time()
@@ -144,56 +575,163 @@ pub(super) mod internal {
             } else {
                 Duration::from_secs(0)
             };
-            let ttl = minimal_duration.checked_add(self.ttl).unwrap_or(self.ttl);
-
-            let delay = self.expirations.insert(id.clone(), ttl);
+            minimal_duration.checked_add(self.ttl).unwrap_or(self.ttl)
+        }
 
-            self.entries.insert(id, (log, delay));
-            id
+        pub fn set_ttl(&mut self, ttl: Duration) {
+            self.ttl = ttl;
+            let expire_at = TokioInstant::from_std(Instant::now() + ttl);
+            for (_, key) in self.entries.values() {
+                self.expirations.reset_at(key, expire_at);
+            }
         }
 
-        pub fn mark_wake(&mut self, leadership_log_id: &LeadershipLogId) {
-            if let Some((ref mut log, ref key)) = self.entries.get_mut(leadership_log_id) {
-                log.mark_wake();
+        /// apply `f` to the log, reset its TTL according to the
+        /// `TtlReset` option and notify the subscribers of its new status.
+        /// A missed log is left as is, see `Logs::mark_missed`.
+        fn update<F>(
+            &mut self,
+            leadership_log_id: &LeadershipLogId,
+            f: F,
+        ) -> Result<(), LogNotFound>
+        where
+            F: FnOnce(&mut LeadershipLog),
+        {
+            let (log, key) = self.entries.get_mut(leadership_log_id).ok_or(LogNotFound)?;
+            if log.status() == &LeadershipLogStatus::Missed {
+                return Ok(());
+            }
+            f(log);
 
+            if self.options.ttl_reset == TtlReset::EveryChange
+                || self.reset.insert(*leadership_log_id)
+            {
                 self.expirations
                     .reset_at(key, TokioInstant::from_std(Instant::now() + self.ttl));
-            } else {
-                unimplemented!()
             }
+            // there may be no subscriber, this is not an error
+            let _ = self
+                .updates
+                .send((*leadership_log_id, log.status().clone()));
+            Ok(())
+        }
+
+        pub fn mark_wake(
+            &mut self,
+            leadership_log_id: &LeadershipLogId,
+        ) -> Result<(), LogNotFound> {
+            self.update(leadership_log_id, |log| log.mark_wake())
         }
 
         pub fn set_status(
             &mut self,
             leadership_log_id: &LeadershipLogId,
             status: LeadershipLogStatus,
-        ) {
-            if let Some((ref mut log, ref key)) = self.entries.get_mut(leadership_log_id) {
-                log.set_status(status);
+        ) -> Result<(), LogNotFound> {
+            self.update(leadership_log_id, |log| log.set_status(status))
+        }
 
-                self.expirations
-                    .reset_at(key, TokioInstant::from_std(Instant::now() + self.ttl));
-            } else {
-                unimplemented!()
+        pub fn mark_finished(
+            &mut self,
+            leadership_log_id: &LeadershipLogId,
+        ) -> Result<(), LogNotFound> {
+            self.update(leadership_log_id, |log| log.mark_finished())
+        }
+
+        pub fn mark_missed(&mut self, grace: Duration) -> Vec<LeadershipLog> {
+            let now = std::time::SystemTime::now();
+            let missed: Vec<LeadershipLogId> = self
+                .logs()
+                .filter(|log| {
+                    log.status() == &LeadershipLogStatus::Pending
+                        && log.wake_at_time().is_none()
+                        && log.finished_at_time().is_none()
+                        && now
+                            .duration_since(*log.scheduled_at_time().as_ref())
+                            .map_or(false, |late| late > grace)
+                })
+                .map(|log| log.leadership_log_id())
+                .collect();
+            missed
+                .iter()
+                .filter_map(|id| {
+                    self.set_status(id, LeadershipLogStatus::Missed).ok()?;
+                    self.get(id).cloned()
+                })
+                .collect()
+        }
+
+        pub fn reject_pending_of(&mut self, leader: EnclaveLeaderId, reason: String) -> usize {
+            let pending: Vec<LeadershipLogId> = self
+                .logs()
+                .filter(|log| {
+                    log.enclave_leader_id() == &leader
+                        && log.status() == &LeadershipLogStatus::Pending
+                        && log.wake_at_time().is_none()
+                })
+                .map(|log| log.leadership_log_id())
+                .collect();
+            for id in pending.iter() {
+                let status = LeadershipLogStatus::Rejected {
+                    reason: reason.clone(),
+                };
+                // the logs were just found, they cannot be missing
+                let _ = self.set_status(id, status);
             }
+            pending.len()
+        }
+
+        pub fn remove(&mut self, leadership_log_id: &LeadershipLogId) -> Option<LeadershipLog> {
+            let (log, key) = self.forget(leadership_log_id)?;
+            self.expirations.remove(&key);
+            Some(log)
         }
 
-        pub fn mark_finished(&mut self, leadership_log_id: &LeadershipLogId) {
-            if let Some((ref mut log, ref key)) = self.entries.get_mut(leadership_log_id) {
-                log.mark_finished();
+        /// remove the entry of the log, leaving its expiration untouched
+        fn forget(
+            &mut self,
+            leadership_log_id: &LeadershipLogId,
+        ) -> Option<(LeadershipLog, delay_queue::Key)> {
+            let entry = self.entries.remove(leadership_log_id)?;
+            self.reset.remove(leadership_log_id);
+            Some(entry)
+        }
 
-                self.expirations
-                    .reset_at(key, TokioInstant::from_std(Instant::now() + self.ttl));
-            } else {
-                unimplemented!()
+        pub fn purge_before(&mut self, cutoff: Instant) -> usize {
+            let cutoff = TokioInstant::from_std(cutoff);
+            let expired: Vec<LeadershipLogId> = self
+                .entries
+                .iter()
+                .filter(|(_, (_, key))| self.expirations.deadline(key) < cutoff)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in expired.iter() {
+                self.remove(id);
             }
+            expired.len()
         }
 
         pub fn poll_purge(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), time::Error>> {
             loop {
                 match self.expirations.as_mut().poll_next(cx) {
                     Poll::Ready(Some(Ok(entry))) => {
-                        self.entries.remove(entry.get_ref());
+                        let id = entry.into_inner();
+                        let retained = self
+                            .entries
+                            .get(&id)
+                            .map_or(false, |(log, _)| self.is_retained(log));
+                        if retained {
+                            // check again later, the log is purged once it
+                            // is out of the last epochs. The delay is never
+                            // zero so the loop cannot spin on the log.
+                            let delay = self.ttl.max(Duration::from_secs(1));
+                            let key = self.expirations.insert(id, delay);
+                            if let Some((_, old_key)) = self.entries.get_mut(&id) {
+                                *old_key = key;
+                            }
+                        } else {
+                            self.forget(&id);
+                        }
                     }
                     Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
                     Poll::Ready(None) => return Poll::Ready(Ok(())),
@@ -206,8 +744,334 @@ pub(super) mod internal {
             }
         }
 
+        pub fn get(&self, leadership_log_id: &LeadershipLogId) -> Option<&LeadershipLog> {
+            self.entries.get(leadership_log_id).map(|(log, _)| log)
+        }
+
         pub fn logs<'a>(&'a self) -> impl Iterator<Item = &'a LeadershipLog> {
             self.entries.values().map(|(v, _)| v)
         }
+
+        pub fn logs_sorted(&self) -> Vec<&LeadershipLog> {
+            let mut logs: Vec<_> = self.logs().collect();
+            logs.sort_by_key(|log| (*log.scheduled_at_time(), log.leadership_log_id()));
+            logs
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_impl_mockchain::block::BlockDate;
+    use jormungandr_lib::{crypto::hash::Hash, interfaces::EnclaveLeaderId};
+    use tokio_compat::runtime;
+
+    fn date(slot_id: u32) -> jormungandr_lib::interfaces::BlockDate {
+        BlockDate { epoch: 0, slot_id }.into()
+    }
+
+    fn log(slot_id: u32) -> LeadershipLog {
+        LeadershipLog::new(EnclaveLeaderId::new(), date(slot_id), SystemTime::now())
+    }
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        let mut rt = runtime::Builder::new().core_threads(1).build().unwrap();
+        rt.block_on_std(future)
+    }
+
+    #[test]
+    fn logs_in_range() {
+        run(async {
+            let at = |secs| SystemTime::from(std::time::UNIX_EPOCH + Duration::from_secs(secs));
+            let logs = Logs::new(Duration::from_secs(3600));
+            for slot_id in 0..4 {
+                let log = LeadershipLog::new(
+                    EnclaveLeaderId::new(),
+                    date(slot_id),
+                    at(3 - slot_id as u64),
+                );
+                logs.insert(log).await.unwrap();
+            }
+            let dates = |logs: Vec<LeadershipLog>| -> Vec<_> {
+                logs.iter().map(|log| *log.scheduled_at_date()).collect()
+            };
+
+            assert_eq!(
+                dates(logs.logs_in_range(at(1)..at(3)).await),
+                vec![date(2), date(1)]
+            );
+            assert_eq!(
+                dates(logs.logs_in_range(at(2)..).await),
+                vec![date(1), date(0)]
+            );
+            assert_eq!(dates(logs.logs_in_range(..).await).len(), 4);
+        })
+    }
+
+    #[test]
+    fn logs_page() {
+        run(async {
+            let at = |secs| SystemTime::from(std::time::UNIX_EPOCH + Duration::from_secs(secs));
+            let logs = Logs::new(Duration::from_secs(3600));
+            for slot_id in 0..5 {
+                let log = LeadershipLog::new(
+                    EnclaveLeaderId::new(),
+                    date(slot_id),
+                    at(4 - slot_id as u64),
+                );
+                logs.insert(log).await.unwrap();
+            }
+            let dates = |logs: Vec<LeadershipLog>| -> Vec<_> {
+                logs.iter().map(|log| *log.scheduled_at_date()).collect()
+            };
+
+            assert_eq!(dates(logs.logs_page(0, 2).await), vec![date(4), date(3)]);
+            assert_eq!(dates(logs.logs_page(3, 10).await), vec![date(1), date(0)]);
+            assert!(logs.logs_page(5, 10).await.is_empty());
+        })
+    }
+
+    #[test]
+    fn mark_missed() {
+        run(async {
+            let ago =
+                |secs| SystemTime::from(std::time::SystemTime::now() - Duration::from_secs(secs));
+            let logs = Logs::new(Duration::from_secs(3600));
+            let missed = LeadershipLog::new(EnclaveLeaderId::new(), date(0), ago(60));
+            let missed_handle = logs.insert(missed).await.unwrap();
+            let woken = LeadershipLog::new(EnclaveLeaderId::new(), date(1), ago(60));
+            logs.insert(woken).await.unwrap().mark_wake().await.unwrap();
+            let recent = LeadershipLog::new(EnclaveLeaderId::new(), date(2), ago(1));
+            logs.insert(recent).await.unwrap();
+
+            let missed = logs.mark_missed(Duration::from_secs(30)).await;
+            assert_eq!(missed.len(), 1);
+            assert_eq!(missed[0].scheduled_at_date(), &date(0));
+            assert_eq!(logs.stats().await.missed, 1);
+            assert!(logs.mark_missed(Duration::from_secs(30)).await.is_empty());
+
+            // the event running late does not undo the miss
+            missed_handle
+                .set_status(LeadershipLogStatus::Block {
+                    block: Hash::from([0; 32]),
+                    chain_length: 1,
+                })
+                .await
+                .unwrap();
+            let stats = logs.stats().await;
+            assert_eq!((stats.missed, stats.blocks), (1, 0));
+        })
+    }
+
+    #[test]
+    fn update_purged_log() {
+        run(async {
+            let logs = Logs::new(Duration::from_secs(3600));
+            let handle = logs.insert(log(0)).await.unwrap();
+            handle.mark_wake().await.unwrap();
+            logs.remove(log(0).leadership_log_id()).await.unwrap();
+
+            assert!(handle.mark_finished().await.is_err());
+            assert!(handle
+                .set_status(LeadershipLogStatus::Rejected {
+                    reason: "test".to_owned(),
+                })
+                .await
+                .is_err());
+        })
+    }
+
+    #[test]
+    fn leaders_of_the_same_slot_are_not_duplicates() {
+        run(async {
+            for duplicates in [DuplicatePolicy::Replace, DuplicatePolicy::UseExisting].iter() {
+                let options = LogsOptions {
+                    duplicates: *duplicates,
+                    ..LogsOptions::default()
+                };
+                let logs = Logs::new_with_options(Duration::from_secs(3600), options);
+                let leader = EnclaveLeaderId::new();
+                logs.insert(LeadershipLog::new(leader, date(0), SystemTime::now()))
+                    .await
+                    .unwrap();
+                let other = LeadershipLog::new(leader.next(), date(0), SystemTime::now());
+                logs.insert(other).await.unwrap();
+
+                assert_eq!(logs.logs().await.len(), 2);
+            }
+        })
+    }
+
+    #[test]
+    fn duplicate_replaces_existing() {
+        run(async {
+            let logs = Logs::new(Duration::from_secs(3600));
+            logs.insert(log(0))
+                .await
+                .unwrap()
+                .mark_wake()
+                .await
+                .unwrap();
+            logs.insert(log(0)).await.unwrap();
+
+            let kept = logs.logs().await;
+            assert_eq!(kept.len(), 1);
+            assert!(kept[0].wake_at_time().is_none());
+        })
+    }
+
+    #[test]
+    fn duplicate_uses_existing() {
+        run(async {
+            let options = LogsOptions {
+                duplicates: DuplicatePolicy::UseExisting,
+                ..LogsOptions::default()
+            };
+            let logs = Logs::new_with_options(Duration::from_secs(3600), options);
+            logs.insert(log(0))
+                .await
+                .unwrap()
+                .mark_wake()
+                .await
+                .unwrap();
+            let handle = logs.insert(log(0)).await.unwrap();
+            handle.mark_finished().await.unwrap();
+
+            let kept = logs.logs().await;
+            assert_eq!(kept.len(), 1);
+            assert!(kept[0].wake_at_time().is_some());
+            assert!(kept[0].finished_at_time().is_some());
+
+            // a finished log is replaced
+            logs.insert(log(0)).await.unwrap();
+            assert!(logs.logs().await[0].wake_at_time().is_none());
+        })
+    }
+
+    #[test]
+    fn set_ttl_extends_retention() {
+        run(async {
+            let mut logs = Logs::new(Duration::from_millis(10));
+            logs.insert(log(0)).await.unwrap();
+            logs.set_ttl(Duration::from_secs(3600)).await;
+
+            tokio02::time::delay_for(Duration::from_millis(50)).await;
+            logs.poll_purge().await.unwrap();
+
+            assert_eq!(logs.logs().await.len(), 1);
+        })
+    }
+
+    #[test]
+    fn insert_all_returns_handles_in_order() {
+        run(async {
+            let logs = Logs::new(Duration::from_secs(3600));
+            let handles = logs.insert_all(vec![log(2), log(0), log(1)]).await;
+
+            let ids: Vec<LeadershipLogId> =
+                handles.iter().map(|handle| handle.internal_id).collect();
+            assert_eq!(
+                ids,
+                vec![
+                    log(2).leadership_log_id(),
+                    log(0).leadership_log_id(),
+                    log(1).leadership_log_id(),
+                ]
+            );
+            handles[0].mark_finished().await.unwrap();
+            let finished = logs.get(log(2).leadership_log_id()).await.unwrap();
+            assert!(finished.finished_at_time().is_some());
+            assert_eq!(logs.logs().await.len(), 3);
+        })
+    }
+
+    #[test]
+    fn ttl_reset_once_ignores_later_changes() {
+        run(async {
+            let ttl = Duration::from_secs(3600);
+            for ttl_reset in [TtlReset::EveryChange, TtlReset::Once].iter() {
+                let options = LogsOptions {
+                    ttl_reset: *ttl_reset,
+                    ..LogsOptions::default()
+                };
+                let logs = Logs::new_with_options(ttl, options);
+                let handle = logs.insert(log(0)).await.unwrap();
+                handle.mark_wake().await.unwrap();
+                let woken = Instant::now();
+                tokio02::time::delay_for(Duration::from_millis(50)).await;
+                handle.mark_finished().await.unwrap();
+
+                // expires at `woken + ttl` if only the first change reset
+                // the expiration, 50ms later otherwise
+                let purged = logs
+                    .purge_before(woken + ttl + Duration::from_millis(25))
+                    .await;
+                match ttl_reset {
+                    TtlReset::EveryChange => assert_eq!(purged, 0),
+                    TtlReset::Once => assert_eq!(purged, 1),
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn set_ttl_shortens_retention_of_scheduled_logs() {
+        run(async {
+            let mut logs = Logs::new(Duration::from_secs(3600));
+            let in_an_hour =
+                SystemTime::from(std::time::SystemTime::now() + Duration::from_secs(3600));
+            let scheduled = LeadershipLog::new(EnclaveLeaderId::new(), date(0), in_an_hour);
+            logs.insert(scheduled).await.unwrap();
+            logs.set_ttl(Duration::from_millis(10)).await;
+
+            tokio02::time::delay_for(Duration::from_millis(50)).await;
+            logs.poll_purge().await.unwrap();
+
+            assert!(logs.logs().await.is_empty());
+        })
+    }
+
+    #[test]
+    fn keep_epochs_outlives_ttl() {
+        run(async {
+            let options = LogsOptions {
+                keep_epochs: 1,
+                ..LogsOptions::default()
+            };
+            let mut logs = Logs::new_with_options(Duration::from_millis(10), options);
+            logs.insert(log(0)).await.unwrap();
+            let date = BlockDate {
+                epoch: 1,
+                slot_id: 0,
+            };
+            let latest = LeadershipLog::new(EnclaveLeaderId::new(), date.into(), SystemTime::now());
+            logs.insert(latest).await.unwrap();
+
+            tokio02::time::delay_for(Duration::from_millis(50)).await;
+            logs.poll_purge().await.unwrap();
+
+            let kept = logs.logs().await;
+            assert_eq!(kept.len(), 1);
+            assert_eq!(kept[0].scheduled_at_date().as_ref().epoch, 1);
+        })
+    }
+
+    #[test]
+    fn export_import_roundtrip() {
+        run(async {
+            let logs = Logs::new(Duration::from_secs(3600));
+            let handle = logs.insert(log(0)).await.unwrap();
+            handle.mark_wake().await.unwrap();
+            logs.insert(log(1)).await.unwrap();
+            let mut exported = Vec::new();
+            logs.export_json(&mut exported).await.unwrap();
+
+            let imported = Logs::new(Duration::from_secs(3600));
+            assert_eq!(imported.import_json(&exported[..]).await.unwrap(), 2);
+            let log = imported.get(log(0).leadership_log_id()).await.unwrap();
+            assert_eq!(*log.status(), LeadershipLogStatus::Pending);
+            assert!(log.wake_at_time().is_some());
+        })
     }
 }
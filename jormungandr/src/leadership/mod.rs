@@ -57,10 +57,16 @@
 //! we don't duplicate the work everywhere.
 //!
 
+mod clock;
 mod enclave;
 mod logs;
 mod process;
+mod schedule;
+pub mod webhook;
 
 pub use self::enclave::{Enclave, EnclaveError, LeaderEvent};
-pub use self::logs::{LeadershipLogHandle, Logs};
-pub use self::process::Module;
+pub use self::logs::{
+    DuplicatePolicy, LeadershipLogHandle, LeadershipStats, LogNotFound, Logs, LogsOptions, TtlReset,
+};
+pub use self::process::{Module, ModuleOptions};
+pub use self::schedule::{preview_schedule, ScheduleError, ScheduledSlot};
@@ -7,9 +7,11 @@ use crate::{
     fragment,
     intercom::BlockMsg,
     leadership::{
+        clock::{self, ClockDrift},
         enclave::{Enclave, EnclaveError, LeaderEvent},
-        LeadershipLogHandle, Logs,
+        LeadershipLogHandle, LogNotFound, Logs,
     },
+    stats_counter::StatsCounter,
     utils::{async_msg::MessageBox, task::TokioServiceInfo},
 };
 use chain_time::{
@@ -18,12 +20,13 @@ use chain_time::{
 };
 use futures03::{compat::*, future::TryFutureExt, sink::SinkExt};
 use jormungandr_lib::{
-    interfaces::{LeadershipLog, LeadershipLogStatus},
+    interfaces::{FragmentSelectionStrategy, LeadershipLog, LeadershipLogStatus},
     time::SystemTime,
 };
 use slog::Logger;
 use std::{
     collections::VecDeque,
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -55,6 +58,35 @@ pub enum LeadershipError {
     CannotUpdateLogs,
 }
 
+/// interval between 2 saves of the logs, when they are persisted
+const LOGS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// interval between 2 checks of the missed slots
+const MISSED_SLOTS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// a log not woken this long after its scheduled time is missed
+const MISSED_SLOT_GRACE: Duration = Duration::from_secs(30);
+
+/// interval between 2 checks of the system clock against the NTP servers
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// options of the leadership [`Module`]
+///
+/// [`Module`]: ./struct.Module.html
+pub struct ModuleOptions {
+    /// interval between 2 purges of the expired logs
+    pub garbage_collection_interval: Duration,
+    /// save the logs periodically to this file
+    pub logs_file: Option<PathBuf>,
+    /// sign the blocks but do not submit them
+    pub dry_run: bool,
+    /// NTP servers (`host:port`) to check the system clock against, the
+    /// clock is not checked if empty
+    pub ntp_servers: Vec<String>,
+    /// warn when the drift of the system clock is larger
+    pub clock_drift_tolerance: Duration,
+}
+
 struct Entry {
     event: LeaderEvent,
     log: LeadershipLogHandle,
@@ -74,29 +106,109 @@ pub struct Module {
     pool: fragment::Pool,
     enclave: Enclave,
     block_message: MessageBox<BlockMsg>,
+    // sign the blocks but do not submit them
+    dry_run: bool,
+    clock_drift: ClockDrift,
 }
 
 impl Module {
     pub async fn new(
         service_info: TokioServiceInfo,
         logs: Logs,
-        garbage_collection_interval: Duration,
+        options: ModuleOptions,
         tip: Tip,
         pool: fragment::Pool,
         enclave: Enclave,
         block_message: MessageBox<BlockMsg>,
+        stats_counter: StatsCounter,
     ) -> Result<Self, LeadershipError> {
         let logs_to_purge = logs.clone();
 
         service_info.run_periodic(
             "garbage collection",
-            garbage_collection_interval,
+            options.garbage_collection_interval,
             move || {
                 let mut logs_to_purge_local = logs_to_purge.clone();
                 Box::pin(async move { logs_to_purge_local.poll_purge().await }).compat()
             },
         );
 
+        let logs_to_check = logs.clone();
+        let logger = service_info.logger().clone();
+        service_info.run_periodic_std("missed slots", MISSED_SLOTS_CHECK_INTERVAL, move || {
+            let logs_to_check = logs_to_check.clone();
+            let logger = logger.clone();
+            let stats_counter = stats_counter.clone();
+            async move {
+                let missed = logs_to_check.mark_missed(MISSED_SLOT_GRACE).await;
+                stats_counter.add_slots_missed_cnt(missed.len());
+                for log in missed {
+                    warn!(logger, "leadership slot missed";
+                        "leader_id" => log.enclave_leader_id().to_string(),
+                        "event_date" => log.scheduled_at_date().to_string(),
+                        "event_start" => log.scheduled_at_time().to_string(),
+                    );
+                }
+                Ok::<(), std::convert::Infallible>(())
+            }
+        });
+
+        let clock_drift = ClockDrift::default();
+        if !options.ntp_servers.is_empty() {
+            let ntp_servers = Arc::new(options.ntp_servers);
+            let tolerance = options.clock_drift_tolerance;
+            let clock_drift = clock_drift.clone();
+            let logger = service_info.logger().clone();
+            service_info.run_periodic_std("clock check", CLOCK_CHECK_INTERVAL, move || {
+                let ntp_servers = Arc::clone(&ntp_servers);
+                let clock_drift = clock_drift.clone();
+                let logger = logger.clone();
+                async move {
+                    let checked_drift = clock_drift.clone();
+                    let res = tokio02::task::spawn_blocking(move || {
+                        clock::check_clock(&ntp_servers, &checked_drift)
+                    })
+                    .await
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+                    .and_then(|res| res);
+                    match res {
+                        Ok(offset) => {
+                            let direction = if offset.ahead { "ahead" } else { "behind" };
+                            if offset.drift > tolerance {
+                                warn!(logger, "the system clock drifts from the NTP time";
+                                    "drift" => ?offset.drift,
+                                    "direction" => direction,
+                                );
+                            } else {
+                                debug!(logger, "system clock checked";
+                                    "drift" => ?offset.drift,
+                                    "direction" => direction,
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            // a drift measured earlier would keep blocking
+                            // or allowing the events with stale data
+                            clock_drift.clear();
+                            warn!(logger, "cannot check the system clock, the drift is unknown";
+                                "reason" => %e,
+                            );
+                        }
+                    }
+                    Ok::<(), std::io::Error>(())
+                }
+            });
+        }
+
+        if let Some(logs_file) = options.logs_file {
+            let logs_to_save = logs.clone();
+            service_info.run_periodic_std("save logs", LOGS_SAVE_INTERVAL, move || {
+                let logs_to_save = logs_to_save.clone();
+                let logs_file = logs_file.clone();
+                async move { logs_to_save.save(logs_file).await }
+            });
+        }
+
         tip.get_ref().compat().await.map(move |tip_ref| Self {
             schedule: Schedule::default(),
             service_info,
@@ -106,6 +218,8 @@ impl Module {
             pool,
             enclave,
             block_message,
+            dry_run: options.dry_run,
+            clock_drift,
         })
     }
 
@@ -283,10 +397,11 @@ impl Module {
     }
 
     async fn action_entry(self, entry: Entry) -> Result<Self, LeadershipError> {
+        let logger = self.service_info.logger().clone();
         let end_log = entry.log.clone();
-        entry.log.mark_wake().await;
+        log_update(&logger, entry.log.mark_wake().await);
         let module = self.action_run_entry(entry).await?;
-        end_log.mark_finished().await;
+        log_update(&logger, end_log.mark_finished().await);
         Ok(module)
     }
 
@@ -302,19 +417,45 @@ impl Module {
             "event_end" => event_end.to_string(),
         ));
 
-        if too_late(now, event_end) {
+        let slot_duration = event_end
+            .as_ref()
+            .duration_since(*event_start.as_ref())
+            .unwrap_or_default();
+        let clock_drift = self
+            .clock_drift
+            .get()
+            .filter(|drift| *drift > slot_duration);
+
+        if let Some(clock_drift) = clock_drift {
+            // the block would be dated for a slot other than the current one
+            error!(
+                logger,
+                "the system clock drifts by more than a slot, not running the event";
+                "drift" => ?clock_drift,
+            );
+
+            let res = entry
+                .log
+                .mark_rejected(format!(
+                    "The system clock drifts by {:?}, more than a slot",
+                    clock_drift
+                ))
+                .await;
+            log_update(&logger, res);
+
+            Ok(self)
+        } else if too_late(now, event_end) {
             // the event happened out of bounds, ignore it and move to the next one
             error!(
                 logger,
                 "Eek... Too late, we missed an event schedule, system time might be off?"
             );
 
-            entry
+            let res = entry
                 .log
-                .set_status(LeadershipLogStatus::Rejected {
-                    reason: "Missed the deadline to compute the schedule".to_owned(),
-                })
+                .mark_rejected("Missed the deadline to compute the schedule".to_owned())
                 .await;
+            log_update(&logger, res);
 
             Ok(self)
         } else {
@@ -379,11 +520,10 @@ impl Module {
             Ok(future_res) => future_res,
             Err(timeout_error) => {
                 error!(timed_out_log, "Eek... took too long to process the event..." ; "reason" => %timeout_error);
-                event_logs
-                    .set_status(LeadershipLogStatus::Rejected {
-                        reason: "Failed to compute the schedule within time boundaries".to_owned(),
-                    })
+                let res = event_logs
+                    .mark_rejected("Failed to compute the schedule within time boundaries".to_owned())
                     .await;
+                log_update(&timed_out_log, res);
                 Ok(())
             }
         }.map(|()| self)
@@ -425,11 +565,12 @@ impl Module {
                 "It appears the node is running a bit behind schedule, system time might be off?"
             );
 
-            event_logs.set_status(
-                    LeadershipLogStatus::Rejected {
-                        reason: "Not computing this schedule because of invalid state against the network blockchain".to_owned()
-                    }
-                ).await;
+            let res = event_logs
+                .mark_rejected(
+                    "Not computing this schedule because of invalid state against the network blockchain".to_owned(),
+                )
+                .await;
+            log_update(&logger, res);
 
             return Ok(());
         };
@@ -437,6 +578,7 @@ impl Module {
         let contents = prepare_block(pool, event.date, ledger, ledger_parameters).await?;
 
         let event_logs_error = event_logs.clone();
+        let logger_error = logger.clone();
         let signing = {
             let ver = match event.output {
                 LeaderOutput::None => BlockVersion::Genesis,
@@ -470,11 +612,10 @@ impl Module {
                             })
                         })
                         .or_else(|e| async move {
-                            event_logs_error
-                                .set_status(LeadershipLogStatus::Rejected {
-                                    reason: format!("Cannot sign the block: {}", e),
-                                })
+                            let res = event_logs_error
+                                .mark_rejected(format!("Cannot sign the block: {}", e))
                                 .await;
+                            log_update(&logger_error, res);
                             Ok(None)
                         })
                         .await
@@ -493,11 +634,10 @@ impl Module {
                             })
                         })
                         .or_else(|e| async move {
-                            event_logs_error
-                                .set_status(LeadershipLogStatus::Rejected {
-                                    reason: format!("Cannot sign the block: {}", e),
-                                })
+                            let res = event_logs_error
+                                .mark_rejected(format!("Cannot sign the block: {}", e))
                                 .await;
+                            log_update(&logger_error, res);
                             Ok(None)
                         })
                         .await
@@ -510,17 +650,32 @@ impl Module {
                 if let Some(block) = maybe_block {
                     let id = block.header.hash();
                     let chain_length: u32 = block.header.chain_length().into();
+                    if self.dry_run {
+                        info!(logger, "dry run, the block is signed but not submitted";
+                            "block" => %id,
+                            "chain_length" => chain_length,
+                        );
+                        let res = event_logs
+                            .set_status(LeadershipLogStatus::DryRun {
+                                block: id.into(),
+                                chain_length,
+                            })
+                            .await;
+                        log_update(&logger, res);
+                        return Ok(());
+                    }
                     sender
                         .sink_compat()
                         .send(BlockMsg::LeadershipBlock(block))
                         .map_err(|_send_error| LeadershipError::CannotSendLeadershipBlock)
                         .await?;
-                    event_logs
+                    let res = event_logs
                         .set_status(LeadershipLogStatus::Block {
                             block: id.into(),
                             chain_length,
                         })
                         .await;
+                    log_update(&logger, res);
                 };
                 Ok(())
             }
@@ -597,25 +752,36 @@ impl Module {
             .await?;
 
         let mut module = self;
-        for schedule in schedules.into_iter() {
-            let epoch = Epoch(schedule.date.epoch);
-            let slot = EpochSlotOffset(schedule.date.slot_id);
-            let scheduled_at_time = module.slot_time(epoch, slot);
-            let log = LeadershipLog::new(schedule.id, schedule.date.into(), scheduled_at_time);
-
-            match module.logs.insert(log).await {
-                Ok(log) => module.schedule.push(Entry {
-                    event: schedule,
-                    log,
-                }),
-                Err(()) => return Err(LeadershipError::CannotUpdateLogs),
-            }
+        let logs = schedules
+            .iter()
+            .map(|schedule| {
+                let epoch = Epoch(schedule.date.epoch);
+                let slot = EpochSlotOffset(schedule.date.slot_id);
+                let scheduled_at_time = module.slot_time(epoch, slot);
+                LeadershipLog::new(schedule.id, schedule.date.into(), scheduled_at_time)
+            })
+            .collect();
+
+        let logs = module.logs.insert_all(logs).await;
+        for (schedule, log) in schedules.into_iter().zip(logs) {
+            module.schedule.push(Entry {
+                event: schedule,
+                log,
+            });
         }
 
         Ok(module)
     }
 }
 
+/// a leadership log may be purged (its TTL expired) while the event is
+/// still being processed, this is not an error for the event itself.
+fn log_update(logger: &Logger, res: Result<(), LogNotFound>) {
+    if let Err(e) = res {
+        debug!(logger, "cannot update the leadership log"; "reason" => %e);
+    }
+}
+
 impl Entry {
     fn instant(&self, module: &Module) -> Result<Option<Instant>, LeadershipError> {
         let epoch = Epoch(self.event.date.epoch);
@@ -644,19 +810,28 @@ async fn prepare_block(
     ledger: Arc<Ledger>,
     epoch_parameters: Arc<LedgerParameters>,
 ) -> Result<Contents, LeadershipError> {
-    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst};
-
-    let selection_algorithm = OldestFirst::new();
-    fragment_pool
-        .select(
-            ledger.as_ref().clone(),
-            block_date,
-            epoch_parameters.as_ref().clone(),
-            selection_algorithm,
-        )
-        .map_ok(|selection_algorithm| selection_algorithm.finalize())
-        .map_err(|()| LeadershipError::FragmentSelectionFailed)
-        .await
+    use crate::fragment::selection::{
+        FragmentSelectionAlgorithm as _, HighestFeeRateFirst, OldestFirst,
+    };
+
+    let ledger = ledger.as_ref().clone();
+    let epoch_parameters = epoch_parameters.as_ref().clone();
+    let contents = match fragment_pool.selection_strategy() {
+        FragmentSelectionStrategy::OldestFirst => fragment_pool
+            .select(ledger, block_date, epoch_parameters, OldestFirst::new())
+            .await
+            .map(|selection_algorithm| selection_algorithm.finalize()),
+        FragmentSelectionStrategy::HighestFeeRate => fragment_pool
+            .select(
+                ledger,
+                block_date,
+                epoch_parameters,
+                HighestFeeRateFirst::new(),
+            )
+            .await
+            .map(|selection_algorithm| selection_algorithm.finalize()),
+    };
+    contents.map_err(|()| LeadershipError::FragmentSelectionFailed)
 }
 
 fn too_late(now: SystemTime, event_end: SystemTime) -> bool {
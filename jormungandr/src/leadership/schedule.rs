@@ -0,0 +1,79 @@
+//! preview of the leadership schedule of an epoch
+//!
+//! The schedule of an epoch can be computed as soon as the tip of the
+//! blockchain is in the epoch or in the epoch before it. For the next epoch
+//! the schedule is computed from the current tip: the epoch nonce still
+//! changes with the blocks added until the end of the current epoch, so is
+//! the schedule.
+
+use crate::{
+    blockchain::{new_epoch_leadership_from, Ref},
+    leadership::{Enclave, EnclaveError},
+};
+use chain_time::era::{EpochPosition, EpochSlotOffset};
+use jormungandr_lib::{
+    interfaces::{BlockDate, EnclaveLeaderId},
+    time::SystemTime,
+};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("the schedule of epoch {epoch} cannot be computed from the tip in epoch {tip_epoch}")]
+    EpochNotAvailable { epoch: u32, tip_epoch: u32 },
+
+    #[error("Cannot query enclave for leader schedules")]
+    Enclave(#[from] EnclaveError),
+}
+
+/// a slot of the schedule where one of the leaders of the enclave is elected
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledSlot {
+    pub enclave_leader_id: EnclaveLeaderId,
+    pub scheduled_at_date: BlockDate,
+    pub scheduled_at_time: SystemTime,
+}
+
+/// compute the slots of `epoch` where the leaders of the `enclave` are
+/// elected, ordered by date
+pub async fn preview_schedule(
+    enclave: &Enclave,
+    tip_ref: Arc<Ref>,
+    epoch: u32,
+) -> Result<Vec<ScheduledSlot>, ScheduleError> {
+    let tip_epoch = tip_ref.block_date().epoch;
+    let leadership = if epoch == tip_epoch {
+        Arc::clone(tip_ref.epoch_leadership_schedule())
+    } else if epoch == tip_epoch + 1 {
+        let (_, leadership, _, _, _) = new_epoch_leadership_from(epoch, Arc::clone(&tip_ref));
+        leadership
+    } else {
+        return Err(ScheduleError::EpochNotAvailable { epoch, tip_epoch });
+    };
+
+    let nb_slots = leadership.era().slots_per_epoch();
+    let events = enclave
+        .query_schedules(Arc::clone(&leadership), 0, nb_slots)
+        .await?;
+
+    let era = leadership.era();
+    let time_frame = tip_ref.time_frame();
+    let mut slots: Vec<ScheduledSlot> = events
+        .into_iter()
+        .filter_map(|event| {
+            let slot = era.from_era_to_slot(EpochPosition {
+                epoch: chain_time::Epoch(event.date.epoch),
+                slot: EpochSlotOffset(event.date.slot_id),
+            });
+            let scheduled_at_time = time_frame.slot_to_systemtime(slot)?;
+            Some(ScheduledSlot {
+                enclave_leader_id: event.id,
+                scheduled_at_date: event.date.into(),
+                scheduled_at_time: scheduled_at_time.into(),
+            })
+        })
+        .collect();
+    slots.sort_by_key(|slot| (slot.scheduled_at_date, slot.enclave_leader_id));
+    Ok(slots)
+}
@@ -0,0 +1,55 @@
+//! notification of the outcome of the leadership events to a webhook
+//!
+//! Every time a leadership log gets a `Block`, `Rejected`, `Missed` or
+//! `DryRun` status the log is POSTed as JSON to the configured URL. A
+//! notification failing is logged and not retried, a log is notified again
+//! only if its status changes.
+
+use crate::{leadership::Logs, utils::task::TokioServiceInfo};
+use futures03::{compat::Future01CompatExt, stream::StreamExt};
+use hyper::{header::CONTENT_TYPE, Body, Client, Request, Uri};
+use jormungandr_lib::interfaces::{LeadershipLogId, LeadershipLogStatus};
+use linked_hash_map::LinkedHashMap;
+
+/// number of logs whose notified status is remembered to not notify the
+/// same status twice
+const NOTIFIED_CAPACITY: usize = 1024;
+
+pub async fn notify(service_info: TokioServiceInfo, logs: Logs, url: Uri) -> Result<(), ()> {
+    let logger = service_info.logger().clone();
+    let client = Client::new();
+    let mut notified: LinkedHashMap<LeadershipLogId, LeadershipLogStatus> = LinkedHashMap::new();
+    let mut updates = Box::pin(logs.subscribe());
+
+    while let Some((id, status)) = updates.next().await {
+        if status == LeadershipLogStatus::Pending || notified.get(&id) == Some(&status) {
+            continue;
+        }
+        let log = match logs.get(id).await {
+            Some(log) => log,
+            None => continue,
+        };
+        notified.insert(id, status);
+        if notified.len() > NOTIFIED_CAPACITY {
+            notified.pop_front();
+        }
+
+        // a log always serializes
+        let body = serde_json::to_vec(&log).unwrap();
+        // the request is made of a valid URI and header
+        let request = Request::post(url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        match client.request(request).compat().await {
+            Ok(response) if response.status().is_success() => (),
+            Ok(response) => warn!(logger, "the webhook refused the leadership log";
+                "status" => response.status().as_u16(),
+            ),
+            Err(e) => warn!(logger, "cannot notify the webhook of the leadership log";
+                "reason" => %e,
+            ),
+        }
+    }
+    Ok(())
+}
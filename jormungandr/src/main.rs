@@ -58,7 +58,11 @@ pub mod utils;
 use stats_counter::StatsCounter;
 
 fn start() -> Result<(), start_up::Error> {
-    let initialized_node = initialize_node()?;
+    let initialized_node = match initialize_node()? {
+        Some(initialized_node) => initialized_node,
+        // the archive has been exported, the node does not start
+        None => return Ok(()),
+    };
 
     let bootstrapped_node = bootstrap(initialized_node)?;
 
@@ -81,6 +85,44 @@ const BLOCK_TASK_QUEUE_LEN: usize = 32;
 const FRAGMENT_TASK_QUEUE_LEN: usize = 1024;
 const NETWORK_TASK_QUEUE_LEN: usize = 32;
 const BOOTSTRAP_RETRY_WAIT: Duration = Duration::from_secs(5);
+/// file of the storage directory the leadership logs are saved to
+const LEADERSHIP_LOGS_FILE: &str = "leadership_logs.json";
+/// file of the storage directory the mempool journal is written to
+const MEMPOOL_JOURNAL_FILE: &str = "mempool.journal";
+
+/// replay the mempool journal at `path` in the pool of `process`, and keep
+/// recording the fragments to it. A journal that cannot be opened is
+/// reported and the mempool is not persistent.
+fn restore_mempool(
+    process: &mut fragment::Process,
+    path: std::path::PathBuf,
+    blockchain_tip: &blockchain::Tip,
+    logger: &Logger,
+) {
+    let (journal, entries) = match fragment::Journal::open(path.clone()) {
+        Ok(opened) => opened,
+        Err(e) => {
+            warn!(logger, "cannot open the mempool journal";
+                "path" => ?path,
+                "reason" => %e,
+            );
+            return;
+        }
+    };
+    let mut pool = process.pool().clone();
+    let restored = block_on(pool.restore(entries, blockchain_tip));
+    if let Err(e) = process.set_journal(journal, logger.clone()) {
+        warn!(logger, "cannot start the mempool journal writer"; "reason" => %e);
+        return;
+    }
+    if let Ok(count) = restored {
+        info!(logger, "restored {} fragments in the mempool", count);
+    }
+    // drop the fragments that were not restored from the journal
+    if let Err(e) = block_on(process.pool().compact_journal()) {
+        warn!(logger, "cannot compact the mempool journal"; "reason" => %e);
+    }
+}
 
 fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::Error> {
     if let Some(context) = bootstrapped_node.rest_context.as_ref() {
@@ -95,10 +137,52 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     let (fragment_msgbox, fragment_queue) = async_msg::channel(FRAGMENT_TASK_QUEUE_LEN);
     let blockchain_tip = bootstrapped_node.blockchain_tip;
     let blockchain = bootstrapped_node.blockchain;
-    let leadership_logs =
-        leadership::Logs::new(bootstrapped_node.settings.leadership.log_ttl.into());
+    let leadership_logs = leadership::Logs::new_with_options(
+        bootstrapped_node.settings.leadership.log_ttl.into(),
+        leadership::LogsOptions {
+            keep_epochs: bootstrapped_node.settings.leadership.keep_epochs,
+            ..leadership::LogsOptions::default()
+        },
+    );
     let leadership_garbage_collection_interval =
         bootstrapped_node.settings.leadership.log_ttl.into();
+    let leadership_logs_file = if bootstrapped_node.settings.leadership.persist_logs {
+        bootstrapped_node
+            .settings
+            .storage
+            .as_ref()
+            .map(|dir| dir.join(LEADERSHIP_LOGS_FILE))
+    } else {
+        None
+    };
+    if let Some(path) = leadership_logs_file.as_ref().filter(|path| path.exists()) {
+        let imported = std::fs::File::open(path)
+            .map(std::io::BufReader::new)
+            .and_then(|reader| block_on(leadership_logs.import_json(reader)));
+        match imported {
+            Ok(count) => info!(
+                bootstrapped_node.logger,
+                "restored {} leadership logs", count
+            ),
+            Err(e) => warn!(
+                bootstrapped_node.logger,
+                "cannot restore the leadership logs";
+                "path" => ?path,
+                "reason" => %e,
+            ),
+        }
+    }
+    let leadership_options = leadership::ModuleOptions {
+        garbage_collection_interval: leadership_garbage_collection_interval,
+        logs_file: leadership_logs_file,
+        dry_run: bootstrapped_node.settings.leadership.dry_run,
+        ntp_servers: bootstrapped_node.settings.leadership.ntp_servers.clone(),
+        clock_drift_tolerance: bootstrapped_node
+            .settings
+            .leadership
+            .clock_drift_tolerance
+            .into(),
+    };
 
     let topology = P2pTopology::new(
         &bootstrapped_node.settings.network,
@@ -111,24 +195,30 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
 
     let (fragment_pool, pool_logs) = {
         let stats_counter = stats_counter.clone();
-        let process = fragment::Process::new(
-            bootstrapped_node.settings.mempool.pool_max_entries.into(),
-            bootstrapped_node.settings.mempool.fragment_ttl.into(),
-            bootstrapped_node.settings.mempool.log_max_entries.into(),
-            bootstrapped_node.settings.mempool.log_ttl.into(),
-            bootstrapped_node
-                .settings
-                .mempool
-                .garbage_collection_interval
-                .into(),
-            network_msgbox.clone(),
-        );
+        let mut process =
+            fragment::Process::new(&bootstrapped_node.settings.mempool, network_msgbox.clone());
+
+        if bootstrapped_node.settings.mempool.persistent {
+            match bootstrapped_node.settings.storage.as_ref() {
+                Some(dir) => restore_mempool(
+                    &mut process,
+                    dir.join(MEMPOOL_JOURNAL_FILE),
+                    &blockchain_tip,
+                    &bootstrapped_node.logger,
+                ),
+                None => warn!(
+                    bootstrapped_node.logger,
+                    "the mempool cannot be persistent without a storage directory"
+                ),
+            }
+        }
 
         let pool = process.pool().clone();
         let logs = process.logs().clone();
+        let blockchain_tip = blockchain_tip.clone();
 
         services.spawn_future("fragment", move |info| {
-            let fut = process.start(info, stats_counter, fragment_queue);
+            let fut = process.start(info, stats_counter, fragment_queue, blockchain_tip);
             Box::pin(fut).compat()
         });
         (pool, logs)
@@ -155,7 +245,11 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         }
     };
 
-    {
+    // a read-only storage cannot store blocks, the node neither processes
+    // the blocks it receives nor creates any
+    let storage_read_only = bootstrapped_node.settings.storage_read_only;
+
+    if !storage_read_only {
         let blockchain = blockchain.clone();
         let blockchain_tip = blockchain_tip.clone();
         let network_msgbox = network_msgbox.clone();
@@ -229,22 +323,24 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     let leader_secrets = leader_secrets?;
     let enclave = block_on(Enclave::from_vec(leader_secrets));
 
-    {
+    if !storage_read_only {
         let leadership_logs = leadership_logs.clone();
         let fragment_pool = fragment_pool.clone();
         let block_msgbox = block_msgbox.clone();
         let blockchain_tip = blockchain_tip.clone();
         let enclave = leadership::Enclave::new(enclave.clone());
+        let stats_counter = stats_counter.clone();
 
         services.spawn_future("leadership", move |info| {
             let fut = leadership::Module::new(
                 info,
                 leadership_logs,
-                leadership_garbage_collection_interval,
+                leadership_options,
                 blockchain_tip,
                 fragment_pool,
                 enclave,
                 block_msgbox,
+                stats_counter,
             )
             .and_then(|module| module.run())
             .map_err(|e| unimplemented!("error in leadership {}", e));
@@ -253,6 +349,14 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
+    if let Some(webhook_url) = bootstrapped_node.settings.leadership.webhook_url.clone() {
+        let leadership_logs = leadership_logs.clone();
+        services.spawn_future("leadership_webhook", move |info| {
+            let fut = leadership::webhook::notify(info, leadership_logs, webhook_url);
+            Box::pin(fut).compat()
+        });
+    }
+
     if let Some(rest_context) = bootstrapped_node.rest_context {
         let full_context = rest::FullContext {
             stats_counter,
@@ -352,7 +456,11 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
         start_up::load_blockchain(block0, storage, block_cache_ttl, &bootstrap_logger)?;
 
     let mut bootstrap_attempt: usize = 0;
-    loop {
+    // the blocks of the archive have been imported instead, or the
+    // storage cannot be written to
+    let skip_network_bootstrap =
+        settings.bootstrap_from_archive.is_some() || settings.storage_read_only;
+    while !skip_network_bootstrap {
         bootstrap_attempt += 1;
 
         // If we have exceeded the maximum number of bootstrap attempts, then we break out of the
@@ -419,7 +527,9 @@ pub struct InitializedNode {
     pub diagnostic: Diagnostic,
 }
 
-fn initialize_node() -> Result<InitializedNode, start_up::Error> {
+/// returns `None` if the node exits once initialized, after exporting an
+/// archive of its storage
+fn initialize_node() -> Result<Option<InitializedNode>, start_up::Error> {
     let command_line = CommandLine::load();
 
     if command_line.full_version {
@@ -464,6 +574,11 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
     }
     let storage = start_up::prepare_storage(&settings, &init_logger)?;
 
+    if let Some(path) = settings.export_archive.clone() {
+        start_up::export_archive(&storage, path, &init_logger)?;
+        return Ok(None);
+    }
+
     // TODO: load network module here too (if needed)
 
     if let Some(context) = rest_context.as_ref() {
@@ -475,7 +590,11 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         &init_logger, /* add network to fetch block0 */
     )?;
 
-    Ok(InitializedNode {
+    if let Some(path) = settings.bootstrap_from_archive.clone() {
+        start_up::import_archive(&storage, path, &block0, &init_logger)?;
+    }
+
+    Ok(Some(InitializedNode {
         settings,
         block0,
         storage,
@@ -483,7 +602,7 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         rest_context,
         services,
         diagnostic,
-    })
+    }))
 }
 
 fn main() {
@@ -0,0 +1,67 @@
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse, Responder};
+use std::fmt::Write as _;
+
+use crate::leadership::LeadershipStats;
+pub use crate::rest::Context;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// the leadership metrics, per epoch: the name of the metric, its help and
+/// how to get it from the summary of the logs of the epoch
+const LEADERSHIP_METRICS: &[(&str, &str, fn(&LeadershipStats) -> usize)] = &[
+    (
+        "jormungandr_leadership_slots_scheduled",
+        "leadership slots scheduled",
+        |stats| stats.pending + stats.wakened + stats.finished,
+    ),
+    (
+        "jormungandr_leadership_blocks_produced",
+        "blocks produced in the scheduled slots",
+        |stats| stats.blocks,
+    ),
+    (
+        "jormungandr_leadership_slots_rejected",
+        "scheduled slots processed without producing a block",
+        |stats| stats.rejected,
+    ),
+    (
+        "jormungandr_leadership_slots_missed",
+        "scheduled slots passed without being processed",
+        |stats| stats.missed,
+    ),
+];
+
+pub async fn get_metrics(context: Data<Context>) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let epochs = full_context.leadership_logs.logs_by_epoch().await;
+
+    // writing to a `String` cannot fail
+    let mut body = String::new();
+    for (name, help, value) in LEADERSHIP_METRICS {
+        writeln!(body, "# HELP {} {} per epoch", name, help).unwrap();
+        writeln!(body, "# TYPE {} gauge", name).unwrap();
+        for (epoch, stats) in epochs.iter() {
+            writeln!(body, "{}{{epoch=\"{}\"}} {}", name, epoch, value(stats)).unwrap();
+        }
+    }
+
+    // counted in the node stats since the node started, not per epoch
+    let name = "jormungandr_leadership_slots_missed_total";
+    writeln!(
+        body,
+        "# HELP {} scheduled slots passed without being processed since the node started",
+        name
+    )
+    .unwrap();
+    writeln!(body, "# TYPE {} counter", name).unwrap();
+    writeln!(
+        body,
+        "{} {}",
+        name,
+        full_context.stats_counter.slots_missed_cnt()
+    )
+    .unwrap();
+
+    Ok(HttpResponse::Ok().content_type(CONTENT_TYPE).body(body))
+}
@@ -0,0 +1,12 @@
+//! metrics of the node in the Prometheus text format
+
+mod handlers;
+
+use actix_web::{
+    dev::HttpServiceFactory,
+    web::{get, resource},
+};
+
+pub fn service(root_path: &str) -> impl HttpServiceFactory {
+    resource(root_path).route(get().to(handlers::get_metrics))
+}
@@ -3,12 +3,16 @@
 mod server;
 
 pub mod explorer;
+pub mod metrics;
 pub mod v0;
 
 pub use self::server::{Error, Server, ServerStopper};
 
-use actix_web::error::{Error as ActixError, ErrorInternalServerError, ErrorServiceUnavailable};
-use actix_web::web::ServiceConfig;
+use actix_web::error::{
+    Error as ActixError, ErrorForbidden, ErrorInternalServerError, ErrorServiceUnavailable,
+    ErrorUnauthorized,
+};
+use actix_web::{http::header, web::ServiceConfig, HttpRequest};
 
 use slog::Logger;
 use std::sync::Arc;
@@ -106,12 +110,42 @@ pub struct FullContext {
     pub diagnostic: crate::diagnostic::Diagnostic,
 }
 
+/// the token the clients must present as a bearer token to use the admin
+/// endpoints, the admin endpoints are disabled without token
+#[derive(Clone)]
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn check(&self, request: &HttpRequest) -> Result<(), ActixError> {
+        let expected = self
+            .0
+            .as_ref()
+            .ok_or_else(|| ErrorForbidden("the admin endpoints are disabled"))?;
+        let provided = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| value.starts_with("Bearer "))
+            .map(|value| &value["Bearer ".len()..]);
+        match provided {
+            Some(provided) if constant_time_eq(provided.as_bytes(), expected.as_bytes()) => Ok(()),
+            _ => Err(ErrorUnauthorized("invalid admin token")),
+        }
+    }
+}
+
+/// compare the tokens in a time depending on their length only
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 pub fn start_rest_server(
     config: Rest,
     explorer_enabled: bool,
     context: &Context,
 ) -> Result<Server, ConfigError> {
-    let app_config = app_config_factory(explorer_enabled, context.clone());
+    let admin_token = AdminToken(config.admin_token.clone());
+    let app_config = app_config_factory(explorer_enabled, admin_token, context.clone());
     let server = Server::start(config, app_config)?;
     block_on(context.set_server_stopper(server.stopper()));
     Ok(server)
@@ -119,13 +153,23 @@ pub fn start_rest_server(
 
 fn app_config_factory(
     explorer_enabled: bool,
+    admin_token: AdminToken,
     context: Context,
 ) -> impl FnOnce(&mut ServiceConfig) + Clone + Send + 'static {
-    move |config| app_config(config, explorer_enabled, context)
+    move |config| app_config(config, explorer_enabled, admin_token, context)
 }
 
-fn app_config(config: &mut ServiceConfig, explorer_enabled: bool, context: Context) {
-    config.data(context).service(v0::service("/api/v0"));
+fn app_config(
+    config: &mut ServiceConfig,
+    explorer_enabled: bool,
+    admin_token: AdminToken,
+    context: Context,
+) {
+    config
+        .data(context)
+        .data(admin_token)
+        .service(v0::service("/api/v0"))
+        .service(metrics::service("/metrics"));
     if explorer_enabled {
         config.service(explorer::service("/explorer"));
     }
@@ -1,12 +1,14 @@
 use jormungandr_lib::interfaces::{
-    AccountState, Address, EnclaveLeaderId, FragmentOrigin, Rewards as StakePoolRewards,
-    StakePoolStats, TaxTypeSerde,
+    AccountState, Address, EnclaveLeaderId, FragmentOrigin, FragmentStatus, LeadershipLogStatus,
+    Rewards as StakePoolRewards, StakePoolStats, TaxTypeSerde,
 };
 use jormungandr_lib::time::SystemTime;
 
-use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
+use actix_web::error::{
+    ErrorBadRequest, ErrorInternalServerError, ErrorNotFound, ErrorNotImplemented,
+};
 use actix_web::web::{Bytes, BytesMut, Data, Json, Path, Query};
-use actix_web::{Error, HttpResponse, Responder};
+use actix_web::{Error, HttpRequest, HttpResponse, Responder};
 use chain_core::property::{Block, Deserialize, Serialize as _};
 use chain_crypto::{bech32::Bech32, Blake2b256, PublicKey};
 use chain_impl_mockchain::account::{AccountAlg, Identifier};
@@ -18,15 +20,18 @@ use chain_impl_mockchain::transaction::Transaction;
 use chain_impl_mockchain::value::{Value, ValueError};
 use chain_storage_sqlite_old::Error as StorageError;
 
-use crate::blockchain::Ref;
+use crate::blockchain::{is_fragment_index_disabled, Ref};
 use crate::intercom::{self, NetworkMsg, TransactionMsg};
+use crate::leadership;
 use crate::secure::NodeSecret;
 use futures::Stream;
 use futures03::compat::Future01CompatExt;
 use jormungandr_lib::interfaces::NodeState;
+use std::ops::Bound;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use crate::rest::AdminToken;
 pub use crate::rest::{Context, FullContext};
 
 async fn chain_tip(context: &Data<Context>) -> Result<Arc<Ref>, Error> {
@@ -78,6 +83,53 @@ pub async fn get_message_logs(context: Data<Context>) -> Result<impl Responder,
         .map(Json)
 }
 
+/// status of a fragment: from the fragment logs while the fragment is
+/// pending or once it is rejected, from the block of the storage containing
+/// it otherwise (only known if the storage indexes the fragments, `501 Not
+/// Implemented` if it does not)
+pub async fn get_fragment_status(
+    context: Data<Context>,
+    fragment_id_hex: Path<String>,
+) -> Result<impl Responder, Error> {
+    let fragment_id = parse_fragment_id(&fragment_id_hex)?;
+    let full_context = context.try_full().await?;
+    let log = full_context
+        .logs
+        .get(fragment_id)
+        .await
+        .map_err(|_| ErrorInternalServerError("Failed to get logs"))?;
+    if let Some(log) = log.filter(|log| !log.is_in_a_block()) {
+        return Ok(Json(json!({ "status": log.status() })));
+    }
+    let storage = full_context.blockchain.storage().clone();
+    let (header_hash, index) = storage
+        .get_fragment_location(fragment_id)
+        .compat()
+        .await
+        .map_err(|e| {
+            if is_fragment_index_disabled(&e) {
+                ErrorNotImplemented("The fragments of the stored blocks are not indexed")
+            } else {
+                ErrorInternalServerError(e)
+            }
+        })?
+        .ok_or_else(|| ErrorNotFound("Fragment not found in a block"))?;
+    let block = storage
+        .get(header_hash)
+        .compat()
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorInternalServerError("Could not find the block of the fragment"))?;
+    let status = FragmentStatus::InABlock {
+        date: block.header.block_date().clone().into(),
+        block: header_hash.into(),
+    };
+    Ok(Json(json!({
+        "status": status,
+        "index": index,
+    })))
+}
+
 pub async fn post_message(context: Data<Context>, message: Bytes) -> Result<impl Responder, Error> {
     let fragment = Fragment::deserialize(&*message).map_err(ErrorBadRequest)?;
     let msg = TransactionMsg::SendTransaction(FragmentOrigin::Rest, vec![fragment]);
@@ -159,11 +211,17 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
     let tip_header = tip.header();
     let stats = &context.stats_counter;
     let node_id = &context.p2p.node_id().to_string();
+    let storage = context.blockchain.storage();
+    let cache_metrics = storage.cache_metrics();
+    // the other stats are still reported if the usage cannot be computed
+    let storage_usage = storage.usage().compat().await.ok();
+    let storage_metrics = storage.metrics();
     Ok(json!({
         "txRecvCnt": stats.tx_recv_cnt(),
         "blockRecvCnt": stats.block_recv_cnt(),
         "lastReceivedBlockTime": stats.slot_start_time().map(SystemTime::from),
         "uptime": stats.uptime_sec(),
+        "slotsMissedCnt": stats.slots_missed_cnt(),
         "lastBlockHash": tip_header.hash().to_string(),
         "lastBlockHeight": tip_header.chain_length().to_string(),
         "lastBlockDate": tip_header.block_date().to_string(),
@@ -177,6 +235,14 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
         "peerUnreachableCnt": nodes_count.not_reachable_count,
         "peerQuarantinedCnt": nodes_count.quarantined_count,
         "nodeId": node_id,
+        "storageCacheHits": cache_metrics.as_ref().map(|metrics| metrics.hits),
+        "storageCacheMisses": cache_metrics.as_ref().map(|metrics| metrics.misses),
+        "storageBlockCnt": storage_usage.as_ref().and_then(|usage| usage.block_count),
+        "storageBytesOnDisk": storage_usage.as_ref().and_then(|usage| usage.bytes_on_disk),
+        "storageReadQueueDepth": storage.read_queue_depth(),
+        "storageGetLatency": storage_metrics.get,
+        "storagePutLatency": storage_metrics.put,
+        "storageStreamBlockLatency": storage_metrics.stream_block,
     }))
 }
 
@@ -319,10 +385,15 @@ pub async fn get_leaders(context: Data<Context>) -> Result<impl Responder, Error
     }))
 }
 
+/// register a leader, for the clients presenting the admin token. Served
+/// for both `POST` and `PUT`.
 pub async fn post_leaders(
+    request: HttpRequest,
     secret: Json<NodeSecret>,
+    admin_token: Data<AdminToken>,
     context: Data<Context>,
 ) -> Result<impl Responder, Error> {
+    admin_token.check(&request)?;
     let leader = Leader {
         bft_leader: secret.bft(),
         genesis_leader: secret.genesis(),
@@ -331,24 +402,118 @@ pub async fn post_leaders(
     Ok(Json(leader_id))
 }
 
+/// retire a leader, for the clients presenting the admin token
 pub async fn delete_leaders(
+    request: HttpRequest,
+    admin_token: Data<AdminToken>,
     context: Data<Context>,
     leader_id: Path<EnclaveLeaderId>,
 ) -> Result<impl Responder, Error> {
-    match context
-        .try_full()
-        .await?
-        .enclave
-        .remove_leader(*leader_id)
-        .await
-    {
-        true => Ok(HttpResponse::Ok().finish()),
+    admin_token.check(&request)?;
+    let context = context.try_full().await?;
+    match context.enclave.remove_leader(*leader_id).await {
+        true => {
+            context
+                .leadership_logs
+                .reject_pending_of(*leader_id, "Leader removed from the enclave".to_owned())
+                .await;
+            Ok(HttpResponse::Ok().finish())
+        }
         false => Err(ErrorNotFound("Leader with given ID not found")),
     }
 }
 
-pub async fn get_leaders_logs(context: Data<Context>) -> Result<impl Responder, Error> {
-    Ok(Json(context.try_full().await?.leadership_logs.logs().await))
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeadershipLogStatusKind {
+    Pending,
+    Rejected,
+    Block,
+    Missed,
+    DryRun,
+}
+
+impl LeadershipLogStatusKind {
+    fn matches(&self, status: &LeadershipLogStatus) -> bool {
+        match (self, status) {
+            (LeadershipLogStatusKind::Pending, LeadershipLogStatus::Pending)
+            | (LeadershipLogStatusKind::Rejected, LeadershipLogStatus::Rejected { .. })
+            | (LeadershipLogStatusKind::Block, LeadershipLogStatus::Block { .. })
+            | (LeadershipLogStatusKind::Missed, LeadershipLogStatus::Missed)
+            | (LeadershipLogStatusKind::DryRun, LeadershipLogStatus::DryRun { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+/// filters of the leadership logs, on the scheduled time (`from` included,
+/// `to` excluded), on the status and on the leader, and the page of the
+/// matching logs to return: at most `limit` logs from the `offset`th
+#[derive(Deserialize)]
+pub struct LeadersLogsQuery {
+    from: Option<SystemTime>,
+    to: Option<SystemTime>,
+    status: Option<LeadershipLogStatusKind>,
+    leader_id: Option<EnclaveLeaderId>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+pub async fn get_leaders_logs(
+    context: Data<Context>,
+    query: Query<LeadersLogsQuery>,
+) -> Result<impl Responder, Error> {
+    let query = query.into_inner();
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(usize::MAX);
+    let full_context = context.try_full().await?;
+    let leadership_logs = &full_context.leadership_logs;
+    let filtered = query.from.is_some()
+        || query.to.is_some()
+        || query.status.is_some()
+        || query.leader_id.is_some();
+    if !filtered {
+        // only the logs of the page are cloned
+        return Ok(Json(leadership_logs.logs_page(offset, limit).await));
+    }
+
+    let range = (
+        query.from.map_or(Bound::Unbounded, Bound::Included),
+        query.to.map_or(Bound::Unbounded, Bound::Excluded),
+    );
+    let logs: Vec<_> = leadership_logs
+        .logs_in_range(range)
+        .await
+        .into_iter()
+        .filter(|log| {
+            query
+                .status
+                .as_ref()
+                .map_or(true, |status| status.matches(log.status()))
+                && query
+                    .leader_id
+                    .map_or(true, |leader_id| log.enclave_leader_id() == &leader_id)
+        })
+        .skip(offset)
+        .take(limit)
+        .collect();
+    Ok(Json(logs))
+}
+
+pub async fn get_leaders_schedule(
+    context: Data<Context>,
+    epoch: Path<u32>,
+) -> Result<impl Responder, Error> {
+    let context = context.try_full().await?;
+    let tip_ref = chain_tip_from_full(&context).await?;
+    let enclave = leadership::Enclave::new(context.enclave.clone());
+    leadership::preview_schedule(&enclave, tip_ref, epoch.into_inner())
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            leadership::ScheduleError::EpochNotAvailable { .. } => ErrorNotFound(e),
+            leadership::ScheduleError::Enclave(_) => ErrorInternalServerError(e),
+        })
 }
 
 pub async fn get_stake_pools(context: Data<Context>) -> Result<impl Responder, Error> {
@@ -453,6 +618,19 @@ pub async fn get_diagnostic(context: Data<Context>) -> Result<impl Responder, Er
     serde_json::to_string(&full_context.diagnostic).map_err(ErrorInternalServerError)
 }
 
+pub async fn get_diagnostic_storage(context: Data<Context>) -> Result<impl Responder, Error> {
+    let usage = context
+        .try_full()
+        .await?
+        .blockchain
+        .storage()
+        .usage()
+        .compat()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(Json(usage))
+}
+
 pub async fn get_network_p2p_quarantined(context: Data<Context>) -> Result<impl Responder, Error> {
     let ctx = context.try_full().await?;
     let list = ctx.p2p.list_quarantined::<Error>().compat().await?;
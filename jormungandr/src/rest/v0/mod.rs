@@ -2,7 +2,7 @@ mod handlers;
 
 use actix_web::{
     dev::HttpServiceFactory,
-    web::{delete, get, post, resource, scope},
+    web::{delete, get, post, put, resource, scope},
 };
 
 pub fn service(root_path: &str) -> impl HttpServiceFactory {
@@ -17,12 +17,21 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
             get().to(handlers::get_block_next_id),
         )
         .route("/fragment/logs", get().to(handlers::get_message_logs))
+        .route(
+            "/fragment/{fragment_id}/status",
+            get().to(handlers::get_fragment_status),
+        )
         .service(
             resource("/leaders")
                 .route(get().to(handlers::get_leaders))
-                .route(post().to(handlers::post_leaders)),
+                .route(post().to(handlers::post_leaders))
+                .route(put().to(handlers::post_leaders)),
         )
         .route("/leaders/logs", get().to(handlers::get_leaders_logs))
+        .route(
+            "/leaders/schedule/{epoch}",
+            get().to(handlers::get_leaders_schedule),
+        )
         .route(
             "/leaders/{leader_id}",
             delete().to(handlers::delete_leaders),
@@ -61,4 +70,8 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
             get().to(handlers::get_utxo),
         )
         .route("/diagnostic", get().to(handlers::get_diagnostic))
+        .route(
+            "/diagnostic/storage",
+            get().to(handlers::get_diagnostic_storage),
+        )
 }
@@ -6,11 +6,15 @@ use chain_impl_mockchain::leadership::{Leader, LeaderOutput, Leadership};
 use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
 use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio02::sync::RwLock;
+use tokio02::sync::{Mutex, RwLock};
 
 #[derive(Clone)]
 pub struct Enclave {
     leaders: Arc<RwLock<BTreeMap<LeaderId, Leader>>>,
+    // the id of the latest leader added, the ids of the removed leaders
+    // are not reused so the leadership logs of a leader stay attributed
+    // to it
+    last_id: Arc<Mutex<LeaderId>>,
 }
 
 pub struct LeaderEvent {
@@ -19,14 +23,11 @@ pub struct LeaderEvent {
     pub output: LeaderOutput,
 }
 
-fn get_maximum_id<A>(leaders: &BTreeMap<LeaderId, A>) -> LeaderId {
-    leaders.keys().last().copied().unwrap_or(LeaderId::new())
-}
-
 impl Enclave {
     pub fn new() -> Self {
         Enclave {
             leaders: Arc::new(RwLock::new(BTreeMap::new())),
+            last_id: Arc::new(Mutex::new(LeaderId::new())),
         }
     }
 
@@ -45,7 +46,9 @@ impl Enclave {
 
     pub async fn add_leader(&self, leader: Leader) -> LeaderId {
         let mut leaders = self.leaders.write().await;
-        let next_leader_id = get_maximum_id(&leaders).next();
+        let mut last_id = self.last_id.lock().await;
+        let next_leader_id = last_id.next();
+        *last_id = next_leader_id;
         // This panic case should never happens in practice, as this structure is
         // not supposed to be shared between thread.
         match leaders.insert(next_leader_id, leader) {
@@ -44,6 +44,29 @@ pub struct StartArguments {
     /// Start the explorer task and enable associated query endpoints.
     #[structopt(long = "enable-explorer")]
     pub explorer_enabled: bool,
+
+    /// Export the blocks of the storage, from the genesis block to the tip,
+    /// to the given archive file and exit. The archive can be used to seed
+    /// the storage of a new node.
+    #[structopt(long = "export-archive", parse(from_os_str))]
+    pub export_archive: Option<PathBuf>,
+
+    /// Import the blocks of an archive created with `--export-archive`
+    /// in the storage instead of bootstrapping from the network.
+    #[structopt(long = "bootstrap-from-archive", parse(from_os_str))]
+    pub bootstrap_from_archive: Option<PathBuf>,
+
+    /// Check the consistency of the blocks of the storage before starting,
+    /// the node does not start if a problem is found.
+    #[structopt(long = "storage-check")]
+    pub storage_check: bool,
+
+    /// Open the storage without writing to it, so the node can share the
+    /// storage of another running node. The node does not bootstrap from
+    /// the network, does not process the blocks it receives and does not
+    /// create blocks.
+    #[structopt(long = "storage-read-only")]
+    pub storage_read_only: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -7,7 +7,7 @@ use jormungandr_lib::{interfaces::Mempool, time::Duration};
 use poldercast;
 use serde::{de::Error as _, de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use slog::FilterLevel;
-use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf};
+use std::{collections::BTreeMap, fmt, net::SocketAddr, num::NonZeroUsize, path::PathBuf};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -15,6 +15,45 @@ pub struct Config {
     #[serde(default)]
     pub secret_files: Vec<PathBuf>,
     pub storage: Option<PathBuf>,
+    /// maximum size of the cache of the blocks read from the storage, in
+    /// bytes. No cache if not set.
+    #[serde(default)]
+    pub storage_cache_size: Option<usize>,
+    /// number of blocks read from the storage at once when sending a
+    /// branch to a peer, at least 1
+    #[serde(default)]
+    pub storage_send_batch_size: Option<NonZeroUsize>,
+    /// maximum number of read operations run concurrently on the storage,
+    /// the other read operations are queued. Not bounded if not set.
+    #[serde(default)]
+    pub storage_max_concurrent_reads: Option<NonZeroUsize>,
+    /// retry the read operations failing on a transient error of the
+    /// storage, see `RetryPolicy::default`
+    #[serde(default)]
+    pub storage_retry: bool,
+    /// index the fragments of the stored blocks so the status of confirmed
+    /// fragments can be queried
+    #[serde(default)]
+    pub storage_fragment_index: bool,
+    /// index the first block of every epoch
+    #[serde(default)]
+    pub storage_epoch_index: bool,
+    /// keep a checksum of the stored blocks, checked when the blocks are
+    /// read, to detect their corruption
+    #[serde(default)]
+    pub storage_checksum: bool,
+    /// journal the block writes to detect on start up a write interrupted
+    /// by a crash
+    #[serde(default)]
+    pub storage_journal: bool,
+    /// answer from a bloom filter whether a block is stored, when it is
+    /// not, instead of querying the storage. No filter if not set.
+    #[serde(default)]
+    pub storage_bloom_filter: Option<BloomFilter>,
+    /// keep the blocks received before their parent until the parent is
+    /// stored, instead of downloading them again. No pool if not set.
+    #[serde(default)]
+    pub storage_orphan_pool: Option<OrphanPool>,
     pub log: Option<ConfigLogSettings>,
 
     /// setting of the mempool, fragment logs and related data
@@ -36,6 +75,24 @@ pub struct Config {
     pub no_blockchain_updates_warning_interval: Option<Duration>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BloomFilter {
+    /// number of blocks the filter is sized for
+    pub capacity: usize,
+    /// rate of the blocks not stored the filter cannot rule out, in `]0, 1[`
+    pub false_positive_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrphanPool {
+    /// maximum number of blocks in the pool
+    pub capacity: NonZeroUsize,
+    /// how long a block is kept in the pool
+    pub ttl: Duration,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigLogSettingsEntry {
@@ -56,6 +113,9 @@ pub struct Rest {
     pub tls: Option<Tls>,
     /// Enables CORS if provided
     pub cors: Option<Cors>,
+    /// Enables the admin endpoints for the clients presenting this token
+    /// as a bearer token if provided
+    pub admin_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -173,6 +233,31 @@ pub struct Leadership {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check logs
     pub garbage_collection_interval: Duration,
+    /// save the logs in the storage directory so they survive a restart
+    /// of the node, ignored if the blockchain is stored in memory
+    #[serde(default)]
+    pub persist_logs: bool,
+    /// keep the logs of the last `keep_epochs` epochs even once their
+    /// `log_ttl` has expired
+    #[serde(default)]
+    pub keep_epochs: u32,
+    /// POST the leadership logs reaching their final status to this URL,
+    /// only `http` URLs are supported
+    #[serde(default, with = "uri_opt_serde")]
+    pub webhook_url: Option<http::Uri>,
+    /// compute the schedules and sign the blocks but never submit them
+    #[serde(default)]
+    pub dry_run: bool,
+    /// NTP servers (`host:port`) to check the system clock against
+    #[serde(default)]
+    pub ntp_servers: Vec<String>,
+    /// warn when the system clock drifts from the NTP time by more
+    #[serde(default = "default_clock_drift_tolerance")]
+    pub clock_drift_tolerance: Duration,
+}
+
+fn default_clock_drift_tolerance() -> Duration {
+    Duration::new(1, 0)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -231,6 +316,12 @@ impl Default for Leadership {
         Leadership {
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            persist_logs: false,
+            keep_epochs: 0,
+            webhook_url: None,
+            dry_run: false,
+            ntp_servers: Vec::new(),
+            clock_drift_tolerance: default_clock_drift_tolerance(),
         }
     }
 }
@@ -396,6 +487,27 @@ impl<'de> Deserialize<'de> for InterestLevel {
     }
 }
 
+mod uri_opt_serde {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<http::Uri>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|uri| uri.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<http::Uri>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        data.as_ref()
+            .map(|uri| uri.to_string())
+            .serialize(serializer)
+    }
+}
+
 mod filter_level_opt_serde {
     use super::*;
 
@@ -4,12 +4,13 @@ pub mod network;
 use self::config::{Config, Leadership};
 pub use self::config::{Cors, Rest, Tls};
 use self::network::Protocol;
+use crate::blockchain::{BloomConfig, OrphanConfig};
 use crate::rest::Error as RestError;
 use crate::settings::logging::{LogFormat, LogOutput, LogSettings, LogSettingsEntry};
 use crate::settings::{command_arguments::*, Block0Info};
 use jormungandr_lib::interfaces::Mempool;
 use slog::{FilterLevel, Logger};
-use std::{fs::File, path::PathBuf};
+use std::{fs::File, num::NonZeroUsize, path::PathBuf};
 use thiserror::Error;
 
 const DEFAULT_FILTER_LEVEL: FilterLevel = FilterLevel::Info;
@@ -31,12 +32,28 @@ pub enum Error {
     TooMuchBlock0Info,
     #[error("In the node configuration file, the `p2p.listen_address` value is not a valid address. Use format `/ip4/x.x.x.x/tcp/4920")]
     ListenAddressNotValid,
+    #[error("In the node configuration file, the `storage_bloom_filter` needs a capacity of at least 1 and a false positive rate between 0 and 1")]
+    StorageBloomFilterNotValid,
 }
 
 /// Overall Settings for node
 pub struct Settings {
     pub network: network::Configuration,
     pub storage: Option<PathBuf>,
+    pub storage_cache_size: Option<usize>,
+    pub storage_send_batch_size: Option<NonZeroUsize>,
+    pub storage_max_concurrent_reads: Option<NonZeroUsize>,
+    pub storage_retry: bool,
+    pub storage_fragment_index: bool,
+    pub storage_epoch_index: bool,
+    pub storage_checksum: bool,
+    pub storage_journal: bool,
+    pub storage_bloom_filter: Option<BloomConfig>,
+    pub storage_orphan_pool: Option<OrphanConfig>,
+    pub export_archive: Option<PathBuf>,
+    pub bootstrap_from_archive: Option<PathBuf>,
+    pub storage_check: bool,
+    pub storage_read_only: bool,
     pub block_0: Block0Info,
     pub secrets: Vec<PathBuf>,
     pub rest: Option<Rest>,
@@ -113,6 +130,7 @@ impl RawSettings {
                 listen: cmd_listen,
                 tls: None,
                 cors: None,
+                admin_token: None,
             }),
             (None, None) => None,
         }
@@ -163,6 +181,23 @@ impl RawSettings {
             (None, Some(hash)) => Block0Info::Hash(hash.clone()),
         };
 
+        let storage_bloom_filter = match config
+            .as_ref()
+            .and_then(|cfg| cfg.storage_bloom_filter.as_ref())
+        {
+            None => None,
+            Some(bloom_filter) => {
+                let bloom_config = BloomConfig {
+                    capacity: bloom_filter.capacity,
+                    false_positive_rate: bloom_filter.false_positive_rate,
+                };
+                if !bloom_config.is_valid() {
+                    return Err(Error::StorageBloomFilterNotValid);
+                }
+                Some(bloom_config)
+            }
+        };
+
         let explorer = command_arguments.explorer_enabled
             || config.as_ref().map_or(false, |cfg| {
                 cfg.explorer
@@ -172,6 +207,30 @@ impl RawSettings {
 
         Ok(Settings {
             storage,
+            storage_cache_size: config.as_ref().and_then(|cfg| cfg.storage_cache_size),
+            storage_send_batch_size: config.as_ref().and_then(|cfg| cfg.storage_send_batch_size),
+            storage_max_concurrent_reads: config
+                .as_ref()
+                .and_then(|cfg| cfg.storage_max_concurrent_reads),
+            storage_retry: config.as_ref().map_or(false, |cfg| cfg.storage_retry),
+            storage_fragment_index: config
+                .as_ref()
+                .map_or(false, |cfg| cfg.storage_fragment_index),
+            storage_epoch_index: config.as_ref().map_or(false, |cfg| cfg.storage_epoch_index),
+            storage_checksum: config.as_ref().map_or(false, |cfg| cfg.storage_checksum),
+            storage_journal: config.as_ref().map_or(false, |cfg| cfg.storage_journal),
+            storage_bloom_filter,
+            storage_orphan_pool: config
+                .as_ref()
+                .and_then(|cfg| cfg.storage_orphan_pool.as_ref())
+                .map(|orphan_pool| OrphanConfig {
+                    capacity: orphan_pool.capacity.get(),
+                    ttl: orphan_pool.ttl.into(),
+                }),
+            export_archive: command_arguments.export_archive.clone(),
+            bootstrap_from_archive: command_arguments.bootstrap_from_archive.clone(),
+            storage_check: command_arguments.storage_check,
+            storage_read_only: command_arguments.storage_read_only,
             block_0,
             network,
             secrets,
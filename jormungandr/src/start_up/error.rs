@@ -54,6 +54,8 @@ pub enum Error {
     ServiceTerminatedWithError,
     #[error("Unable to get system limits: {0}")]
     DiagnosticError(#[from] DiagnosticError),
+    #[error("The storage is corrupted: {0:?}")]
+    StorageCorrupted(blockchain::VerifyReport),
 }
 
 impl Error {
@@ -74,6 +76,7 @@ impl Error {
             Error::ExplorerBootstrapError { .. } => 11,
             Error::ServiceTerminatedWithError => 12,
             Error::DiagnosticError { .. } => 13,
+            Error::StorageCorrupted { .. } => 14,
         }
     }
 }
@@ -3,13 +3,16 @@ mod error;
 pub use self::error::{Error, ErrorKind};
 use crate::{
     blockcfg::Block,
-    blockchain::{Blockchain, ErrorKind as BlockchainError, Storage, Tip},
+    blockchain::{
+        Blockchain, CacheConfig, ErrorKind as BlockchainError, Recovery, RetryPolicy, Storage, Tip,
+        MAIN_BRANCH_TAG, STORAGE_VERSION,
+    },
     network,
     settings::start::Settings,
 };
-use chain_storage_sqlite_old::{BlockStore, BlockStoreConnection};
+use chain_storage_sqlite_old::{BlockStore, BlockStoreConnection, Error as StorageError};
 use slog::Logger;
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 use tokio_compat::runtime;
 
 pub type NodeStorage = BlockStore;
@@ -18,10 +21,10 @@ pub type NodeStorageConnection = BlockStoreConnection<Block>;
 /// prepare the block storage from the given settings
 ///
 pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<Storage, Error> {
-    let raw_block_store = match &setting.storage {
+    let storage = match &setting.storage {
         None => {
             info!(logger, "storing blockchain in memory");
-            BlockStore::memory()
+            Storage::new(BlockStore::memory()).with_logger(logger.clone())
         }
         Some(dir) => {
             std::fs::create_dir_all(dir).map_err(|err| Error::IO {
@@ -30,12 +33,177 @@ pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<Storage, E
             })?;
             let mut sqlite = dir.clone();
             sqlite.push("blocks.sqlite");
-            info!(logger, "storing blockchain in '{:?}'", sqlite);
-            BlockStore::file(sqlite)
+            if setting.storage_read_only {
+                info!(logger, "reading blockchain from '{:?}'", sqlite);
+                Storage::open_read_only(sqlite).with_logger(logger.clone())
+            } else {
+                info!(logger, "storing blockchain in '{:?}'", sqlite);
+                Storage::new(BlockStore::file(sqlite.clone()))
+                    .with_file_path(sqlite)
+                    .with_logger(logger.clone())
+            }
+        }
+    };
+
+    let storage = match setting.storage_send_batch_size {
+        None => storage,
+        Some(send_batch_size) => storage.with_send_batch_size(send_batch_size),
+    };
+
+    let storage = match setting.storage_max_concurrent_reads {
+        None => storage,
+        Some(max_concurrent_reads) => storage.with_max_concurrent_reads(max_concurrent_reads.get()),
+    };
+
+    let storage = if setting.storage_retry {
+        storage.with_retry(RetryPolicy::default())
+    } else {
+        storage
+    };
+
+    let storage = if setting.storage_fragment_index {
+        storage.with_fragment_index()
+    } else {
+        storage
+    };
+
+    let storage = if setting.storage_epoch_index {
+        storage.with_epoch_index()
+    } else {
+        storage
+    };
+
+    let storage = if setting.storage_checksum {
+        storage.with_checksum()
+    } else {
+        storage
+    };
+
+    let storage = if setting.storage_journal {
+        storage.with_journal()
+    } else {
+        storage
+    };
+
+    let storage = match setting.storage_cache_size {
+        None => storage,
+        Some(size) => {
+            info!(
+                logger,
+                "caching up to {} bytes of blocks read from the storage", size
+            );
+            storage.with_cache(CacheConfig::with_max_bytes(size))
         }
     };
 
-    Ok(Storage::new(raw_block_store))
+    let storage = match setting.storage_bloom_filter {
+        None => storage,
+        Some(config) => storage.with_bloom_filter(config),
+    };
+
+    let storage = match setting.storage_orphan_pool.clone() {
+        None => storage,
+        Some(config) => storage.with_orphan_pool(config),
+    };
+
+    let mut rt = runtime::Builder::new()
+        .name_prefix("prepare-storage-worker-")
+        .core_threads(1)
+        .build()
+        .unwrap();
+    rt.block_on(storage.check_version(STORAGE_VERSION))?;
+
+    let recovery = if setting.storage_read_only {
+        Recovery::Clean
+    } else {
+        rt.block_on(storage.recover())?
+    };
+    let mut storage_check = setting.storage_check;
+    match recovery {
+        Recovery::Clean => {}
+        Recovery::Completed(hash) => {
+            info!(
+                logger,
+                "the write of block {} was interrupted but completed", hash
+            )
+        }
+        Recovery::NotWritten(hash) => {
+            info!(
+                logger,
+                "the write of block {} was interrupted, nothing was written", hash
+            )
+        }
+        Recovery::Partial(hash) => {
+            warn!(
+                logger,
+                "block {} was partially written, checking the storage", hash
+            );
+            storage_check = true;
+        }
+    }
+
+    if storage_check {
+        info!(logger, "checking the consistency of the storage");
+        let report = rt.block_on(storage.verify_integrity())?;
+        if !report.is_consistent() {
+            return Err(Error::StorageCorrupted(report));
+        }
+        info!(
+            logger,
+            "checked {} blocks of the storage", report.blocks_checked
+        );
+    }
+
+    if setting.storage_bloom_filter.is_some() {
+        // the storage works the same without the filter, only slower
+        match rt.block_on(storage.enable_bloom_filter()) {
+            Ok(()) => info!(logger, "bloom filter of the stored blocks enabled"),
+            Err(e) => warn!(
+                logger,
+                "cannot enable the bloom filter of the stored blocks";
+                "reason" => %e
+            ),
+        }
+    }
+
+    Ok(storage)
+}
+
+/// export the blocks of the storage, from the block0 to the tip, to the
+/// archive file at `path`
+pub fn export_archive(storage: &Storage, path: PathBuf, logger: &Logger) -> Result<(), Error> {
+    let mut rt = runtime::Builder::new()
+        .name_prefix("export-archive-worker-")
+        .core_threads(1)
+        .build()
+        .unwrap();
+
+    let tip = rt
+        .block_on(storage.get_tag(MAIN_BRANCH_TAG.to_owned()))?
+        .ok_or(StorageError::BlockNotFound)?;
+    info!(logger, "exporting the blocks up to {} to '{:?}'", tip, path);
+    rt.block_on(storage.export_archive(path, tip))?;
+    Ok(())
+}
+
+/// import the blocks of the archive file at `path` in the storage, the
+/// blocks are validated when the blockchain is loaded from the storage
+pub fn import_archive(
+    storage: &Storage,
+    path: PathBuf,
+    block0: &Block,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let mut rt = runtime::Builder::new()
+        .name_prefix("import-archive-worker-")
+        .core_threads(1)
+        .build()
+        .unwrap();
+
+    info!(logger, "importing the blocks of '{:?}'", path);
+    let last = rt.block_on(storage.import_archive(path, block0.header.hash()))?;
+    info!(logger, "imported the blocks up to {}", last);
+    Ok(())
 }
 
 /// loading the block 0 is not as trivial as it seems,
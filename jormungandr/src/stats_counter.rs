@@ -14,6 +14,7 @@ pub struct StatsCounter {
 struct StatsCounterImpl {
     tx_recv_cnt: AtomicUsize,
     block_recv_cnt: AtomicUsize,
+    slots_missed_cnt: AtomicUsize,
     start_time: Instant,
     slot_start_time: AtomicU64,
 }
@@ -23,6 +24,7 @@ impl Default for StatsCounterImpl {
         Self {
             tx_recv_cnt: AtomicUsize::default(),
             block_recv_cnt: AtomicUsize::default(),
+            slots_missed_cnt: AtomicUsize::default(),
             start_time: Instant::now(),
             slot_start_time: AtomicU64::new(SLOT_START_TIME_UNDEFINED),
         }
@@ -49,6 +51,17 @@ impl StatsCounter {
         self.stats.block_recv_cnt.load(Ordering::Relaxed) as u64
     }
 
+    /// count the leadership slots passed without being processed
+    pub fn add_slots_missed_cnt(&self, count: usize) {
+        self.stats
+            .slots_missed_cnt
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn slots_missed_cnt(&self) -> u64 {
+        self.stats.slots_missed_cnt.load(Ordering::Relaxed) as u64
+    }
+
     pub fn uptime_sec(&self) -> u64 {
         self.stats.start_time.elapsed().as_secs()
     }